@@ -1,12 +1,14 @@
+#![allow(clippy::needless_return)]
+
 // Use the shared utility for recording failed transactions
 use utils::record_failed_transaction;
+use base64::{engine::general_purpose, Engine as _};
 use chrono::{Local, NaiveDateTime};
 use crypto_hash::{hex_digest, Algorithm};
 use hex::FromHex;
-use magic_crypt::{new_magic_crypt, MagicCryptTrait};
 use reqwest::{header, Client};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
 use std::path::Path;
 use tokio::time::{Duration, Instant};
@@ -21,22 +23,24 @@ mod scl01 {
 use crate::scl01::scl01_utils::{
     self, convert_old_contracts, handle_payload_extra_trade_info, read_contract, save_contract,
 };
-use scl01::scl01_contract::{self};
+use scl01::scl01_contract::{self, BPS_SCALE};
 
 mod utils;
 use utils::{
-    check_txid_confirmed, check_utxo_spent, dequeue_item, enqueue_item, extract_commands,
-    extract_contract_id, get_contract_header, get_current_block_height,
-    get_current_block_height_from_esplora, get_transaction, get_txid_from_hash, handle_get_request,
+    check_txid_confirmed, check_utxo_spent, dequeue_item, enqueue_item, extract_command_keyword,
+    extract_commands, extract_contract_id, get_contract_header, get_current_block_height,
+    get_current_block_height_from_esplora, get_op_return_payload, get_transaction,
+    get_tx_block_info, get_txid_from_hash, handle_get_request, parse_fixed_point,
     read_contract_interactions, read_from_file, read_queue, read_server_config, read_server_lookup,
     remove_transaction, save_command_backup, save_contract_interactions, save_server_config,
     trim_chars, write_to_file, LiquidityPoolString,
 };
 use utils::{
-    BidData, BidPayload, CheckBalancesResult, CommandStruct, Config, ContractHistoryEntry,
+    BidData, BidPayload, Candle, CheckBalancesResult, CommandStruct, Config, ContractHistoryEntry,
     ContractListingResponse, ContractSummary, ContractTradeResponse, CustomError, ListingSummary,
-    PagingMetaData, PendingCommandStruct, RelayedCommandStruct, ResultStruct, TradeUtxoRequest,
-    TxInfo, TxidCheck, TxidCheckResponse, UtxoBalanceResult, UtxoBalances,
+    OrderBook, OrderBookLevel, PagingMetaData, PendingCommandStruct, PoolAnalytics,
+    PoolWindowStats, RelayedCommandStruct, ResultStruct, TradeUtxoRequest, TxInfo, TxidCheck,
+    TxidCheckResponse, UtxoBalanceResult, UtxoBalances,
 };
 
 
@@ -159,7 +163,7 @@ async fn handle_utxo_balances(req: UtxoBalancesRequest) -> Result<impl Reply, Re
 
                 let mut drip_amt = 0;
                 for drip in drips {
-                    drip_amt += (drip.block_end - current_block) * drip.drip_amount
+                    drip_amt += drip.remaining(current_block);
                 }
                 drip_amount = Some(drip_amt);
             }
@@ -195,6 +199,8 @@ async fn main() {
             convert_old_contracts();
         } else if user_input == "check_spent" {
             remove_spent_utxos().await;
+        } else if user_input == "backfill_trades" {
+            backfill_trade_history().await;
         }
     }
 
@@ -254,8 +260,14 @@ async fn main() {
         .and(warp::path!("transfer_details" / String))
         .and_then(handle_check_transfer_details_request);
 
+    let get_contract_content = warp::get()
+        .and(warp::path!(String / "content"))
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and_then(handle_get_contract_content);
+
     let get_contract_field = warp::get()
         .and(warp::path!(String / String))
+        .and(warp::query::<HashMap<String, String>>())
         .and_then(handle_get_contract_field);
 
     let get_contract_field_paged = warp::get()
@@ -266,6 +278,10 @@ async fn main() {
         .and(warp::path!(String / String / String))
         .and_then(handle_get_utxo_data);
 
+    let get_dge_refunds = warp::get()
+        .and(warp::path!(String / "dge" / String / "refunds"))
+        .and_then(handle_get_dge_refunds);
+
     let get_contract_history = warp::get()
         .and(warp::path!(String / "history"))
         .and_then(handle_get_tx_history);
@@ -326,6 +342,7 @@ async fn main() {
             key: None,
             esplora: Some("https://btc.darkfusion.tech/".to_owned()),
             url: Some("https://scl.darkfusion.tech/".to_string()),
+            legacy_lp_cutoff: None,
         };
         let _ = save_server_config(c);
     }
@@ -338,12 +355,14 @@ async fn main() {
         .or(listings_for_bids)
         .or(txid_history)
         .or(listing_summaries)
+        .or(get_contract_content)
         .or(get_contract_field)
         .or(get_contract_field_paged)
         .or(get_health)
         .or(get_contracts_route)
         .or(get_coin_drops)
         .or(get_utxo_data)
+        .or(get_dge_refunds)
         .or(get_contract_history)
         .or(get_liquidity_contracts)
         .or(check_all_summaries)
@@ -795,7 +814,7 @@ async fn handle_check_utxo_files(data: UtxoBalances) -> Result<impl Reply, Rejec
 
                 let mut drip_amount = 0;
                 for drip in drips {
-                    drip_amount += (drip.block_end - current_block) * drip.drip_amount
+                    drip_amount += drip.remaining(current_block);
                 }
 
                 results.push(UtxoBalanceResult {
@@ -1202,6 +1221,13 @@ async fn handle_liquidity_pool_request() -> Result<impl Reply, Rejection> {
                     Err(_) => continue,
                 }
 
+                if let Ok(analytics) = get_pool_analytics(&contract) {
+                    if let Ok(analytics) = serde_json::to_string(&analytics) {
+                        results.push_str(",\"analytics\":");
+                        results.push_str(&analytics);
+                    }
+                }
+
                 results.push_str("}");
             }
             Err(_) => continue,
@@ -1359,7 +1385,23 @@ async fn handle_check_transfer_details_request(
                 let mut all_recipients: Vec<(String, u64)> = Vec::new();
                 let mut found_transfer = false;
                 for cmd in &commands {
-                    if cmd.contains("TRANSFER") {
+                    if cmd.contains("TRANSFER_TOKENS") {
+                        found_transfer = true;
+                        let (senders, recipients) =
+                            match scl01_utils::handle_transfer_tokens_payload(
+                                &txid,
+                                &format!("{{{}}}", cmd),
+                            ) {
+                                Ok(res) => res,
+                                Err(_) => continue,
+                            };
+                        all_senders.extend(senders);
+                        all_recipients.extend(
+                            recipients
+                                .into_iter()
+                                .map(|(recipient, token_ids)| (recipient, token_ids.len() as u64)),
+                        );
+                    } else if cmd.contains("TRANSFER") {
                         found_transfer = true;
                         let (senders, recipients, _extra): (Vec<String>, Vec<(String, u64)>, String) =
                             match scl01_utils::handle_transfer_payload(&txid, &format!("{{{}}}", cmd)) {
@@ -1461,6 +1503,7 @@ async fn handle_check_transfer_details_request(
 async fn handle_get_contract_field(
     contract_id: String,
     field: String,
+    query: HashMap<String, String>,
 ) -> Result<impl Reply, Rejection> {
     let pending: bool;
     let command: String;
@@ -1472,6 +1515,192 @@ async fn handle_get_contract_field(
         command = field;
     }
 
+    if command == "orderbook" {
+        let limit = match query.get("limit") {
+            Some(limit) => match limit.parse::<usize>() {
+                Ok(limit) => Some(limit),
+                Err(_) => {
+                    let error = CustomError {
+                        message: "Invalid limit".to_string(),
+                    };
+                    return Err(warp::reject::custom(error));
+                }
+            },
+            None => None,
+        };
+
+        match get_orderbook(&contract_id, pending, limit) {
+            Ok(result) => return Ok(warp::reply::html(result)),
+            Err(error) => {
+                let error = CustomError { message: error };
+                return Err(warp::reject::custom(error));
+            }
+        };
+    }
+
+    if command == "candles" {
+        let interval: i64 = match query.get("interval").map(|interval| interval.as_str()) {
+            Some("1h") | None => 3600,
+            Some("1d") => 86400,
+            Some(_) => {
+                let error = CustomError {
+                    message: "Invalid interval, expected 1h or 1d".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        let mut range: (Option<i64>, Option<i64>) = (None, None);
+        for (index, key) in ["from", "to"].iter().enumerate() {
+            if let Some(value) = query.get(*key) {
+                let time = match value.parse::<i64>() {
+                    Ok(time) => time,
+                    Err(_) => {
+                        let error = CustomError {
+                            message: format!("Invalid {} time", key),
+                        };
+                        return Err(warp::reject::custom(error));
+                    }
+                };
+
+                if index == 0 {
+                    range.0 = Some(time);
+                } else {
+                    range.1 = Some(time);
+                }
+            }
+        }
+
+        match get_candles(&contract_id, interval, range.0, range.1) {
+            Ok(result) => return Ok(warp::reply::html(result)),
+            Err(error) => {
+                let error = CustomError { message: error };
+                return Err(warp::reject::custom(error));
+            }
+        };
+    }
+
+    if command == "quote" {
+        let from = match query.get("from") {
+            Some(from) => from.to_string(),
+            None => {
+                let error = CustomError {
+                    message: "Missing from contract id".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        let amount = match query.get("amount").map(|amount| amount.parse::<u64>()) {
+            Some(Ok(amount)) => amount,
+            _ => {
+                let error = CustomError {
+                    message: "Invalid amount".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        let tolerance = match query.get("tolerance") {
+            Some(tolerance) => tolerance.to_string(),
+            None => "0.005".to_string(),
+        };
+
+        let tolerance_bps = match parse_fixed_point(&tolerance, 4) {
+            Ok(tolerance_bps) if tolerance_bps <= 10_000 => tolerance_bps as u64,
+            _ => {
+                let error = CustomError {
+                    message: "Invalid tolerance".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        match get_swap_quote(&contract_id, pending, &from, amount, tolerance_bps) {
+            Ok(result) => return Ok(warp::reply::html(result)),
+            Err(error) => {
+                let error = CustomError { message: error };
+                return Err(warp::reject::custom(error));
+            }
+        };
+    }
+
+    if command == "route" {
+        let to = match query.get("to") {
+            Some(to) => to.to_string(),
+            None => {
+                let error = CustomError {
+                    message: "Missing to contract id".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        let amount = match query.get("amount").map(|amount| amount.parse::<u64>()) {
+            Some(Ok(amount)) => amount,
+            _ => {
+                let error = CustomError {
+                    message: "Invalid amount".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        let tolerance = match query.get("tolerance") {
+            Some(tolerance) => tolerance.to_string(),
+            None => "0.005".to_string(),
+        };
+
+        let tolerance_bps = match parse_fixed_point(&tolerance, 4) {
+            Ok(tolerance_bps) if tolerance_bps <= 10_000 => tolerance_bps as u64,
+            _ => {
+                let error = CustomError {
+                    message: "Invalid tolerance".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        let route =
+            match scl01_utils::find_swap_route(&contract_id, &to, amount, tolerance_bps, pending) {
+                Ok(route) => route,
+                Err(error) => {
+                    let error = CustomError { message: error };
+                    return Err(warp::reject::custom(error));
+                }
+            };
+
+        match serde_json::to_string(&route) {
+            Ok(result) => return Ok(warp::reply::html(result)),
+            Err(_) => {
+                let error = CustomError {
+                    message: "Unable to get swap route".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+    }
+
+    if command == "envelope" {
+        let payload = match query.get("payload") {
+            Some(payload) => payload.to_string(),
+            None => {
+                let error = CustomError {
+                    message: "Missing payload".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        };
+
+        match scl01_utils::encode_lp_envelope(&contract_id, &payload) {
+            Ok(envelope) => return Ok(warp::reply::html(hex::encode(envelope))),
+            Err(error) => {
+                let error = CustomError { message: error };
+                return Err(warp::reject::custom(error));
+            }
+        };
+    }
+
     match get_contract_field(&contract_id, &command, pending, 1) {
         Ok(result) => return Ok(warp::reply::html(format!("{}", result))),
         Err(error) => {
@@ -1519,6 +1748,92 @@ async fn handle_get_contract_field_paged(
     };
 }
 
+async fn handle_get_contract_content(
+    contract_id: String,
+    if_none_match: Option<String>,
+) -> Result<impl Reply, Rejection> {
+    let contract = match read_contract(&contract_id, false) {
+        Ok(contract) => contract,
+        Err(_) => {
+            let error = CustomError {
+                message: "Unable to get contract".to_string(),
+            };
+            return Err(warp::reject::custom(error));
+        }
+    };
+
+    let token_data = match contract.token_data {
+        Some(token_data) => token_data,
+        None => {
+            let error = CustomError {
+                message: "Contract has no content".to_string(),
+            };
+            return Err(warp::reject::custom(error));
+        }
+    };
+
+    let mut content = match general_purpose::STANDARD.decode(token_data.trim()) {
+        Ok(content) => content,
+        Err(_) => match general_purpose::STANDARD_NO_PAD.decode(token_data.trim()) {
+            Ok(content) => content,
+            Err(_) => {
+                let error = CustomError {
+                    message: "Contract content is not valid base64".to_string(),
+                };
+                return Err(warp::reject::custom(error));
+            }
+        },
+    };
+
+    let content_type = utils::sniff_content_type(&content);
+    if content_type == "image/svg+xml" {
+        content = match utils::sanitize_svg(&String::from_utf8_lossy(&content)) {
+            Ok(svg) => svg.into_bytes(),
+            Err(error) => {
+                let error = CustomError { message: error };
+                return Err(warp::reject::custom(error));
+            }
+        };
+    }
+
+    let etag = format!("\"{}\"", hex_digest(Algorithm::SHA256, &content));
+    let not_modified = match if_none_match {
+        Some(if_none_match) => if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag || tag.trim() == "*"),
+        None => false,
+    };
+
+    let mut response = warp::http::Response::builder()
+        .header("ETag", etag.as_str())
+        .header("Cache-Control", "public, max-age=31536000, immutable")
+        .header("X-Content-Type-Options", "nosniff");
+    if content_type == "image/svg+xml" {
+        response = response.header(
+            "Content-Security-Policy",
+            "default-src 'none'; style-src 'unsafe-inline'; img-src data:",
+        );
+    }
+
+    let response = if not_modified {
+        response
+            .status(warp::http::StatusCode::NOT_MODIFIED)
+            .body(Vec::new())
+    } else {
+        response.header("Content-Type", content_type).body(content)
+    };
+
+    match response {
+        Ok(response) => return Ok(response),
+        Err(_) => {
+            let error = CustomError {
+                message: "Unable to build content response".to_string(),
+            };
+            return Err(warp::reject::custom(error));
+        }
+    };
+}
+
 async fn handle_get_utxo_data(
     contract_id: String,
     field: String,
@@ -1543,6 +1858,47 @@ async fn handle_get_utxo_data(
     };
 }
 
+async fn handle_get_dge_refunds(
+    contract_id: String,
+    dge_id: String,
+) -> Result<impl Reply, Rejection> {
+    let contract = match read_contract(&contract_id, false) {
+        Ok(contract) => contract,
+        Err(_) => {
+            return Err(reject::custom(CustomError {
+                message: "Unable to read contract".to_string(),
+            }))
+        }
+    };
+
+    let dge = match contract.dges.as_ref().and_then(|dges| dges.get(&dge_id)) {
+        Some(dge) => dge.clone(),
+        None => {
+            return Err(reject::custom(CustomError {
+                message: "DGE not found".to_string(),
+            }))
+        }
+    };
+
+    let refunds = dge.refunds.unwrap_or_default();
+
+    let total_refundable: u64 = refunds.iter().map(|refund| refund.amount).sum();
+    let report = serde_json::json!({
+        "dge_id": dge_id,
+        "donations_address": dge.donations_address,
+        "start_block": dge.start_block,
+        "end_block": dge.end_block,
+        "soft_cap": dge.soft_cap,
+        "soft_cap_met": dge.soft_cap_met,
+        "hard_cap": dge.hard_cap,
+        "sats_raised": dge.sats_raised.unwrap_or(0),
+        "total_refundable": total_refundable,
+        "refunds": refunds,
+    });
+
+    return Ok(warp::reply::json(&report));
+}
+
 async fn handle_get_tx_history(contract_id: String) -> Result<impl Reply, Rejection> {
     let mut entries: Vec<ContractHistoryEntry> = Vec::new();
     let payloads: HashMap<String, String>;
@@ -1597,7 +1953,7 @@ async fn perform_commands(
         Err(_) => Vec::new(),
     };
 
-    if payload.contains("SLP[") || payload.contains("PLP[") || payload.contains("LLP[") {
+    if scl01_utils::is_liquidity_pool_payload(payload) {
         let contract_id = match lp_contract_id {
             Some(contract_id) => contract_id,
             None => return,
@@ -1608,10 +1964,21 @@ async fn perform_commands(
             Err(_) => return,
         };
 
-        if payload.contains("PLP") {
+        if payload.contains("ZLP[") {
+            scl01_utils::perform_zap_liquidity(txid, payload, pending, contract_id, block_height)
+                .await;
+            return;
+        } else if payload.contains("PFC[") {
+            scl01_utils::perform_claim_protocol_fees(txid, payload, pending, contract_id).await;
+            return;
+        } else if payload.contains("MSLP[") {
+            scl01_utils::perform_multi_hop_swap(txid, payload, pending, contract_id, block_height)
+                .await;
+            return;
+        } else if payload.contains("PLP") {
             scl01_utils::perform_provide_liquidity(
                 txid,
-                &payload,
+                payload,
                 pending,
                 contract_id,
                 block_height,
@@ -1652,27 +2019,61 @@ async fn perform_commands(
     }
 
     for command in commands {
+        // Newer commands dispatch on their exact keyword so free text in their arguments can't
+        // be mistaken for another command
+        match extract_command_keyword(&command).as_str() {
+            "REVOKE_DRIP" => {
+                scl01_utils::perform_revoke_drip(txid, &command, payload, pending).await;
+                continue;
+            }
+            "MERKLEAIRDROP" => {
+                scl01_utils::perform_create_merkle_airdrop(txid, &command, payload, pending).await;
+                continue;
+            }
+            "CLAIM_MERKLEAIRDROP" => {
+                scl01_utils::perform_claim_merkle_airdrop(txid, &command, payload, pending).await;
+                continue;
+            }
+            "MINT_SUPPLY" => {
+                scl01_utils::perform_mint_supply(txid, &command, payload, pending).await;
+                continue;
+            }
+            "RENOUNCE_MINT" => {
+                scl01_utils::perform_renounce_mint(txid, &command, payload, pending).await;
+                continue;
+            }
+            "METADATA" => {
+                scl01_utils::perform_set_metadata(txid, &command, payload, pending).await;
+                continue;
+            }
+            "FREEZE" | "UNFREEZE" => {
+                scl01_utils::perform_freeze(txid, &command, payload, pending).await;
+                continue;
+            }
+            _ => {}
+        }
+
         if command.contains("SCL01") {
-            scl01_utils::perform_minting_scl01(txid, &payload);
+            scl01_utils::perform_minting_scl01(txid, payload);
             return;
         } else if command.contains("SCL02") {
-            scl01_utils::perform_minting_scl02(txid, &payload);
+            scl01_utils::perform_minting_scl02(txid, payload);
             return;
         } else if command.contains("SCL03") {
-            scl01_utils::perform_minting_scl03(txid, &payload);
+            scl01_utils::perform_minting_scl03(txid, payload);
             return;
         } else if command.contains("SCL04") {
-            scl01_utils::perform_minting_scl04(txid, &payload);
+            scl01_utils::perform_minting_scl04(txid, payload);
             return;
         } else if command.contains("SCL05") {
-            scl01_utils::perform_minting_scl05(txid, &payload);
+            scl01_utils::perform_minting_scl05(txid, payload);
             return;
         } else if command.contains("TRANSFER") {
-            scl01_utils::perform_transfer(txid, &command, &payload, pending).await;
+            scl01_utils::perform_transfer(txid, &command, payload, pending).await;
         } else if command.contains("BURN") {
-            scl01_utils::perform_burn(&txid, &command, &payload, pending).await;
+            scl01_utils::perform_burn(txid, &command, payload, pending).await;
         } else if command.contains(":LIST") {
-            scl01_utils::perform_list(&txid, &command, &payload, pending).await;
+            scl01_utils::perform_list(txid, &command, payload, pending).await;
         } else if command.contains(":BID") {
             let contract_id = match extract_contract_id(&command) {
                 Ok(contract_id) => contract_id,
@@ -1913,23 +2314,32 @@ async fn payload_validation_and_confirmation_lp(
         Err(_) => return (false, false, 0, Vec::new(), Vec::new()),
     };
 
-    let mc = new_magic_crypt!(contract_id.to_string(), 64);
     let bytes = match Vec::from_hex(payload.clone()) {
         Ok(vec) => vec,
         Err(_) => Vec::new(),
     };
 
-    let payload_bytes = match mc.decrypt_bytes_to_bytes(&bytes) {
-        Ok(bytes) => bytes,
-        Err(_) => return (false, false, 0, Vec::new(), Vec::new()),
+    // An unconfirmed payload can confirm in the next block at the earliest
+    let block_height = match get_tx_block_info(txid).await {
+        Some((block_height, _)) => block_height,
+        None => match get_current_block_height().await {
+            Ok(current_block_height) => current_block_height as u64 + 1,
+            Err(_) => return (false, false, 0, Vec::new(), Vec::new()),
+        },
+    };
+
+    let legacy_cutoff = match read_server_config() {
+        Ok(config) => config.legacy_lp_cutoff,
+        Err(_) => None,
     };
 
-    let payload: String = payload_bytes.iter().map(|&byte| byte as char).collect();
+    let payload =
+        match scl01_utils::decode_lp_payload(&bytes, contract_id, block_height, legacy_cutoff) {
+            Some(payload) => payload,
+            None => return (false, false, 0, Vec::new(), Vec::new()),
+        };
 
     println!("Payload: {}", payload);
-    if !payload.contains("SLP[") && !payload.contains("PLP[") && !payload.contains("LLP[") {
-        return (false, false, 0, Vec::new(), Vec::new());
-    }
 
     return (true, confirmed, fee, vin, vout);
 }
@@ -1965,36 +2375,17 @@ async fn handle_tx_info(
             None => return Err(String::new()),
         };
 
-        if scriptpubkey_type == "op_return".to_string() {
-            let scriptpubkey_asm = match output.scriptpubkey_asm {
-                Some(scriptpubkey_asm) => scriptpubkey_asm,
+        if scriptpubkey_type == "op_return" {
+            let scriptpubkey = match output.scriptpubkey {
+                Some(scriptpubkey) => scriptpubkey,
                 None => return Err(String::new()),
             };
 
-            let hash_check: String = scriptpubkey_asm;
-            let push_byte_length;
-            // Find the index of "OP_PUSHBYTES_" in the input string
-            if let Some(index) = hash_check.find("OP_PUSHBYTES_") {
-                // Move the index forward by the length of "OP_PUSHBYTES_" to get the start of the number
-                let start_index = index + "OP_PUSHBYTES_".len();
-                // Find the end index of the number
-                let end_index = hash_check[start_index..]
-                    .find(' ')
-                    .map(|pos| pos + start_index)
-                    .unwrap_or(hash_check.len());
-                // Extract the number substring
-                push_byte_length = &hash_check[start_index..end_index];
-            } else {
-                return Err(String::new());
-            }
-
-            let op_hash_checkpush_str = format!("OP_PUSHBYTES_{} ", push_byte_length);
-            let hash_check_index = match hash_check.find(op_hash_checkpush_str.as_str()) {
-                Some(hash_check_index) => hash_check_index,
+            payload = match get_op_return_payload(&scriptpubkey) {
+                Some(payload) => hex::encode(payload),
                 None => return Err(String::new()),
             };
 
-            payload = hash_check[hash_check_index + op_hash_checkpush_str.len()..].to_string();
             let status = match tx_info.status {
                 Some(status) => status,
                 None => return Err(String::new()),
@@ -2044,6 +2435,7 @@ async fn perform_contracts_checks() -> Result<String, String> {
             key: config.key,
             esplora: config.esplora.clone(),
             url: config.url,
+            legacy_lp_cutoff: config.legacy_lp_cutoff,
         };
 
         let _ = save_server_config(c);
@@ -2109,6 +2501,8 @@ async fn perform_contracts_checks() -> Result<String, String> {
                 Err(_) => Vec::new(),
             };
 
+            let lp_ids = scl01_utils::lp_envelope_ids(&contract_ids);
+
             for transaction in block_transactions {
                 let vout = match transaction.vout {
                     Some(vout) => vout,
@@ -2120,171 +2514,135 @@ async fn perform_contracts_checks() -> Result<String, String> {
                     None => continue,
                 };
 
-                let mut encrypted_payload: String = String::new();
+                let mut bytes: Vec<u8> = Vec::new();
                 for output in vout {
                     let scriptpubkey_type = match output.scriptpubkey_type {
                         Some(scriptpubkey_type) => scriptpubkey_type,
                         None => continue,
                     };
 
-                    if scriptpubkey_type == "op_return".to_string() {
-                        let scriptpubkey_asm = match output.scriptpubkey_asm {
-                            Some(scriptpubkey_asm) => scriptpubkey_asm,
+                    if scriptpubkey_type == "op_return" {
+                        let scriptpubkey = match output.scriptpubkey {
+                            Some(scriptpubkey) => scriptpubkey,
                             None => continue,
                         };
 
-                        let hash_check: String = scriptpubkey_asm;
-                        let push_byte_length;
-                        // Find the index of "OP_PUSHBYTES_" in the input string
-                        if let Some(index) = hash_check.find("OP_PUSHBYTES_") {
-                            // Move the index forward by the length of "OP_PUSHBYTES_" to get the start of the number
-                            let start_index = index + "OP_PUSHBYTES_".len();
-                            // Find the end index of the number
-                            let end_index = hash_check[start_index..]
-                                .find(' ')
-                                .map(|pos| pos + start_index)
-                                .unwrap_or(hash_check.len());
-                            // Extract the number substring
-                            push_byte_length = &hash_check[start_index..end_index];
-                        } else {
-                            continue;
-                        }
-
-                        let op_hash_checkpush_str = format!("OP_PUSHBYTES_{} ", push_byte_length);
-                        let hash_check_index = match hash_check.find(op_hash_checkpush_str.as_str())
-                        {
-                            Some(hash_check_index) => hash_check_index,
+                        bytes = match get_op_return_payload(&scriptpubkey) {
+                            Some(payload) => payload,
                             None => continue,
                         };
 
-                        encrypted_payload = hash_check
-                            [hash_check_index + op_hash_checkpush_str.len()..]
-                            .to_string();
                         break;
                     }
                 }
 
-                if encrypted_payload.len() == 0 {
+                if bytes.is_empty() {
                     continue;
                 }
 
-                let bytes = match Vec::from_hex(encrypted_payload.clone()) {
-                    Ok(vec) => vec,
-                    Err(_) => Vec::new(),
+                let (contract_id, payload) = match scl01_utils::route_lp_payload(
+                    &bytes,
+                    &lp_ids,
+                    &contract_ids,
+                    current_block as u64,
+                    config.legacy_lp_cutoff,
+                ) {
+                    Some(routed) => routed,
+                    None => continue,
                 };
 
-                for contract_id in contract_ids.clone() {
-                    let mc = new_magic_crypt!(contract_id.to_string(), 64);
-                    let payload_bytes = match mc.decrypt_bytes_to_bytes(&bytes) {
-                        Ok(bytes) => bytes,
-                        Err(_) => continue,
-                    };
-
-                    let payload: String = payload_bytes.iter().map(|&byte| byte as char).collect();
-
-                    if payload.contains("SLP[")
-                        || payload.contains("PLP[")
-                        || payload.contains("LLP[")
-                    {
-                        let pending_command = PendingCommandStruct {
-                            txid: txid.clone(),
-                            payload: payload.clone(),
-                            bid_payload: None,
-                            contract_id: Some(contract_id.clone()),
-                            time_added: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                        };
+                let pending_command = PendingCommandStruct {
+                    txid: txid.clone(),
+                    payload: payload.clone(),
+                    bid_payload: None,
+                    contract_id: Some(contract_id.clone()),
+                    time_added: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                };
 
-                        let command = CommandStruct {
-                            txid: txid.clone(),
-                            payload: payload.clone(),
-                            bid_payload: None,
-                            contract_id: Some(contract_id.clone()),
-                        };
+                let command = CommandStruct {
+                    txid: txid.clone(),
+                    payload: payload.clone(),
+                    bid_payload: None,
+                    contract_id: Some(contract_id.clone()),
+                };
 
-                        let command_str = match serde_json::to_string(&pending_command) {
-                            Ok(command_str) => command_str,
-                            Err(_) => break,
-                        };
+                let command_str = match serde_json::to_string(&pending_command) {
+                    Ok(command_str) => command_str,
+                    Err(_) => continue,
+                };
 
-                        let _ = match enqueue_item(
-                            format!(
-                                "{}{}-{}.txt",
-                                "./Json/Queues/Claims/",
-                                Local::now().format("%Y-%m-%d-%H-%M-%S").to_string(),
-                                txid
-                            ),
-                            &command_str.to_string(),
-                        ) {
-                            Ok(_) => {}
-                            Err(_) => break,
-                        };
+                match enqueue_item(
+                    format!(
+                        "{}{}-{}.txt",
+                        "./Json/Queues/Claims/",
+                        Local::now().format("%Y-%m-%d-%H-%M-%S"),
+                        txid
+                    ),
+                    &command_str.to_string(),
+                ) {
+                    Ok(_) => {}
+                    Err(_) => continue,
+                };
 
-                        save_command_backup(&command, false);
-                        break;
-                    }
-                }
+                save_command_backup(&command, false);
             }
         }
     }
 
-    for entry in entries {
-        if let Ok(entry) = entry {
-            let _thread = tokio::spawn(async move {
-                let pending_path = format!("{}/pending.txt", entry.path().to_string_lossy());
-                let path = format!("{}/state.txt", entry.path().to_string_lossy());
-                if !fs::metadata(&pending_path).is_ok() || !fs::metadata(&path).is_ok() {
-                    return;
-                }
+    for entry in entries.flatten() {
+        let _thread = tokio::spawn(async move {
+            let pending_path = format!("{}/pending.txt", entry.path().to_string_lossy());
+            let path = format!("{}/state.txt", entry.path().to_string_lossy());
+            if fs::metadata(&pending_path).is_err() || fs::metadata(&path).is_err() {
+                return;
+            }
 
-                let state_str = match read_from_file(path) {
-                    Some(state_str) => state_str,
-                    None => return,
-                };
+            let state_str = match read_from_file(path) {
+                Some(state_str) => state_str,
+                None => return,
+            };
 
-                write_to_file(pending_path, state_str);
-                if config.block_height < current_block {
-                    let directory_name = entry
-                        .path()
-                        .file_name()
-                        .and_then(|os_str| os_str.to_str())
-                        .map(|s| s.to_string());
+            write_to_file(pending_path, state_str);
+            if config.block_height < current_block {
+                let directory_name = entry
+                    .path()
+                    .file_name()
+                    .and_then(|os_str| os_str.to_str())
+                    .map(|s| s.to_string());
 
-                    let contract_id = match directory_name {
-                        Some(contract_id) => contract_id,
-                        None => return,
-                    };
+                let contract_id = match directory_name {
+                    Some(contract_id) => contract_id,
+                    None => return,
+                };
 
-                    _ = scl01_utils::perform_drips(
-                        contract_id.clone(),
-                        current_block as u64,
-                        false,
-                    );
-                    let contract = match read_contract(contract_id.as_str(), false) {
-                        Ok(contract) => contract,
-                        Err(_) => return,
-                    };
+                scl01_utils::perform_drips(contract_id.clone(), current_block as u64, false);
+                scl01_utils::perform_order_expiry(contract_id.clone(), current_block as u64);
+                scl01_utils::perform_dge_settlement(contract_id.clone(), current_block as u64);
+                let contract = match read_contract(contract_id.as_str(), false) {
+                    Ok(contract) => contract,
+                    Err(_) => return,
+                };
 
-                    if let Some(bids) = contract.bids.clone() {
-                        for (key, value) in bids {
-                            match add_fulfillment_commands_to_queue(
-                                &value.accept_tx,
-                                &key,
-                                &contract_id,
-                            )
-                            .await
-                            {
-                                Ok(_) => {}
-                                Err(_) => return,
-                            };
-                        }
+                if let Some(bids) = contract.bids.clone() {
+                    for (key, value) in bids {
+                        match add_fulfillment_commands_to_queue(
+                            &value.accept_tx,
+                            &key,
+                            &contract_id,
+                        )
+                        .await
+                        {
+                            Ok(_) => {}
+                            Err(_) => return,
+                        };
                     }
+                }
 
-                    if let Some(_split) = contract.clone().last_airdrop_split {
-                        scl01_utils::perform_airdrop_split(contract)
-                    }
+                if let Some(_split) = contract.clone().last_airdrop_split {
+                    scl01_utils::perform_airdrop_split(contract)
                 }
-            });
-        }
+            }
+        });
     }
 
     return Ok("Success".to_string());
@@ -2357,21 +2715,88 @@ fn get_contract_field(
             return Ok(format!("{{\"Token_Data\":\"{}\"}}", token_data.to_string()));
         }
 
-        "owners" => {
-            let total_pages = (contract.owners.len() as f64 / 100 as f64).ceil() as usize;
-            if contract.owners.len() > 100 {
-                if page > total_pages {
-                    page = total_pages;
-                }
+        "mintauthority" => {
+            let mint_authority = match &contract.mint_authority {
+                Some(mint_authority) => mint_authority,
+                None => return Ok("{}".to_string()),
+            };
 
-                let mut sorted_entries: Vec<_> = contract.owners.clone().drain().collect();
-                sorted_entries.sort_by(|a, b| a.0.cmp(&b.0));
-                let owners: Vec<_> = sorted_entries
-                    .iter()
-                    .skip(100 * (page - 1))
-                    .take(100)
-                    .collect();
-                let result = match serde_json::to_string(&owners) {
+            let max_supply = match contract.max_supply {
+                Some(max_supply) => max_supply,
+                None => contract.supply,
+            };
+
+            let minted = match contract.minted {
+                Some(minted) => minted,
+                None => contract.supply,
+            };
+
+            return Ok(format!(
+                "{{\"Mint_Authority\":\"{}\",\"Max_Supply\":\"{}\",\"Minted\":\"{}\"}}",
+                mint_authority, max_supply, minted
+            ));
+        }
+
+        "frozen" => {
+            let frozen_utxos = match &contract.frozen_utxos {
+                Some(frozen_utxos) => frozen_utxos.clone(),
+                None => Vec::new(),
+            };
+
+            let result = match serde_json::to_string(&frozen_utxos) {
+                Ok(result) => result,
+                Err(_) => return Err("Unable to get frozen utxos".to_string()),
+            };
+
+            return Ok(result);
+        }
+
+        "merkle_airdrops" => {
+            let merkle_airdrops = match &contract.merkle_airdrops {
+                Some(merkle_airdrops) => merkle_airdrops,
+                None => return Ok("[]".to_string()),
+            };
+
+            let mut sorted_entries: Vec<_> = merkle_airdrops.iter().collect();
+            sorted_entries.sort_by(|a, b| a.0.cmp(b.0));
+            let summaries: Vec<serde_json::Value> = sorted_entries
+                .iter()
+                .map(|(claim_id, airdrop)| {
+                    serde_json::json!({
+                        "claim_id": claim_id,
+                        "pool_amount": airdrop.pool_amount,
+                        "merkle_root": airdrop.merkle_root,
+                        "leaf_count": airdrop.leaf_count,
+                        "amount_airdropped": airdrop.amount_airdropped,
+                        "claimed_leaves": airdrop.claimed_count(),
+                        "claimed": airdrop.claimed,
+                    })
+                })
+                .collect();
+
+            let result = match serde_json::to_string(&summaries) {
+                Ok(result) => result,
+                Err(_) => return Err("Unable to get merkle airdrops".to_string()),
+            };
+
+            return Ok(result);
+        }
+
+        "owners" => {
+            let total_pages = (contract.owners.len() as f64 / 100 as f64).ceil() as usize;
+            if contract.owners.len() > 100 {
+                if page > total_pages {
+                    page = total_pages;
+                }
+
+                let mut sorted_entries: Vec<_> = contract.owners.clone().drain().collect();
+                sorted_entries.sort_by(|a, b| a.0.cmp(&b.0));
+                let owners: Vec<_> = sorted_entries
+                    .iter()
+                    .skip(100 * (page - 1))
+                    .take(100)
+                    .collect();
+                let result = match serde_json::to_string(&owners) {
                     Ok(result) => result,
                     Err(_) => return Err("Unable to get contract owners".to_string()),
                 };
@@ -2477,7 +2902,59 @@ fn get_contract_field(
                 Err(_) => return Ok("{}".to_string()),
             };
 
-            return Ok(format!("{}", result));
+            return Ok(result.to_string());
+        }
+
+        "analytics" => {
+            let analytics = get_pool_analytics(&contract)?;
+
+            let result = match serde_json::to_string(&analytics) {
+                Ok(result) => result,
+                Err(_) => return Ok("{}".to_string()),
+            };
+
+            return Ok(result.to_string());
+        }
+
+        "protocol_fees" => {
+            let liquidity_pool = match &contract.liquidity_pool {
+                Some(liquidity_pool) => liquidity_pool,
+                None => return Err("Contract is not a liquidity pool".to_string()),
+            };
+
+            let protocol_fee = match &liquidity_pool.protocol_fee {
+                Some(protocol_fee) => protocol_fee,
+                None => return Ok("{}".to_string()),
+            };
+
+            let result = match serde_json::to_string(&protocol_fee) {
+                Ok(result) => result,
+                Err(_) => return Ok("{}".to_string()),
+            };
+
+            return Ok(result.to_string());
+        }
+
+        "tokens" => {
+            let tokens = contract.nft_tokens()?;
+
+            let result = match serde_json::to_string(&tokens) {
+                Ok(result) => result,
+                Err(_) => return Ok("[]".to_string()),
+            };
+
+            return Ok(result.to_string());
+        }
+
+        "positions" => {
+            let positions = contract.lp_positions()?;
+
+            let result = match serde_json::to_string(&positions) {
+                Ok(result) => result,
+                Err(_) => return Ok("[]".to_string()),
+            };
+
+            return Ok(result.to_string());
         }
 
         "listings" => {
@@ -2486,14 +2963,14 @@ fn get_contract_field(
                 None => return Ok("{}".to_string()),
             };
 
-            let total_pages = (listings.len() as f64 / 100 as f64).ceil() as usize;
+            let total_pages = (listings.len() as f64 / 100_f64).ceil() as usize;
             if listings.len() > 100 {
                 if page > total_pages {
                     page = total_pages;
                 }
 
-                let mut sorted_entries: Vec<_> = listings.into_iter().collect();
-                sorted_entries.sort_by(|a, b| a.0.cmp(&b.0));
+                let mut sorted_entries: Vec<_> = listings.iter().collect();
+                sorted_entries.sort_by(|a, b| a.0.cmp(b.0));
                 let filtered_listings: Vec<_> = sorted_entries
                     .iter()
                     .skip(100 * (page - 1))
@@ -2753,40 +3230,28 @@ fn get_contract_field(
             match read_contract_interactions(&contract.contractid) {
                 Ok(contract_interactions) => {
                     let mut summary = ContractSummary::default();
-                    let mut total_list_val = 0;
-                    let mut total_bid_val: u64 = 0;
+                    // Volume weighted averages over every fulfilled trade
+                    let mut total_list_val: u128 = 0;
+                    let mut total_bid_val: u128 = 0;
                     let mut avg_fulfil_price = 0;
                     let mut avg_list_price = 0;
                     let mut total_fulfiled = 0;
                     let mut total_listed = 0;
-                    let mut index = 0;
-                    let mut latest_fulfilled = 0;
-                    let mut latest_bid_value = 0;
                     for fulfilment in &contract_interactions.fulfillment_summaries {
-                        total_list_val += fulfilment.listing_price * fulfilment.listing_amount;
+                        total_list_val +=
+                            fulfilment.listing_price as u128 * fulfilment.listing_amount as u128;
                         total_listed += fulfilment.listing_amount;
+                        total_bid_val +=
+                            fulfilment.bid_price as u128 * fulfilment.bid_amount as u128;
+                        total_fulfiled += fulfilment.bid_amount;
+                    }
 
-                        if index == &contract_interactions.fulfillment_summaries.len() - 1 {
-                            latest_fulfilled = fulfilment.bid_amount;
-                            latest_bid_value = fulfilment.bid_price * fulfilment.bid_amount;
-                        } else {
-                            total_bid_val += fulfilment.bid_price * fulfilment.bid_amount;
-                            total_fulfiled += fulfilment.bid_amount;
-                        }
-                        index += 1;
+                    if total_fulfiled != 0 {
+                        avg_fulfil_price = (total_bid_val / total_fulfiled as u128) as u64;
                     }
 
-                    if contract_interactions.fulfillment_summaries.len() > 1
-                        && total_fulfiled != 0
-                        && total_bid_val != 0
-                    {
-                        avg_fulfil_price = ((total_bid_val / total_fulfiled)
-                            + (latest_bid_value / latest_fulfilled))
-                            / 2;
-                        avg_list_price = total_list_val / total_listed;
-                    } else if contract_interactions.fulfillment_summaries.len() == 1 {
-                        avg_fulfil_price = latest_bid_value / latest_fulfilled;
-                        avg_list_price = total_list_val / total_listed;
+                    if total_listed != 0 {
+                        avg_list_price = (total_list_val / total_listed as u128) as u64;
                     }
 
                     let mut available_airdrops: Option<u64> = None;
@@ -2853,6 +3318,7 @@ fn get_contract_field(
                     summary.lp_ratio = ratio;
                     summary.lp_contracts = lp_contracts;
                     summary.token_data = t_data;
+                    summary.metadata = import.metadata;
                     match serde_json::to_string(&summary) {
                         Ok(parsed_data) => return Ok(parsed_data),
                         Err(_) => return Err("Failed to serialize contract summary".to_string()),
@@ -2900,6 +3366,52 @@ fn get_utxo_field(
     };
 
     match field.as_str() {
+        "token" => {
+            let token = contract.nft_token(&utxo)?;
+
+            let result = match serde_json::to_string(&token) {
+                Ok(result) => result,
+                Err(_) => return Ok("{}".to_string()),
+            };
+
+            return Ok(result.to_string());
+        }
+
+        "position" => {
+            let position = contract.lp_position(&utxo)?;
+
+            let result = match serde_json::to_string(&position) {
+                Ok(result) => result,
+                Err(_) => return Ok("{}".to_string()),
+            };
+
+            return Ok(result.to_string());
+        }
+
+        "drips" => {
+            // Completed drips are removed from the contract so they come from the drip history
+            let drips = contract.drip_status(&utxo).unwrap_or_default();
+
+            let history = scl01_utils::read_drip_history(contract_id, &utxo);
+            if drips.is_empty() && history.completed.is_empty() {
+                return Err("drip_status: utxo has no drips".to_string());
+            }
+
+            let released = drips
+                .iter()
+                .chain(history.completed.iter())
+                .map(|drip| drip.released);
+            let response = serde_json::json!({
+                "drips": drips,
+                "completed": history.completed,
+                "released": released.sum::<u64>(),
+                "remaining": drips.iter().map(|drip| drip.remaining).sum::<u64>(),
+                "events": history.events,
+            });
+
+            return Ok(response.to_string());
+        }
+
         "bids_on_listing" => {
             let bids = match &contract.bids {
                 Some(bids) => bids,
@@ -3165,6 +3677,326 @@ fn get_listing_summaries(
     return Ok(summaries);
 }
 
+fn get_orderbook(
+    contract_id: &String,
+    pending: bool,
+    limit: Option<usize>,
+) -> Result<String, String> {
+    let contract = match read_contract(contract_id, pending) {
+        Ok(contract) => contract,
+        Err(_) => return Err("Unable to read contract".to_string()),
+    };
+
+    let listings = contract.listings.unwrap_or_default();
+
+    let bids = contract.bids.unwrap_or_default();
+
+    let fulfillments = contract.fulfillments.unwrap_or_default();
+
+    // Orders that already have an accepted bid are no longer available to trade against
+    let mut ask_levels: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    for (order_id, listing) in &listings {
+        if fulfillments.values().any(|value| value == order_id) {
+            continue;
+        }
+
+        let level = ask_levels.entry(listing.price).or_insert((0, 0));
+        level.0 += listing.list_amt;
+        level.1 += 1;
+    }
+
+    let mut bid_levels: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    for (bid_id, bid) in &bids {
+        if fulfillments.contains_key(bid_id)
+            || !listings.contains_key(&bid.order_id)
+            || fulfillments.values().any(|value| *value == bid.order_id)
+        {
+            continue;
+        }
+
+        let level = bid_levels.entry(bid.bid_price).or_insert((0, 0));
+        level.0 += bid.bid_amount;
+        level.1 += 1;
+    }
+
+    let asks = construct_orderbook_ladder(ask_levels.into_iter().collect(), limit);
+    let bids = construct_orderbook_ladder(bid_levels.into_iter().rev().collect(), limit);
+    let mut orderbook = OrderBook {
+        contract_id: contract_id.to_string(),
+        pending,
+        best_ask: asks.first().map(|level| level.price),
+        best_bid: bids.first().map(|level| level.price),
+        asks,
+        bids,
+        spread: None,
+        mid_price: None,
+    };
+
+    if let (Some(best_ask), Some(best_bid)) = (orderbook.best_ask, orderbook.best_bid) {
+        orderbook.spread = Some(best_ask as i64 - best_bid as i64);
+        orderbook.mid_price = Some((best_ask + best_bid) / 2);
+    }
+
+    let result = match serde_json::to_string(&orderbook) {
+        Ok(result) => result,
+        Err(_) => return Err("Unable to get contract orderbook".to_string()),
+    };
+
+    return Ok(result);
+}
+
+fn get_candles(
+    contract_id: &str,
+    interval: i64,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<String, String> {
+    let contract = match read_contract(contract_id, false) {
+        Ok(contract) => contract,
+        Err(_) => return Err("Unable to read contract".to_string()),
+    };
+
+    let interactions = match read_contract_interactions(&contract.contractid) {
+        Ok(interactions) => interactions,
+        Err(_) => return Err("Unable to get contract fulfillments".to_string()),
+    };
+
+    // Trades recorded before block times were stored are skipped until backfilled
+    let mut trades: Vec<(i64, u64, u64)> = Vec::new();
+    for fulfilment in &interactions.fulfillment_summaries {
+        let block_time = match fulfilment.block_time {
+            Some(block_time) => block_time,
+            None => continue,
+        };
+
+        if from.is_some_and(|from| block_time < from) || to.is_some_and(|to| block_time > to) {
+            continue;
+        }
+
+        let volume = (fulfilment.bid_amount as u128 * fulfilment.bid_price as u128
+            / 10u128.pow(contract.decimals as u32)) as u64;
+        trades.push((block_time, fulfilment.bid_price, volume));
+    }
+
+    trades.sort_by_key(|trade| trade.0);
+    let mut candles: Vec<Candle> = Vec::new();
+    for (block_time, price, volume) in trades {
+        let time = block_time - block_time.rem_euclid(interval);
+        match candles.last_mut() {
+            Some(candle) if candle.time == time => {
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.close = price;
+                candle.volume += volume;
+                candle.trades += 1;
+            }
+            _ => candles.push(Candle {
+                time,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume,
+                trades: 1,
+            }),
+        }
+    }
+
+    let result = match serde_json::to_string(&candles) {
+        Ok(result) => result,
+        Err(_) => return Err("Unable to get contract candles".to_string()),
+    };
+
+    return Ok(result);
+}
+
+fn get_last_trade_price(contract_id: &str) -> Option<u64> {
+    let interactions = match read_contract_interactions(contract_id) {
+        Ok(interactions) => interactions,
+        Err(_) => return None,
+    };
+
+    // Summaries are appended in trade order, block times break ties after a backfill
+    let mut last: Option<(i64, u64)> = None;
+    for fulfilment in &interactions.fulfillment_summaries {
+        let block_time = fulfilment.block_time.unwrap_or_default();
+
+        if last.is_none_or(|(last_time, _)| block_time >= last_time) {
+            last = Some((block_time, fulfilment.bid_price));
+        }
+    }
+
+    return last.map(|(_, price)| price);
+}
+
+fn get_pool_analytics(contract: &scl01_contract::SCL01Contract) -> Result<PoolAnalytics, String> {
+    let liquidity_pool = match &contract.liquidity_pool {
+        Some(liquidity_pool) => liquidity_pool,
+        None => return Err("Contract is not a liquidity pool".to_string()),
+    };
+
+    let unit = 10u128.pow(contract.decimals as u32);
+
+    // Token amounts are valued in token 1 at the current pool price
+    let value_in_token_1 = |contract_id: &String, amount: u64| -> u128 {
+        if *contract_id == liquidity_pool.contract_id_1 {
+            return amount as u128;
+        }
+
+        return liquidity_pool.spot_output(false, amount as u128);
+    };
+
+    // A missing marketplace price is implied from the other token through the pool price
+    let mut price_1 = get_last_trade_price(&liquidity_pool.contract_id_1).map(|p| p as u128);
+    let mut price_2 = get_last_trade_price(&liquidity_pool.contract_id_2).map(|p| p as u128);
+    if price_1.is_none() {
+        price_1 = price_2.map(|price_2| price_2 * liquidity_pool.spot_output(true, unit) / unit);
+    }
+
+    if price_2.is_none() {
+        price_2 = price_1.map(|price_1| price_1 * liquidity_pool.spot_output(false, unit) / unit);
+    }
+
+    let to_sats = |amount_in_token_1: u128| -> Option<u64> {
+        return price_1.map(|price_1| (amount_in_token_1 * price_1 / unit) as u64);
+    };
+
+    let now = Local::now().timestamp();
+    let windows: [i64; 2] = [86400, 604800];
+    let mut stats = [PoolWindowStats::default(), PoolWindowStats::default()];
+    let mut fees_in_token_1: [u128; 2] = [0, 0];
+    let swap_records = liquidity_pool.swap_records.clone().unwrap_or_default();
+
+    for swap_record in swap_records.values() {
+        let block_time = match swap_record.block_time {
+            Some(block_time) => block_time,
+            None => continue,
+        };
+
+        for (index, window) in windows.iter().enumerate() {
+            if now - block_time > *window {
+                continue;
+            }
+
+            stats[index].swaps += 1;
+            if swap_record.sender_contract_id == liquidity_pool.contract_id_1 {
+                stats[index].volume_token_1 += swap_record.provided_amount;
+                stats[index].fees_token_1 += swap_record.fee;
+            } else {
+                stats[index].volume_token_2 += swap_record.provided_amount;
+                stats[index].fees_token_2 += swap_record.fee;
+            }
+
+            fees_in_token_1[index] +=
+                value_in_token_1(&swap_record.sender_contract_id, swap_record.fee);
+        }
+    }
+
+    let liquidation_records = liquidity_pool
+        .liquidation_records
+        .clone()
+        .unwrap_or_default();
+
+    for liquidation_record in liquidation_records.values() {
+        let block_time = match liquidation_record.block_time {
+            Some(block_time) => block_time,
+            None => continue,
+        };
+
+        for (index, window) in windows.iter().enumerate() {
+            if now - block_time <= *window {
+                stats[index].liquidations += 1;
+            }
+        }
+    }
+
+    for stat in stats.iter_mut() {
+        let volume_in_token_1 =
+            value_in_token_1(&liquidity_pool.contract_id_1, stat.volume_token_1)
+                + value_in_token_1(&liquidity_pool.contract_id_2, stat.volume_token_2);
+        let fee_in_token_1 = value_in_token_1(&liquidity_pool.contract_id_1, stat.fees_token_1)
+            + value_in_token_1(&liquidity_pool.contract_id_2, stat.fees_token_2);
+        stat.volume_sats = to_sats(volume_in_token_1);
+        stat.fees_sats = to_sats(fee_in_token_1);
+    }
+
+    let tvl_token_1 = value_in_token_1(&liquidity_pool.contract_id_1, liquidity_pool.pool_1)
+        + value_in_token_1(&liquidity_pool.contract_id_2, liquidity_pool.pool_2);
+    let tvl_token_2 = liquidity_pool.spot_output(true, tvl_token_1);
+
+    // Fees stay in the pool, so the annualised 7 day fee revenue over TVL is the LP return
+    let fee_apr_bps = (fees_in_token_1[1] * 365 * BPS_SCALE as u128)
+        .checked_div(7 * tvl_token_1)
+        .map(|apr| apr as u64);
+
+    let [day, week] = stats;
+    return Ok(PoolAnalytics {
+        contract_id: contract.contractid.clone(),
+        contract_id_1: liquidity_pool.contract_id_1.clone(),
+        contract_id_2: liquidity_pool.contract_id_2.clone(),
+        price_1: price_1.map(|price| price as u64),
+        price_2: price_2.map(|price| price as u64),
+        tvl_token_1: tvl_token_1 as u64,
+        tvl_token_2: tvl_token_2 as u64,
+        tvl_sats: to_sats(tvl_token_1),
+        day,
+        week,
+        fee_apr_bps,
+    });
+}
+
+fn get_swap_quote(
+    lp_contract_id: &str,
+    pending: bool,
+    from: &str,
+    amount: u64,
+    tolerance_bps: u64,
+) -> Result<String, String> {
+    let contract = match read_contract(lp_contract_id, pending) {
+        Ok(contract) => contract,
+        Err(_) => return Err("Unable to read contract".to_string()),
+    };
+
+    let liquidity_pool = match contract.liquidity_pool {
+        Some(liquidity_pool) => liquidity_pool,
+        None => return Err("Contract is not a liquidity pool".to_string()),
+    };
+
+    let quote = liquidity_pool.quote_swap(from, amount, tolerance_bps)?;
+
+    let result = match serde_json::to_string(&quote) {
+        Ok(result) => result,
+        Err(_) => return Err("Unable to get swap quote".to_string()),
+    };
+
+    return Ok(result);
+}
+
+fn construct_orderbook_ladder(
+    levels: Vec<(u64, (u64, u64))>,
+    limit: Option<usize>,
+) -> Vec<OrderBookLevel> {
+    let mut ladder: Vec<OrderBookLevel> = Vec::new();
+    let mut cumulative_amount: u64 = 0;
+    for (price, (amount, orders)) in levels {
+        if let Some(limit) = limit {
+            if ladder.len() >= limit {
+                break;
+            }
+        }
+
+        cumulative_amount += amount;
+        ladder.push(OrderBookLevel {
+            price,
+            amount,
+            orders,
+            cumulative_amount,
+        });
+    }
+
+    return ladder;
+}
+
 fn get_trade_details_from_bid_utxo(
     contract_id: &String,
     bid_utxos: Vec<String>,
@@ -3351,6 +4183,94 @@ fn extract_info_from_payload(
             continue;
         }
 
+        // History is classified on the same exact keywords the newer commands are dispatched on,
+        // so free text in their arguments can't be read as another command
+        match extract_command_keyword(&command).as_str() {
+            "MERKLEAIRDROP" => {
+                let result = match scl01_utils::handle_create_merkle_airdrop_payload(txid, &command)
+                {
+                    Ok(res) => res,
+                    Err(_) => continue,
+                };
+
+                entries.push(ContractHistoryEntry {
+                    tx_type: "Create Merkle Airdrop".to_owned(),
+                    scl_value: result.1,
+                    btc_price: None,
+                    txid: txid.to_owned(),
+                    pending,
+                    royalty_amount: None,
+                });
+                continue;
+            }
+            "CLAIM_MERKLEAIRDROP" => {
+                let result = match scl01_utils::handle_claim_merkle_airdrop_payload(txid, &command)
+                {
+                    Ok(res) => res,
+                    Err(_) => continue,
+                };
+
+                entries.push(ContractHistoryEntry {
+                    tx_type: "Claim Merkle Airdrop".to_owned(),
+                    scl_value: result.2,
+                    btc_price: None,
+                    txid: txid.to_owned(),
+                    pending,
+                    royalty_amount: None,
+                });
+                continue;
+            }
+            "TRANSFER_TOKENS" => {
+                let token_count = match scl01_utils::handle_transfer_tokens_payload(txid, &command)
+                {
+                    Ok((_, receivers)) => receivers
+                        .iter()
+                        .map(|(_, token_ids)| token_ids.len() as u64)
+                        .sum(),
+                    Err(_) => 0,
+                };
+
+                entries.push(ContractHistoryEntry {
+                    tx_type: "Transfer".to_owned(),
+                    scl_value: token_count,
+                    txid: txid.to_owned(),
+                    btc_price: None,
+                    pending,
+                    royalty_amount: None,
+                });
+                continue;
+            }
+            "MINT_SUPPLY" => {
+                let result = match scl01_utils::handle_mint_supply_payload(txid, &command) {
+                    Ok(res) => res,
+                    Err(_) => continue,
+                };
+
+                entries.push(ContractHistoryEntry {
+                    tx_type: "Mint".to_owned(),
+                    scl_value: result.3,
+                    btc_price: None,
+                    txid: txid.to_owned(),
+                    pending,
+                    royalty_amount: None,
+                });
+                continue;
+            }
+            "RENOUNCE_MINT" => {
+                entries.push(ContractHistoryEntry {
+                    tx_type: "Renounce Mint".to_owned(),
+                    scl_value: 0,
+                    btc_price: None,
+                    txid: txid.to_owned(),
+                    pending,
+                    royalty_amount: None,
+                });
+                continue;
+            }
+            "REVOKE_DRIP" | "METADATA" | "FREEZE" | "UNFREEZE" => continue,
+            _ => {}
+        }
+
         if command.contains("AIRDROP") {
             let contract = match read_contract(contract_id, false) {
                 Ok(contract) => contract,
@@ -3368,6 +4288,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("TRANSFER") {
             let result: (Vec<String>, Vec<(String, u64)>, String) =
@@ -3387,6 +4308,7 @@ fn extract_info_from_payload(
                 txid: txid.to_owned(),
                 btc_price: None,
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("BURN") {
             let result = match scl01_utils::handle_burn_payload(&txid, &command) {
@@ -3400,6 +4322,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("SCL01") {
             let result = match scl01_utils::handle_mint_payload(&command, &txid) {
@@ -3413,6 +4336,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("SCL02") {
             let result = match scl01_utils::handle_mint_payload(&command, &txid) {
@@ -3426,6 +4350,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("SCL03") {
             let result = match scl01_utils::handle_mint_rtm_payload(&command, &txid) {
@@ -3444,6 +4369,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains(":DIMAIRDROP") {
             let result =
@@ -3458,6 +4384,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("CLAIM_DIMAIRDROP") {
             entries.push(ContractHistoryEntry {
@@ -3466,9 +4393,15 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains(":DGE") {
-            let result = match scl01_utils::handle_create_dge_payload(&command, &txid) {
+            let dge_command = match scl01_utils::split_claim_rules(&command) {
+                Ok((dge_command, _)) => dge_command,
+                Err(_) => continue,
+            };
+
+            let result = match scl01_utils::handle_create_dge_payload(&dge_command, txid) {
                 Ok(res) => res,
                 Err(_) => continue,
             };
@@ -3479,6 +4412,7 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains("CLAIM_DGE") {
             entries.push(ContractHistoryEntry {
@@ -3487,20 +4421,51 @@ fn extract_info_from_payload(
                 btc_price: None,
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
+            });
+        } else if command.contains("EXPIRE_LISTING") || command.contains("EXPIRE_BID") {
+            let mut tx_type = "Listing Expired".to_owned();
+            if command.contains("EXPIRE_BID") {
+                tx_type = "Bid Expired".to_owned();
+            }
+
+            let expired_info: Vec<&str> = command.split(",").collect();
+            let amount = match expired_info.last() {
+                Some(amount) => amount.replace("]", "").parse::<u64>().unwrap_or(0),
+                None => 0,
+            };
+
+            entries.push(ContractHistoryEntry {
+                tx_type,
+                scl_value: amount,
+                btc_price: None,
+                txid: txid.to_owned(),
+                pending,
+                royalty_amount: None,
             });
         } else if command.contains("LIST") {
-            let result: (Vec<String>, String, String, String, u64, u64) =
-                match scl01_utils::handle_list_payload(&txid, &command) {
-                    Ok(res) => res,
-                    Err(_) => (
-                        Vec::new(),
-                        "".to_string(),
-                        "".to_string(),
-                        "".to_string(),
-                        0,
-                        0,
-                    ),
-                };
+            let result: (
+                Vec<String>,
+                String,
+                String,
+                String,
+                u64,
+                u64,
+                Option<u64>,
+                bool,
+            ) = match scl01_utils::handle_list_payload(&txid, &command) {
+                Ok(res) => res,
+                Err(_) => (
+                    Vec::new(),
+                    "".to_string(),
+                    "".to_string(),
+                    "".to_string(),
+                    0,
+                    0,
+                    None,
+                    false,
+                ),
+            };
 
             entries.push(ContractHistoryEntry {
                 tx_type: "List".to_owned(),
@@ -3508,6 +4473,7 @@ fn extract_info_from_payload(
                 btc_price: Some(result.5),
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: None,
             });
         } else if command.contains(":BID") {
             let results = match scl01_utils::handle_bid_payload(&txid, &command) {
@@ -3522,6 +4488,7 @@ fn extract_info_from_payload(
                     btc_price: Some(res.2),
                     txid: txid.to_owned(),
                     pending,
+                    royalty_amount: None,
                 });
             }
         } else if command.contains("ACCEPT_BID") {
@@ -3534,6 +4501,7 @@ fn extract_info_from_payload(
                         btc_price: None,
                         txid: txid.to_owned(),
                         pending,
+                        royalty_amount: None,
                     });
                     continue;
                 }
@@ -3544,6 +4512,7 @@ fn extract_info_from_payload(
                 btc_price: Some(result.2),
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: result.3,
             });
         } else if command.contains("FULFIL_TRADE") {
             let result = match handle_payload_extra_trade_info(&payload) {
@@ -3555,6 +4524,7 @@ fn extract_info_from_payload(
                         btc_price: None,
                         txid: txid.to_owned(),
                         pending,
+                        royalty_amount: None,
                     });
                     continue;
                 }
@@ -3565,6 +4535,7 @@ fn extract_info_from_payload(
                 btc_price: Some(result.2),
                 txid: txid.to_owned(),
                 pending,
+                royalty_amount: result.3,
             });
         }
     }
@@ -3672,18 +4643,170 @@ fn contruct_pagination_metadata(
     };
     let mut result: String = "{\"data\":".to_string();
     result.push_str(&data);
-    result.push_str(",");
-    let meta_str = match serde_json::to_string(&meta) {
-        Ok(mata_str) => mata_str,
-        Err(_) => String::new(),
-    };
+    result.push(',');
+    let meta_str = serde_json::to_string(&meta).unwrap_or_default();
 
     result.push_str("\"meta\":");
     result.push_str(&meta_str);
-    result.push_str("}");
+    result.push('}');
     return result;
 }
 
+async fn backfill_trade_history() {
+    let entries = match fs::read_dir(CONTRACTSPATH) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries {
+        if let Ok(entry) = entry {
+            let directory_name = entry
+                .path()
+                .file_name()
+                .and_then(|os_str| os_str.to_str())
+                .map(|s| s.to_string());
+
+            let contract_id = match directory_name {
+                Some(contract_id) => contract_id,
+                None => continue,
+            };
+
+            let mut contract = match read_contract(&contract_id, false) {
+                Ok(contract) => contract,
+                Err(_) => continue,
+            };
+
+            if let Some(mut liquidity_pool) = contract.liquidity_pool.clone() {
+                let mut swap_records = liquidity_pool.swap_records.clone().unwrap_or_default();
+
+                for (txid, (provided_amount, swap_amount)) in &liquidity_pool.swaps {
+                    let mut swap_record = match swap_records.get(txid) {
+                        Some(swap_record) if swap_record.block_height.is_some() => continue,
+                        Some(swap_record) => swap_record.clone(),
+                        // Swaps settled before records were kept only have a sender in their
+                        // payload and are charged the current fee rate
+                        None => {
+                            let sender_contract_id = match contract
+                                .payloads
+                                .get(txid)
+                                .map(|payload| scl01_utils::handle_swap_payload_lp(payload))
+                            {
+                                Some(Ok((true, _, _, _))) => liquidity_pool.contract_id_1.clone(),
+                                Some(Ok((false, _, _, _))) => liquidity_pool.contract_id_2.clone(),
+                                _ => continue,
+                            };
+
+                            scl01_contract::SwapRecord {
+                                sender_contract_id,
+                                provided_amount: *provided_amount,
+                                swap_amount: *swap_amount,
+                                fee_bps: liquidity_pool.fee_bps(),
+                                fee: liquidity_pool.swap_fee(*provided_amount),
+                                block_height: None,
+                                block_time: None,
+                            }
+                        }
+                    };
+
+                    if let Some((block_height, block_time)) = get_tx_block_info(txid).await {
+                        swap_record.block_height = Some(block_height);
+                        swap_record.block_time = Some(block_time);
+                    }
+
+                    swap_records.insert(txid.to_string(), swap_record);
+                }
+
+                liquidity_pool.swap_records = Some(swap_records);
+                let mut liquidation_records = liquidity_pool
+                    .liquidation_records
+                    .clone()
+                    .unwrap_or_default();
+
+                for (txid, (token_1, token_2)) in &liquidity_pool.liquidations {
+                    if liquidation_records
+                        .get(txid)
+                        .is_some_and(|liquidation_record| liquidation_record.block_height.is_some())
+                    {
+                        continue;
+                    }
+
+                    if let Some((block_height, block_time)) = get_tx_block_info(txid).await {
+                        liquidation_records.insert(
+                            txid.to_string(),
+                            scl01_contract::LiquidationRecord {
+                                token_1: *token_1,
+                                token_2: *token_2,
+                                block_height: Some(block_height),
+                                block_time: Some(block_time),
+                            },
+                        );
+                    }
+                }
+
+                liquidity_pool.liquidation_records = Some(liquidation_records);
+                contract.liquidity_pool = Some(liquidity_pool);
+                let _ = save_contract(&contract, "", "", false);
+            }
+
+            let mut interactions = match read_contract_interactions(&contract_id) {
+                Ok(interactions) => interactions,
+                Err(_) => continue,
+            };
+
+            if interactions
+                .fulfillment_summaries
+                .iter()
+                .all(|fulfilment| fulfilment.block_time.is_some())
+            {
+                continue;
+            }
+
+            // Fulfilled trade payloads carry the bid amount and price, which is enough to
+            // pair them back up with the summaries that were stored without a txid
+            let mut trades: Vec<(String, u64, u64, u64, i64)> = Vec::new();
+            for (txid, payload) in &contract.payloads {
+                if !payload.contains("FULFIL_TRADE") {
+                    continue;
+                }
+
+                let (_, amount, price, _) = match handle_payload_extra_trade_info(payload) {
+                    Ok(result) => result,
+                    Err(_) => continue,
+                };
+
+                if let Some((block_height, block_time)) = get_tx_block_info(txid).await {
+                    trades.push((txid.to_string(), amount, price, block_height, block_time));
+                }
+            }
+
+            trades.sort_by_key(|trade| trade.3);
+            for fulfilment in interactions.fulfillment_summaries.iter_mut() {
+                if fulfilment.block_time.is_some() {
+                    continue;
+                }
+
+                let known_txid = fulfilment.txid.clone();
+                let position = trades.iter().position(|trade| match &known_txid {
+                    Some(txid) => trade.0 == *txid,
+                    None => trade.1 == fulfilment.bid_amount && trade.2 == fulfilment.bid_price,
+                });
+
+                if let Some(position) = position {
+                    let trade = trades.remove(position);
+                    fulfilment.txid = Some(trade.0);
+                    fulfilment.block_height = Some(trade.3);
+                    fulfilment.block_time = Some(trade.4);
+                }
+            }
+
+            match save_contract_interactions(&interactions, &contract_id) {
+                Ok(_) => println!("Backfilled trade history for {}", contract_id),
+                Err(_) => continue,
+            };
+        }
+    }
+}
+
 async fn remove_spent_utxos() {
     let config = match read_server_config() {
         Ok(config) => config,
@@ -1,7 +1,23 @@
+use bitcoin::util::uint::Uint256;
+use bitcoin::Address;
+use crypto_hash::{digest, Algorithm};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+pub const BPS_SCALE: u64 = 10_000;
+pub const RATIO_SCALE: u128 = 1_000_000_000;
+pub const MAX_SWAP_HOPS: usize = 3;
+pub const SCL04_FEE_TIERS_BPS: [u64; 4] = [1, 5, 30, 100];
+pub const MAX_PROTOCOL_FEE_SHARE_BPS: u64 = 5_000;
+pub const CURVE_SCALE: u128 = 1_000_000_000_000_000_000;
+pub const MAX_AMPLIFICATION: u64 = 10_000;
+pub const MAX_ROYALTY_BPS: u64 = 2_500;
+pub const BLOCKS_PER_MONTH: u64 = 4_320;
+pub const MAX_MERKLE_LEAVES: u64 = 1_048_576;
+pub const MAX_MERKLE_PROOF_LENGTH: usize = 32;
+const STABLE_SWAP_ITERATIONS: usize = 255;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct SCL01Contract {
     pub ticker: String,
     pub contractid: String,
@@ -25,6 +41,14 @@ pub struct SCL01Contract {
     pub liquidated_tokens: Option<u64>,
     pub liquidity_pool: Option<LiquidityPool>,
     pub token_data: Option<String>,
+    pub nft_tokens: Option<HashMap<String, NftToken>>,
+    pub royalty: Option<Royalty>,
+    pub metadata_authority: Option<String>,
+    pub mint_authority: Option<String>,
+    pub minted: Option<u64>,
+    pub freeze_authority: Option<String>,
+    pub frozen_utxos: Option<Vec<String>>,
+    pub merkle_airdrops: Option<HashMap<String, MerkleAirdrop>>,
 }
 
 impl SCL01Contract {
@@ -90,6 +114,10 @@ impl SCL01Contract {
         receivers: &Vec<String>,
         current_block_height: u64,
     ) -> Result<(bool, u64), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("consolidate: utxo {} is frozen", frozen_utxo));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -99,6 +127,13 @@ impl SCL01Contract {
         if owners_amount == 0 {
             return Err("consolidate: owner amount is zero".to_string());
         }
+
+        let position_receivers: Vec<(String, u64)> = receivers
+            .iter()
+            .map(|receiver| (receiver.clone(), owners_amount))
+            .collect();
+        self.transfer_lp_positions(sender_utxos, &position_receivers);
+        self.transfer_nft_tokens(sender_utxos, &position_receivers);
         let mut drips = match self.drips.clone() {
             Some(drips) => drips,
             None => HashMap::new(),
@@ -110,16 +145,7 @@ impl SCL01Contract {
                 self.owners.remove(&sender_utxo);
                 if let Some(old_drips) = drips.get(&sender_utxo) {
                     for drip in old_drips {
-                        let new_drip = Drip {
-                            block_end: drip.block_end.clone(),
-                            drip_amount: drip.drip_amount.clone(),
-                            amount: drip.amount.clone()
-                                - (current_block_height - drip.start_block) * drip.drip_amount,
-                            start_block: current_block_height,
-                            last_block_dripped: current_block_height,
-                        };
-
-                        new_drips.push(new_drip.clone());
+                        new_drips.push(drip.rebase(current_block_height));
                     }
 
                     // Remove the old drip from the vector
@@ -164,6 +190,10 @@ impl SCL01Contract {
         receivers: &Vec<(String, u64)>,
         current_block_height: u64,
     ) -> Result<(Vec<bool>, u64), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("transfer: utxo {} is frozen", frozen_utxo));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -183,23 +213,20 @@ impl SCL01Contract {
             None => HashMap::new(),
         };
 
+        if self.nft_tokens.is_some() && total_value != owners_amount {
+            return Err("transfer: every collection token held must be sent".to_string());
+        }
+
         if total_value <= owners_amount {
+            self.transfer_lp_positions(sender_utxos, receivers);
+            self.transfer_nft_tokens(sender_utxos, receivers);
             let mut new_drips: Vec<Drip> = Vec::new();
             for sender_utxo in sender_utxos.clone() {
                 if self.owners.contains_key(&sender_utxo.clone()) {
                     self.owners.remove(&sender_utxo);
                     if let Some(old_drips) = drips.get(&sender_utxo) {
                         for drip in old_drips {
-                            let new_drip = Drip {
-                                block_end: drip.block_end.clone(),
-                                drip_amount: drip.drip_amount.clone(),
-                                amount: drip.amount.clone()
-                                    - (current_block_height - drip.start_block) * drip.drip_amount,
-                                start_block: current_block_height,
-                                last_block_dripped: current_block_height,
-                            };
-
-                            new_drips.push(new_drip.clone());
+                            new_drips.push(drip.rebase(current_block_height));
                         }
 
                         // Remove the old drip from the vector
@@ -243,15 +270,21 @@ impl SCL01Contract {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn start_drip(
         &mut self,
         txid: &String,
         payload: &String,
         sender_utxos: &Vec<String>,
-        receivers: &HashMap<String, (u64, u64)>,
+        receivers: &HashMap<String, (u64, u64, VestingSchedule)>,
         change_utxo: &String,
+        revoke_authority: &Option<String>,
         current_block_height: u64,
     ) -> Result<(Vec<(String, u64)>, (String, u64)), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("start_drip: utxo {} is frozen", frozen_utxo));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -265,6 +298,10 @@ impl SCL01Contract {
 
         let mut total_value: u64 = 0;
         for entry in receivers.clone() {
+            if entry.1 .1 == 0 {
+                return Err("start_drip: drip duration is zero".to_string());
+            }
+
             total_value += entry.1 .0;
         }
 
@@ -296,15 +333,17 @@ impl SCL01Contract {
             let mut drippers: Vec<(String, u64)> = Vec::new();
             let mut block_drip = 0;
             for entry in receivers.clone() {
-                let drip_amt = (entry.1 .0) / (entry.1 .1);
                 let drip = Drip {
                     block_end: current_block_height + entry.1 .1 - 1,
-                    drip_amount: drip_amt.clone(),
+                    drip_amount: (entry.1 .0) / (entry.1 .1),
                     amount: entry.1 .0.clone(),
                     start_block: current_block_height.clone(),
                     last_block_dripped: current_block_height,
+                    schedule: Some(entry.1 .2.clone()),
+                    revoke_authority: revoke_authority.clone(),
                 };
 
+                let drip_amt = drip.vested(current_block_height);
                 let mut drips = Vec::new();
                 drips.push(drip);
                 d.insert(entry.0.clone(), drips);
@@ -348,13 +387,7 @@ impl SCL01Contract {
                     current_block = drip.block_end
                 }
 
-                let mut drip_amount = (current_block - drip.last_block_dripped) * drip.drip_amount;
-                if current_block == drip.block_end
-                    && ((drip.block_end - drip.start_block) + 1) * drip.drip_amount < drip.amount
-                {
-                    drip_amount +=
-                        drip.amount - (drip.block_end - drip.start_block + 1) * drip.drip_amount;
-                }
+                let drip_amount = drip.vested(current_block) - drip.vested(drip.last_block_dripped);
 
                 match self.owners.get(&utxo) {
                     Some(&e) => {
@@ -390,6 +423,155 @@ impl SCL01Contract {
         return Ok(new_owners);
     }
 
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    pub fn revoke_drip(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        authority_utxo: &String,
+        new_authority_utxo: &str,
+        drip_utxo: &String,
+        receiver_utxo: &String,
+        current_block_height: u64,
+    ) -> Result<((String, u64, bool), (String, u64)), String> {
+        let mut drips = match self.drips.clone() {
+            Some(drips) => drips,
+            None => return Err("revoke_drip: contract has no drips".to_string()),
+        };
+
+        let drips_on_utxo = match drips.get(drip_utxo) {
+            Some(drips_on_utxo) => drips_on_utxo.clone(),
+            None => return Err("revoke_drip: utxo has no drips".to_string()),
+        };
+
+        let (revoked, kept): (Vec<Drip>, Vec<Drip>) = drips_on_utxo
+            .into_iter()
+            .partition(|drip| drip.revoke_authority.as_ref() == Some(authority_utxo));
+        if revoked.is_empty() {
+            return Err("revoke_drip: utxo is not the revoke authority".to_string());
+        }
+
+        // Whatever has vested up to this block stays with the drip utxo, the rest is returned
+        let mut released = 0;
+        let mut unvested = 0;
+        for drip in revoked {
+            let current_block = std::cmp::min(current_block_height, drip.block_end);
+            released += drip.vested(current_block) - drip.vested(drip.last_block_dripped);
+            unvested += drip.remaining(current_block);
+        }
+
+        let drip_balance = match self.owners.get(drip_utxo) {
+            Some(balance) => balance + released,
+            None => released,
+        };
+
+        let receiver_balance = match self.owners.get(receiver_utxo) {
+            Some(balance) => balance + unvested,
+            None => unvested,
+        };
+
+        let drip_present = !kept.is_empty();
+        if drip_present {
+            drips.insert(drip_utxo.clone(), kept);
+        } else {
+            drips.remove(drip_utxo);
+        }
+
+        for drips_on_utxo in drips.values_mut() {
+            for drip in drips_on_utxo.iter_mut() {
+                if drip.revoke_authority.as_ref() == Some(authority_utxo) {
+                    drip.revoke_authority = Some(new_authority_utxo.to_owned());
+                }
+            }
+        }
+
+        self.owners.insert(drip_utxo.clone(), drip_balance);
+        if unvested > 0 {
+            self.owners.insert(receiver_utxo.clone(), receiver_balance);
+        }
+
+        self.supply += released + unvested;
+        self.drips = Some(drips);
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok((
+            (drip_utxo.clone(), drip_balance, drip_present),
+            (receiver_utxo.clone(), receiver_balance),
+        ));
+    }
+
+    // Releases are worked out from each drip's schedule alone, so a settlement run gives one event
+    // per utxo covering every block it caught up on however late it runs
+    pub fn drip_events(&self, block_height: u64) -> Vec<DripEvent> {
+        let drips = match &self.drips {
+            Some(drips) => drips,
+            None => return Vec::new(),
+        };
+
+        let mut events: Vec<DripEvent> = Vec::new();
+        for (utxo, drips_on_utxo) in drips {
+            let mut event = DripEvent {
+                utxo: utxo.clone(),
+                from_block: u64::MAX,
+                to_block: 0,
+                amount: 0,
+            };
+
+            for drip in drips_on_utxo {
+                let current_block = std::cmp::min(block_height, drip.block_end);
+                if current_block <= drip.last_block_dripped {
+                    continue;
+                }
+
+                let amount = drip.vested(current_block) - drip.vested(drip.last_block_dripped);
+                if amount > 0 {
+                    event.from_block = std::cmp::min(event.from_block, drip.last_block_dripped + 1);
+                    event.to_block = std::cmp::max(event.to_block, current_block);
+                    event.amount += amount;
+                }
+            }
+
+            if event.amount > 0 {
+                events.push(event);
+            }
+        }
+
+        events.sort_by(|a, b| a.utxo.cmp(&b.utxo));
+        return events;
+    }
+
+    pub fn drip_status(&self, utxo: &String) -> Result<Vec<DripStatus>, String> {
+        let drips_on_utxo = match &self.drips {
+            Some(drips) => match drips.get(utxo) {
+                Some(drips_on_utxo) => drips_on_utxo,
+                None => return Err("drip_status: utxo has no drips".to_string()),
+            },
+            None => return Err("drip_status: contract has no drips".to_string()),
+        };
+
+        return Ok(drips_on_utxo.iter().map(|drip| drip.status(utxo)).collect());
+    }
+
+    // Drips that settling up to the block height completes, taken before drip removes them
+    pub fn completed_drips(&self, block_height: u64) -> Vec<DripStatus> {
+        let drips = match &self.drips {
+            Some(drips) => drips,
+            None => return Vec::new(),
+        };
+
+        let mut completed: Vec<DripStatus> = Vec::new();
+        for (utxo, drips_on_utxo) in drips {
+            for drip in drips_on_utxo {
+                if drip.block_end <= block_height {
+                    let mut settled = drip.clone();
+                    settled.last_block_dripped = drip.block_end;
+                    completed.push(settled.status(utxo));
+                }
+            }
+        }
+
+        return completed;
+    }
+
     pub fn burn(
         &mut self,
         txid: &String,
@@ -397,7 +579,39 @@ impl SCL01Contract {
         burner_utxos: &Vec<String>,
         burn_amount: &u64,
         change_utxo: &String,
+        token_ids: &Option<Vec<String>>,
     ) -> Result<i32, String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(burner_utxos) {
+            return Err(format!("burn: utxo {} is frozen", frozen_utxo));
+        }
+
+        // Collection burns name the tokens they destroy, the rest move to the change utxo
+        let mut kept_then_burned: Vec<String> = Vec::new();
+        if self.nft_tokens.is_some() {
+            let token_ids = match token_ids {
+                Some(token_ids) => token_ids,
+                None => return Err("burn: collection burns must name the token ids".to_string()),
+            };
+
+            let held = self.held_nft_tokens(burner_utxos);
+            let mut unique = token_ids.clone();
+            unique.sort();
+            unique.dedup();
+            if unique.len() != token_ids.len() || token_ids.len() as u64 != *burn_amount {
+                return Err("burn: token ids do not match the burn amount".to_string());
+            }
+
+            if token_ids.iter().any(|token_id| !held.contains(token_id)) {
+                return Err("burn: token not held by burners".to_string());
+            }
+
+            kept_then_burned = held
+                .into_iter()
+                .filter(|id| !token_ids.contains(id))
+                .collect();
+            kept_then_burned.extend(token_ids.iter().cloned());
+        }
+
         let mut owners_amount = 0;
         for burner_utxo in burner_utxos.iter() {
             if let Some(&amount) = self.owners.get(burner_utxo) {
@@ -410,6 +624,10 @@ impl SCL01Contract {
         }
 
         if owners_amount >= *burn_amount {
+            self.assign_nft_tokens(
+                &kept_then_burned,
+                &vec![(change_utxo.to_string(), owners_amount - *burn_amount)],
+            );
             for burner_utxo in burner_utxos {
                 if let Some(&_amount) = self.owners.get(burner_utxo) {
                     self.owners.remove(burner_utxo);
@@ -431,9 +649,13 @@ impl SCL01Contract {
         txid: &String,
         payload: &String,
         sender_utxos: &Vec<String>,
-        new_listing: Listing,
+        mut new_listing: Listing,
         current_block_height: u64,
     ) -> Result<(String, u64, bool), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("list: utxo {} is frozen", frozen_utxo));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -444,6 +666,12 @@ impl SCL01Contract {
             return Err("list: owner amount is zero".to_string());
         }
 
+        if let Some(expiry_block) = new_listing.expiry_block {
+            if expiry_block <= current_block_height {
+                return Err("list: expiry block has already passed".to_string());
+            }
+        }
+
         if sender_utxos.len() == 0 {
             return Err("list: no senders".to_string());
         }
@@ -451,6 +679,39 @@ impl SCL01Contract {
         let mut new_owner = (new_listing.change_utxo.to_string(), 0, false);
 
         if new_listing.list_amt <= owners_amount {
+            if self.nft_tokens.is_some() {
+                let held = self.held_nft_tokens(sender_utxos);
+                let listed: Vec<String> = match new_listing.token_ids.clone() {
+                    Some(token_ids) => token_ids,
+                    None => held
+                        .iter()
+                        .take(new_listing.list_amt as usize)
+                        .cloned()
+                        .collect(),
+                };
+
+                let mut ordered = listed.clone();
+                ordered.extend(held.iter().filter(|id| !listed.contains(id)).cloned());
+                if listed.len() as u64 != new_listing.list_amt
+                    || ordered.len() != held.len()
+                    || listed.iter().any(|id| !held.contains(id))
+                {
+                    return Err("list: listed tokens are not held by senders".to_string());
+                }
+
+                self.assign_nft_tokens(
+                    &ordered,
+                    &vec![
+                        (new_listing.list_utxo.clone(), new_listing.list_amt),
+                        (
+                            new_listing.change_utxo.clone(),
+                            owners_amount - new_listing.list_amt,
+                        ),
+                    ],
+                );
+                new_listing.token_ids = Some(listed);
+            }
+
             let mut drips = match self.drips.clone() {
                 Some(drips) => drips,
                 None => HashMap::new(),
@@ -464,15 +725,7 @@ impl SCL01Contract {
                 if let Some(old_drips) = drips.get(&sender_utxo) {
                     let mut new_drips: Vec<Drip> = Vec::new();
                     for drip in old_drips {
-                        let new_drip = Drip {
-                            block_end: drip.block_end.clone(),
-                            drip_amount: drip.drip_amount.clone(),
-                            amount: drip.amount.clone()
-                                - (current_block_height - drip.start_block) * drip.drip_amount,
-                            start_block: current_block_height,
-                            last_block_dripped: current_block_height.clone(),
-                        };
-                        new_drips.push(new_drip.clone());
+                        new_drips.push(drip.rebase(current_block_height));
                     }
 
                     drips.insert(new_listing.change_utxo.clone(), new_drips);
@@ -533,10 +786,15 @@ impl SCL01Contract {
                     continue;
                 }
 
-                if bids[i].bid_amount / 10u64.pow(self.decimals as u32) * bids[i].bid_price
-                    >= listings_available[&bids[i].order_id].list_amt
-                        / 10u64.pow(self.decimals as u32)
-                        * listings_available[&bids[i].order_id].price
+                let partial_bid = listings_available[&bids[i].order_id]
+                    .allow_partial
+                    .unwrap_or(false)
+                    && bids[i].bid_price >= listings_available[&bids[i].order_id].price;
+                if partial_bid
+                    || bids[i].bid_amount / 10u64.pow(self.decimals as u32) * bids[i].bid_price
+                        >= listings_available[&bids[i].order_id].list_amt
+                            / 10u64.pow(self.decimals as u32)
+                            * listings_available[&bids[i].order_id].price
                 {
                     let mut listing = listings_available[&bids[i].order_id].clone();
                     listing.valid_bid_block = Some(current_block_height);
@@ -571,14 +829,16 @@ impl SCL01Contract {
 
         if bids_available.contains_key(bid_id) {
             let order_id: String = bids_available[bid_id].order_id.clone();
+            let royalty_info = self.trade_royalty_info(&bids_available[bid_id]);
             fulfillments.insert(bid_id.to_string(), order_id);
             self.fulfillments = Some(fulfillments.clone());
             let payload_data = format!(
-                "{}-ExtraInfo-{},{},{}",
+                "{}-ExtraInfo-{},{},{}{}",
                 payload,
                 bid_id,
                 bids_available[bid_id].bid_amount,
-                bids_available[bid_id].bid_price
+                bids_available[bid_id].bid_price,
+                royalty_info
             );
             self.payloads.insert(txid.to_string(), payload_data);
         }
@@ -586,12 +846,37 @@ impl SCL01Contract {
         return Ok(0);
     }
 
+    /// Royalty owed on a trade, None when the contract has no royalty
+    pub fn trade_royalty(&self, bid: &Bid) -> Option<u64> {
+        let royalty = match &self.royalty {
+            Some(royalty) => royalty,
+            None => return None,
+        };
+
+        let seller_address = match self.listings.as_ref().and_then(|l| l.get(&bid.order_id)) {
+            Some(listing) => listing.rec_addr.clone(),
+            None => return None,
+        };
+
+        let trade_value = (bid.bid_amount as u128 * bid.bid_price as u128
+            / 10u128.pow(self.decimals as u32)) as u64;
+        return Some(royalty.owed(trade_value, &seller_address));
+    }
+
+    // Royalty field appended to trade payloads so the history can show it
+    fn trade_royalty_info(&self, bid: &Bid) -> String {
+        match self.trade_royalty(bid) {
+            Some(royalty_amount) => return format!(",{}", royalty_amount),
+            None => return String::new(),
+        }
+    }
+
     pub fn fulfil(
         &mut self,
         txid: &String,
         payload: &String,
         bid_id: &String,
-    ) -> Result<(HashMap<String, u64>, Vec<String>, String), String> {
+    ) -> Result<(HashMap<String, u64>, Vec<String>, String, Option<Listing>), String> {
         let mut bids_available = match self.bids.clone() {
             Some(bids_available) => bids_available,
             None => return Err("accept_bid: no bids for contract".to_string()),
@@ -610,9 +895,11 @@ impl SCL01Contract {
         let mut new_owners = HashMap::<String, u64>::new();
         let mut listing_removed = String::new();
         let mut bids_removed = Vec::<String>::new();
+        let mut relisted: Option<Listing> = None;
         if fulfillments.clone().contains_key(bid_id) {
             let order_id = fulfillments[bid_id].clone();
             let bid = bids_available[bid_id].clone();
+            let royalty_info = self.trade_royalty_info(&bid);
             let recievers_utxo = format!("{}:0", txid);
             if self.owners.contains_key(&recievers_utxo) {
                 let new_amount = self.owners[&recievers_utxo] + bid.bid_amount;
@@ -624,7 +911,40 @@ impl SCL01Contract {
                 new_owners.insert(recievers_utxo.to_string(), bid.bid_amount);
             }
 
-            if listing[&order_id].list_amt > bid.bid_amount {
+            let listed_ids = self.held_nft_tokens(&[listing[&order_id].list_utxo.clone()]);
+            self.assign_nft_tokens(
+                &listed_ids,
+                &vec![
+                    (recievers_utxo.clone(), bid.bid_amount),
+                    (
+                        format!("{}:2", txid),
+                        listing[&order_id].list_amt.saturating_sub(bid.bid_amount),
+                    ),
+                ],
+            );
+
+            let partial_fill = listing[&order_id].allow_partial.unwrap_or(false);
+            if listing[&order_id].list_amt > bid.bid_amount && partial_fill {
+                // The remainder stays listed under the same order id at the same unit price
+                let mut remaining_listing = listing[&order_id].clone();
+                let mut fills = remaining_listing.fills.clone().unwrap_or_default();
+
+                fills.push(bid_id.to_string());
+                remaining_listing.list_utxo = format!("{}:2", txid);
+                remaining_listing.list_amt = listing[&order_id].list_amt - bid.bid_amount;
+                remaining_listing.valid_bid_block = None;
+                remaining_listing.fills = Some(fills);
+                if self.nft_tokens.is_some() {
+                    remaining_listing.token_ids = Some(
+                        listed_ids
+                            .iter()
+                            .skip(bid.bid_amount as usize)
+                            .cloned()
+                            .collect(),
+                    );
+                }
+                relisted = Some(remaining_listing);
+            } else if listing[&order_id].list_amt > bid.bid_amount {
                 let change = format!("{}:2", txid);
                 let change_amount = listing[&order_id].list_amt - bid.bid_amount;
                 if self.owners.contains_key(&change) {
@@ -639,13 +959,17 @@ impl SCL01Contract {
 
             listing_removed = listing[&order_id].list_utxo.clone();
             fulfillments.remove(bid_id);
-            listing.remove(&order_id);
+            match relisted.clone() {
+                Some(remaining_listing) => listing.insert(order_id.clone(), remaining_listing),
+                None => listing.remove(&order_id),
+            };
             let payload_data = format!(
-                "{}-ExtraInfo-{},{},{}",
+                "{}-ExtraInfo-{},{},{}{}",
                 payload,
                 bid_id,
                 bids_available[bid_id].bid_amount,
-                bids_available[bid_id].bid_price
+                bids_available[bid_id].bid_price,
+                royalty_info
             );
 
             for (key, value) in bids_available.clone().iter_mut() {
@@ -661,7 +985,7 @@ impl SCL01Contract {
             self.payloads.insert(txid.to_string(), payload_data);
         }
 
-        return Ok((new_owners, bids_removed, listing_removed));
+        return Ok((new_owners, bids_removed, listing_removed, relisted));
     }
 
     pub fn cancel_listing(
@@ -704,6 +1028,11 @@ impl SCL01Contract {
         let mut bids_removed = Vec::<String>::new();
         let recievers_utxo: String = format!("{}:0", txid);
         let mut new_owner = (recievers_utxo.to_string(), canceled_listing.list_amt);
+        let listed_ids = self.held_nft_tokens(&[canceled_listing.list_utxo.clone()]);
+        self.assign_nft_tokens(
+            &listed_ids,
+            &vec![(recievers_utxo.clone(), canceled_listing.list_amt)],
+        );
         if self.owners.contains_key(&recievers_utxo) {
             let new_amount = self.owners[&recievers_utxo] + canceled_listing.list_amt;
             self.owners.insert(recievers_utxo.to_string(), new_amount);
@@ -768,6 +1097,82 @@ impl SCL01Contract {
         return Ok(0);
     }
 
+    #[allow(clippy::type_complexity)]
+    pub fn expire_orders(
+        &mut self,
+        current_block_height: u64,
+    ) -> Result<(Vec<(String, u64)>, Vec<String>), String> {
+        let mut listings = self.listings.clone().unwrap_or_default();
+
+        let mut bids_available = self.bids.clone().unwrap_or_default();
+
+        let fulfillments = self.fulfillments.clone().unwrap_or_default();
+
+        let mut new_owners: Vec<(String, u64)> = Vec::new();
+        let mut bids_removed: Vec<String> = Vec::new();
+        for (order_id, listing) in listings.clone() {
+            let expiry_block = match listing.expiry_block {
+                Some(expiry_block) => expiry_block,
+                None => continue,
+            };
+
+            if expiry_block > current_block_height
+                || fulfillments.values().any(|value| *value == order_id)
+            {
+                continue;
+            }
+
+            let mut new_amount = listing.list_amt;
+            if self.owners.contains_key(&listing.list_utxo) {
+                new_amount += self.owners[&listing.list_utxo];
+            }
+
+            self.owners.insert(listing.list_utxo.clone(), new_amount);
+            new_owners.push((listing.list_utxo.clone(), new_amount));
+            listings.remove(&order_id);
+
+            for (key, value) in bids_available.clone() {
+                if value.order_id == order_id {
+                    bids_available.remove(&key);
+                    bids_removed.push(value.reseved_utxo.clone());
+                }
+            }
+
+            self.payloads.insert(
+                format!("{}-EXPIRED-{}", listing.list_utxo, current_block_height),
+                format!(
+                    "{{{}:EXPIRE_LISTING[{},{},{}]}}",
+                    self.contractid, order_id, listing.list_utxo, listing.list_amt
+                ),
+            );
+        }
+
+        for (key, value) in bids_available.clone() {
+            let expiry_block = match value.expiry_block {
+                Some(expiry_block) => expiry_block,
+                None => continue,
+            };
+
+            if expiry_block > current_block_height || fulfillments.contains_key(&key) {
+                continue;
+            }
+
+            bids_available.remove(&key);
+            bids_removed.push(value.reseved_utxo.clone());
+            self.payloads.insert(
+                format!("{}-EXPIRED-{}", value.reseved_utxo, current_block_height),
+                format!(
+                    "{{{}:EXPIRE_BID[{},{},{}]}}",
+                    self.contractid, value.order_id, value.reseved_utxo, value.bid_amount
+                ),
+            );
+        }
+
+        self.listings = Some(listings);
+        self.bids = Some(bids_available);
+        return Ok((new_owners, bids_removed));
+    }
+
     pub fn airdop(
         &mut self,
         txid: &String,
@@ -881,8 +1286,16 @@ impl SCL01Contract {
         min_airdrop: &u64,
         change_utxo: &String,
         single_drop: &bool,
+        claim_rules: &Option<ClaimRules>,
         current_block_height: u64,
     ) -> Result<(String, u64, bool), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!(
+                "create_dim_airdrop: utxo {} is frozen",
+                frozen_utxo
+            ));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -917,15 +1330,7 @@ impl SCL01Contract {
                 if let Some(old_drips) = drips.get(&sender_utxo) {
                     let mut new_drips: Vec<Drip> = Vec::new();
                     for drip in old_drips {
-                        let new_drip = Drip {
-                            block_end: drip.block_end.clone(),
-                            drip_amount: drip.drip_amount.clone(),
-                            amount: drip.amount.clone()
-                                - (current_block_height - drip.start_block) * drip.drip_amount,
-                            start_block: current_block_height,
-                            last_block_dripped: current_block_height.clone(),
-                        };
-                        new_drips.push(new_drip.clone());
+                        new_drips.push(drip.rebase(current_block_height));
                     }
 
                     drips.insert(change_utxo.clone(), new_drips);
@@ -960,6 +1365,7 @@ impl SCL01Contract {
             last_airdrop_split: None,
             claimers: HashMap::new(),
             single_drop: *single_drop,
+            claim_rules: claim_rules.clone(),
         };
 
         diminishing_airdrops.insert(sender_utxos[0].clone(), dim_airdrop);
@@ -971,6 +1377,7 @@ impl SCL01Contract {
         return Ok(new_owner);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn claim_dim_airdrop(
         &mut self,
         txid: &String,
@@ -979,6 +1386,7 @@ impl SCL01Contract {
         reciever_utxo: &String,
         pending: bool,
         donater_pub_address: &String,
+        claimer: &Option<ClaimerInfo>,
     ) -> Result<(String, u64, bool), String> {
         let mut diminishing_airdrops = match self.diminishing_airdrops.clone() {
             Some(diminishing_airdrops) => diminishing_airdrops,
@@ -1010,15 +1418,17 @@ impl SCL01Contract {
             airdrop_amount = dim_airdrop.pool_amount - dim_airdrop.amount_airdropped;
         }
 
-        let drips = match self.drips.clone() {
-            Some(drips) => drips,
-            None => HashMap::new(),
-        };
+        // A claim still in the mempool has no block height, its per block and age rules wait for it
+        // to confirm
+        if let (Some(claim_rules), Some(claimer)) = (&mut dim_airdrop.claim_rules, claimer) {
+            claim_rules.check(claimer, airdrop_amount)?;
 
-        let mut pending_claims = match self.pending_claims.clone() {
-            Some(pending_claims) => pending_claims,
-            None => HashMap::new(),
-        };
+            claim_rules.record(claimer, airdrop_amount);
+        }
+
+        let drips = self.drips.clone().unwrap_or_default();
+
+        let mut pending_claims = self.pending_claims.clone().unwrap_or_default();
 
         if pending {
             pending_claims.insert(reciever_utxo.to_string(), airdrop_amount);
@@ -1066,173 +1476,511 @@ impl SCL01Contract {
         return Ok(new_owner);
     }
 
-    pub fn create_dge(
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_merkle_airdrop(
         &mut self,
         txid: &String,
         payload: &String,
         sender_utxos: &Vec<String>,
-        dge: DGE,
+        pool_amount: &u64,
+        merkle_root: &str,
+        leaf_count: &u64,
         change_utxo: &String,
         current_block_height: u64,
     ) -> Result<(String, u64, bool), String> {
+        if let Some(frozen) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("create_merkle_airdrop: utxo {} is frozen", frozen));
+        }
+
+        if *pool_amount == 0 {
+            return Err("create_merkle_airdrop: pool amount is zero".to_string());
+        }
+
+        if *leaf_count == 0 || *leaf_count > MAX_MERKLE_LEAVES {
+            return Err(format!(
+                "create_merkle_airdrop: leaf count must be between 1 and {}",
+                MAX_MERKLE_LEAVES
+            ));
+        }
+
+        let root = merkle_root.to_ascii_lowercase();
+        if root.len() != 64 || hex::decode(&root).is_err() {
+            return Err(
+                "create_merkle_airdrop: merkle root must be a 32 byte hex hash".to_string(),
+            );
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
                 owners_amount += self.owners[&sender_utxo];
             }
         }
+
         if owners_amount == 0 {
-            return Err("create_dge: owner amount is zero".to_string());
+            return Err("create_merkle_airdrop: owner amount is zero".to_string());
         }
 
-        if dge.pool_amount > owners_amount {
-            return Err("create_dge: pool amount is more than the owned amount".to_string());
+        if pool_amount > &owners_amount {
+            return Err(
+                "create_merkle_airdrop: pool amount is more than the owned amount".to_string(),
+            );
         }
 
-        let mut drips = match self.drips.clone() {
-            Some(drips) => drips,
-            None => HashMap::new(),
-        };
+        let mut merkle_airdrops = self.merkle_airdrops.clone().unwrap_or_default();
 
-        let mut new_owner = (change_utxo.to_string(), 0, false);
-        let mut dges = match self.dges.clone() {
-            Some(dges) => dges,
-            None => HashMap::new(),
-        };
+        if merkle_airdrops.contains_key(&sender_utxos[0]) {
+            return Err("create_merkle_airdrop: airdrop id already exists".to_string());
+        }
+
+        let mut drips = self.drips.clone().unwrap_or_default();
 
+        let mut new_owner = (change_utxo.to_string(), 0, false);
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo.clone()) {
                 self.owners.remove(&sender_utxo);
                 if let Some(old_drips) = drips.get(&sender_utxo) {
                     let mut new_drips: Vec<Drip> = Vec::new();
                     for drip in old_drips {
-                        let new_drip = Drip {
-                            block_end: drip.block_end.clone(),
-                            drip_amount: drip.drip_amount.clone(),
-                            amount: drip.amount.clone()
-                                - (current_block_height - drip.start_block) * drip.drip_amount,
-                            start_block: current_block_height,
-                            last_block_dripped: current_block_height.clone(),
-                        };
-                        new_drips.push(new_drip.clone());
+                        new_drips.push(drip.rebase(current_block_height));
                     }
 
                     drips.insert(change_utxo.clone(), new_drips);
-                    // Remove the old drip from the vector
                     drips.remove(&sender_utxo);
                     new_owner.2 = true;
                 }
             }
         }
 
-        let change_amt: u64 = owners_amount - dge.pool_amount;
+        let change_amt: u64 = owners_amount - pool_amount;
         if change_amt > 0 {
             if self.owners.contains_key(change_utxo) {
                 let new_amount = self.owners[change_utxo] + change_amt;
-                new_owner.1 = new_amount.clone();
+                new_owner.1 = new_amount;
                 self.owners.insert(change_utxo.to_string(), new_amount);
             } else {
-                new_owner.1 = change_amt.clone();
+                new_owner.1 = change_amt;
                 self.owners.insert(change_utxo.to_string(), change_amt);
             }
         }
 
-        dges.insert(sender_utxos[0].clone(), dge.clone());
+        let merkle_airdrop = MerkleAirdrop {
+            pool_amount: *pool_amount,
+            merkle_root: root,
+            leaf_count: *leaf_count,
+            amount_airdropped: 0,
+            claimed: "00".repeat(leaf_count.div_ceil(8) as usize),
+        };
+
+        merkle_airdrops.insert(sender_utxos[0].clone(), merkle_airdrop);
 
         self.payloads.insert(txid.to_string(), payload.to_string());
-        self.supply -= dge.pool_amount;
-        self.dges = Some(dges);
+        self.merkle_airdrops = Some(merkle_airdrops);
+        self.supply -= pool_amount;
+        self.drips = Some(drips);
         return Ok(new_owner);
     }
 
-    pub fn claim_dge(
+    #[allow(clippy::too_many_arguments)]
+    pub fn claim_merkle_airdrop(
         &mut self,
         txid: &String,
         payload: &String,
         claim_id: &String,
+        index: &u64,
+        amount: &u64,
+        proof: &Vec<String>,
+        claimer_address: &String,
         reciever_utxo: &String,
-        donater: &String,
-        donation: u64,
-        current_block_height: u64,
-    ) -> Result<(String, u64), String> {
-        let mut dges = match self.dges.clone() {
-            Some(dges) => dges,
-            None => return Err("claim_dge: contract has reached no claimable dges".to_string()),
+    ) -> Result<(String, u64, bool), String> {
+        let mut merkle_airdrops = match self.merkle_airdrops.clone() {
+            Some(merkle_airdrops) => merkle_airdrops,
+            None => return Err("claim_merkle_airdrop: contract has no merkle airdrops".to_string()),
         };
 
-        let mut dge: DGE = match dges.get(claim_id) {
-            Some(dge) => dge.clone(),
-            None => return Err("claim_dge: dge claim id not found".to_string()),
+        let mut merkle_airdrop: MerkleAirdrop = match merkle_airdrops.get(claim_id) {
+            Some(merkle_airdrop) => merkle_airdrop.clone(),
+            None => {
+                return Err("claim_merkle_airdrop: merkle airdrop claim id not found".to_string())
+            }
         };
 
-        if donation as u128
-            > (dge.max_drop as u128 * dge.sats_rate as u128)
-                / 10u64.pow(self.decimals as u32) as u128
-        {
-            return Err("claim_dge: donation over maximum limit".to_string());
+        if *index >= merkle_airdrop.leaf_count {
+            return Err("claim_merkle_airdrop: leaf index is out of range".to_string());
         }
 
-        let mut new_owner = (reciever_utxo.to_string(), 0);
-        let mut token_amount = donation * 10u64.pow(self.decimals as u32) / (dge.sats_rate);
-        if token_amount == 0 {
-            return Err("claim_dge: token allocation is zero".to_string());
+        match merkle_airdrop.is_claimed(*index) {
+            Ok(false) => {}
+            Ok(true) => return Err("claim_merkle_airdrop: leaf already claimed".to_string()),
+            Err(err) => return Err(format!("claim_merkle_airdrop: {}", err)),
         }
 
-        if token_amount + dge.current_amount_dropped >= dge.pool_amount {
-            token_amount = dge.pool_amount - dge.current_amount_dropped;
+        let amount_airdropped = match merkle_airdrop.amount_airdropped.checked_add(*amount) {
+            Some(amount_airdropped) => amount_airdropped,
+            None => {
+                return Err("claim_merkle_airdrop: amount exceeds the remaining pool".to_string())
+            }
+        };
+
+        if *amount == 0 || amount_airdropped > merkle_airdrop.pool_amount {
+            return Err("claim_merkle_airdrop: amount exceeds the remaining pool".to_string());
         }
 
-        let mut drips = match self.drips.clone() {
-            Some(drips) => drips,
-            None => HashMap::new(),
-        };
+        match merkle_airdrop.verify_proof(index, claimer_address, amount, proof) {
+            Ok(true) => {}
+            Ok(false) => return Err("claim_merkle_airdrop: invalid merkle proof".to_string()),
+            Err(err) => return Err(format!("claim_merkle_airdrop: {}", err)),
+        }
 
-        let drip_amount = token_amount / dge.drip_duration;
-        let drip = Drip {
-            block_end: current_block_height + dge.drip_duration - 1,
-            drip_amount: drip_amount.clone(),
-            amount: token_amount.clone(),
-            start_block: current_block_height.clone(),
-            last_block_dripped: current_block_height.clone(),
-        };
+        if let Err(err) = merkle_airdrop.set_claimed(*index) {
+            return Err(format!("claim_merkle_airdrop: {}", err));
+        }
 
-        let mut new_drips = Vec::new();
-        new_drips.push(drip);
-        drips.insert(reciever_utxo.clone(), new_drips);
-        match self.owners.get(reciever_utxo) {
-            Some(&existing_amount) => {
-                self.owners
-                    .insert(reciever_utxo.to_string(), &existing_amount + drip_amount);
-                new_owner.1 = &existing_amount + drip_amount;
-            }
-            None => {
-                self.owners.insert(reciever_utxo.to_string(), drip_amount);
-                new_owner.1 = drip_amount;
+        let mut new_owner = (reciever_utxo.to_string(), 0, false);
+        if self.owners.contains_key(reciever_utxo) {
+            let new_amount = self.owners[reciever_utxo] + amount;
+            new_owner.1 = new_amount;
+            self.owners.insert(reciever_utxo.to_string(), new_amount);
+        } else {
+            new_owner.1 = *amount;
+            self.owners.insert(reciever_utxo.to_string(), *amount);
+        }
+
+        if let Some(drips) = &self.drips {
+            if drips.contains_key(reciever_utxo) {
+                new_owner.2 = true;
             }
         }
 
-        self.supply += drip_amount;
-        self.drips = Some(drips);
-        dge.current_amount_dropped += token_amount;
-        if dge.single_drop {
-            dge.donaters.insert(donater.to_string(), donation);
+        merkle_airdrop.amount_airdropped = amount_airdropped;
+        if merkle_airdrop.amount_airdropped == merkle_airdrop.pool_amount {
+            merkle_airdrops.remove(claim_id);
+        } else {
+            merkle_airdrops.insert(claim_id.to_string(), merkle_airdrop);
         }
 
-        dges.insert(claim_id.to_string(), dge);
-        self.dges = Some(dges);
+        self.supply += amount;
+        self.merkle_airdrops = Some(merkle_airdrops);
         self.payloads.insert(txid.to_string(), payload.to_string());
         return Ok(new_owner);
     }
 
-    pub fn provide_liquidity(
+    pub fn create_dge(
         &mut self,
         txid: &String,
         payload: &String,
         sender_utxos: &Vec<String>,
-        liquidation_amount: u64,
-        block_height: u64,
-        is_contract_1: bool,
+        dge: DGE,
+        change_utxo: &String,
+        current_block_height: u64,
     ) -> Result<(String, u64, bool), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("create_dge: utxo {} is frozen", frozen_utxo));
+        }
+
+        let mut owners_amount: u64 = 0;
+        for sender_utxo in sender_utxos.clone() {
+            if self.owners.contains_key(&sender_utxo) {
+                owners_amount += self.owners[&sender_utxo];
+            }
+        }
+        if owners_amount == 0 {
+            return Err("create_dge: owner amount is zero".to_string());
+        }
+
+        if dge.pool_amount > owners_amount {
+            return Err("create_dge: pool amount is more than the owned amount".to_string());
+        }
+
+        if let (Some(start_block), Some(end_block)) = (dge.start_block, dge.end_block) {
+            if start_block > end_block {
+                return Err("create_dge: start block is after the end block".to_string());
+            }
+        }
+
+        if dge
+            .end_block
+            .is_some_and(|end_block| end_block < current_block_height)
+        {
+            return Err("create_dge: end block has already passed".to_string());
+        }
+
+        if dge.soft_cap.is_some() && dge.end_block.is_none() {
+            return Err("create_dge: a soft cap needs an end block".to_string());
+        }
+
+        if let (Some(soft_cap), Some(hard_cap)) = (dge.soft_cap, dge.hard_cap) {
+            if soft_cap > hard_cap {
+                return Err("create_dge: soft cap is above the hard cap".to_string());
+            }
+        }
+
+        let mut drips = self.drips.clone().unwrap_or_default();
+
+        let mut new_owner = (change_utxo.to_string(), 0, false);
+        let mut dges = self.dges.clone().unwrap_or_default();
+
+        for sender_utxo in sender_utxos.clone() {
+            if self.owners.contains_key(&sender_utxo.clone()) {
+                self.owners.remove(&sender_utxo);
+                if let Some(old_drips) = drips.get(&sender_utxo) {
+                    let mut new_drips: Vec<Drip> = Vec::new();
+                    for drip in old_drips {
+                        new_drips.push(drip.rebase(current_block_height));
+                    }
+
+                    drips.insert(change_utxo.clone(), new_drips);
+                    // Remove the old drip from the vector
+                    drips.remove(&sender_utxo);
+                    new_owner.2 = true;
+                }
+            }
+        }
+
+        let change_amt: u64 = owners_amount - dge.pool_amount;
+        if change_amt > 0 {
+            if self.owners.contains_key(change_utxo) {
+                let new_amount = self.owners[change_utxo] + change_amt;
+                new_owner.1 = new_amount;
+                self.owners.insert(change_utxo.to_string(), new_amount);
+            } else {
+                new_owner.1 = change_amt;
+                self.owners.insert(change_utxo.to_string(), change_amt);
+            }
+        }
+
+        dges.insert(sender_utxos[0].clone(), dge.clone());
+
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        self.supply -= dge.pool_amount;
+        self.dges = Some(dges);
+        return Ok(new_owner);
+    }
+
+    pub fn claim_dge(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        claim_id: &String,
+        reciever_utxo: &String,
+        donater: &String,
+        donation: u64,
+        claimer: &Option<ClaimerInfo>,
+        donation_block_height: u64,
+        current_block_height: u64,
+    ) -> Result<(String, u64, u64), String> {
+        let mut dges = match self.dges.clone() {
+            Some(dges) => dges,
+            None => return Err("claim_dge: contract has reached no claimable dges".to_string()),
+        };
+
+        let mut dge: DGE = match dges.get(claim_id) {
+            Some(dge) => dge.clone(),
+            None => return Err("claim_dge: dge claim id not found".to_string()),
+        };
+
+        let existing_amount = match self.owners.get(reciever_utxo) {
+            Some(existing_amount) => *existing_amount,
+            None => 0,
+        };
+
+        // Donations the dge cannot honour at all are kept as refunds for the creator to return
+        let sats_raised = dge.sats_raised.unwrap_or(0);
+        let (honoured, token_amount, partial_reason) = dge.allocation(donation, self.decimals);
+        let mut refund_reason: Option<String> = None;
+        if dge.single_drop && dge.donaters.contains_key(donater) {
+            refund_reason = Some("donater_already_exists".to_string());
+        } else if dge
+            .start_block
+            .is_some_and(|start_block| donation_block_height < start_block)
+        {
+            refund_reason = Some("before_start".to_string());
+        } else if dge
+            .end_block
+            .is_some_and(|end_block| donation_block_height > end_block)
+        {
+            refund_reason = Some("after_end".to_string());
+        } else if dge.hard_cap.is_some_and(|hard_cap| sats_raised >= hard_cap) {
+            refund_reason = Some("hard_cap_reached".to_string());
+        } else if dge.current_amount_dropped >= dge.pool_amount {
+            refund_reason = Some("pool_exhausted".to_string());
+        } else if token_amount == 0 {
+            refund_reason = Some("token_allocation_zero".to_string());
+        } else if let (Some(claim_rules), Some(claimer)) = (&dge.claim_rules, claimer) {
+            // Per block and age rules of a claim still in the mempool are checked once it confirms
+            if let Err(err) = claim_rules.check(claimer, token_amount) {
+                refund_reason = Some(err);
+            }
+        }
+
+        if let Some(reason) = refund_reason {
+            dge.add_refund(txid, donater, donation, donation_block_height, &reason);
+            dges.insert(claim_id.to_string(), dge);
+            self.dges = Some(dges);
+            self.payloads.insert(txid.to_string(), payload.to_string());
+            return Ok((reciever_utxo.to_string(), existing_amount, 0));
+        }
+
+        if let (Some(claim_rules), Some(claimer)) = (&mut dge.claim_rules, claimer) {
+            claim_rules.record(claimer, token_amount);
+        }
+
+        if honoured < donation {
+            dge.add_refund(
+                txid,
+                donater,
+                donation - honoured,
+                donation_block_height,
+                partial_reason,
+            );
+        }
+
+        dge.current_amount_dropped += token_amount;
+        dge.sats_raised = Some(sats_raised + honoured);
+        if dge.single_drop {
+            dge.donaters.insert(donater.to_string(), donation);
+        }
+
+        // With a soft cap the tokens are held until the dge settles after its end block
+        if dge.soft_cap.is_some() {
+            let mut held = dge.held.clone().unwrap_or_default();
+            held.push(DGEAllocation {
+                txid: txid.to_string(),
+                address: donater.to_string(),
+                reciever_utxo: reciever_utxo.to_string(),
+                sats: honoured,
+                token_amount,
+                block_height: donation_block_height,
+            });
+            dge.held = Some(held);
+            dges.insert(claim_id.to_string(), dge);
+            self.dges = Some(dges);
+            self.payloads.insert(txid.to_string(), payload.to_string());
+            return Ok((reciever_utxo.to_string(), existing_amount, 0));
+        }
+
+        let mut drips = self.drips.clone().unwrap_or_default();
+
+        let drip_amount = token_amount / dge.drip_duration;
+        let drip = Drip {
+            block_end: current_block_height + dge.drip_duration - 1,
+            drip_amount,
+            amount: token_amount,
+            start_block: current_block_height,
+            last_block_dripped: current_block_height,
+            schedule: None,
+            revoke_authority: None,
+        };
+
+        let mut new_drips = Vec::new();
+        new_drips.push(drip);
+        drips.insert(reciever_utxo.clone(), new_drips);
+        self.owners
+            .insert(reciever_utxo.to_string(), existing_amount + drip_amount);
+
+        self.supply += drip_amount;
+        self.drips = Some(drips);
+        dges.insert(claim_id.to_string(), dge);
+        self.dges = Some(dges);
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok((
+            reciever_utxo.to_string(),
+            existing_amount + drip_amount,
+            token_amount,
+        ));
+    }
+
+    // Dges with a soft cap settle in the first block after their end block, the held tokens
+    // start dripping from that block if the soft cap was met and are refunded otherwise
+    pub fn settle_dges(&mut self, block_height: u64) -> Option<Vec<(String, u64, bool)>> {
+        let mut dges = self.dges.clone()?;
+
+        let mut drips = self.drips.clone().unwrap_or_default();
+
+        let mut dge_ids: Vec<String> = dges.keys().cloned().collect();
+        dge_ids.sort();
+        let mut new_owners: Vec<(String, u64, bool)> = Vec::new();
+        let mut settled = false;
+        for dge_id in dge_ids {
+            let dge = match dges.get_mut(&dge_id) {
+                Some(dge) => dge,
+                None => continue,
+            };
+
+            let (soft_cap, end_block) = match (dge.soft_cap, dge.end_block) {
+                (Some(soft_cap), Some(end_block)) => (soft_cap, end_block),
+                _ => continue,
+            };
+
+            if dge.soft_cap_met.is_some() || block_height <= end_block {
+                continue;
+            }
+
+            settled = true;
+            let soft_cap_met = dge.sats_raised.unwrap_or(0) >= soft_cap;
+            let start_block = end_block + 1;
+            for allocation in dge.held.clone().unwrap_or_default() {
+                if !soft_cap_met {
+                    dge.add_refund(
+                        &allocation.txid,
+                        &allocation.address,
+                        allocation.sats,
+                        allocation.block_height,
+                        "soft_cap_not_met",
+                    );
+                    dge.current_amount_dropped -= allocation.token_amount;
+                    continue;
+                }
+
+                let drip_amount = allocation.token_amount / dge.drip_duration;
+                drips
+                    .entry(allocation.reciever_utxo.clone())
+                    .or_default()
+                    .push(Drip {
+                        block_end: start_block + dge.drip_duration - 1,
+                        drip_amount,
+                        amount: allocation.token_amount,
+                        start_block,
+                        last_block_dripped: start_block,
+                        schedule: None,
+                        revoke_authority: None,
+                    });
+
+                let balance =
+                    self.owners.get(&allocation.reciever_utxo).unwrap_or(&0) + drip_amount;
+                self.owners
+                    .insert(allocation.reciever_utxo.clone(), balance);
+                self.supply += drip_amount;
+                new_owners.retain(|(utxo, _, _)| *utxo != allocation.reciever_utxo);
+                new_owners.push((allocation.reciever_utxo.clone(), balance, true));
+            }
+
+            dge.held = None;
+            dge.soft_cap_met = Some(soft_cap_met);
+        }
+
+        if !settled {
+            return None;
+        }
+
+        self.dges = Some(dges);
+        self.drips = Some(drips);
+        return Some(new_owners);
+    }
+
+    pub fn provide_liquidity(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        sender_utxos: &Vec<String>,
+        liquidation_amount: u64,
+        block_height: u64,
+        is_contract_1: bool,
+    ) -> Result<(String, u64, bool), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("provide_liquidity: utxo {} is frozen", frozen_utxo));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -1290,6 +2038,10 @@ impl SCL01Contract {
         swap_amount: u64,
         block_height: u64,
     ) -> Result<(String, u64, bool), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(sender_utxos) {
+            return Err(format!("swap_claim: utxo {} is frozen", frozen_utxo));
+        }
+
         let mut owners_amount: u64 = 0;
         for sender_utxo in sender_utxos.clone() {
             if self.owners.contains_key(&sender_utxo) {
@@ -1401,47 +2153,63 @@ impl SCL01Contract {
     }
 
     pub fn provide_liquidity_lp(
-    &mut self,
-    txid: &str,
-    payload: &str,
-    provided_amount: u64,
-) -> Result<(String, u64), String> {
-    let mut liquidity_pool = match self.liquidity_pool.clone() {
-        Some(pool) => pool,
-        None => return Err("provide_liquidity_lp: no liquidity pools".to_string()),
-    };
-
-    // Compute paired amount from ratio
-    let ratio = liquidity_pool.liquidity_ratio;
-    let paired_amount = (provided_amount as f64 * ratio) as u64;
-
-    // Validate ratio using float division
-    let actual_ratio = provided_amount as f64 / paired_amount as f64;
-    if (actual_ratio - 1.0 / ratio).abs() > 0.0001 {
-        return Err(format!(
-            "provide_liquidity_lp: incorrect ratio. Expected: {:.4}, Got: {:.4}",
-            1.0 / ratio,
-            actual_ratio
-        ));
-    }
+        &mut self,
+        txid: &str,
+        payload: &str,
+        provided_amount: u64,
+        block_height: u64,
+    ) -> Result<(String, u64), String> {
+        let mut liquidity_pool = match self.liquidity_pool.clone() {
+            Some(pool) => pool,
+            None => return Err("provide_liquidity_lp: no liquidity pools".to_string()),
+        };
 
-    // Update pools
-    liquidity_pool.pool_1 += provided_amount;
-    liquidity_pool.pool_2 += paired_amount;
-    self.supply += provided_amount + paired_amount;
+        // Compute paired amount from the fixed point ratio
+        let paired_amount = match liquidity_pool.paired_amount(provided_amount) {
+            Ok(paired_amount) => paired_amount,
+            Err(err) => return Err(format!("provide_liquidity_lp: {}", err)),
+        };
 
-    // Track ownership and state
-    let lp_utxo = format!("{}:0", txid);
-    self.owners
-        .insert(lp_utxo.clone(), provided_amount + paired_amount);
-    liquidity_pool.k = liquidity_pool.pool_1 as u128 * liquidity_pool.pool_2 as u128;
-    self.liquidity_pool = Some(liquidity_pool);
-    self.payloads.insert(txid.to_string(), payload.to_string());
+        if provided_amount == 0 || paired_amount == 0 {
+            return Err("provide_liquidity_lp: provided amount is too small".to_string());
+        }
 
-    Ok((lp_utxo, provided_amount + paired_amount))
-}
+        let lp_tokens =
+            liquidity_pool.deposit_lp_tokens(provided_amount, paired_amount, self.supply);
+        if lp_tokens == 0 {
+            return Err("provide_liquidity_lp: provided amount is too small".to_string());
+        }
 
+        // Update pools
+        liquidity_pool.pool_1 += provided_amount;
+        liquidity_pool.pool_2 += paired_amount;
+        self.supply += lp_tokens;
+
+        // Track ownership and state
+        let lp_utxo = format!("{}:0", txid);
+        self.owners.insert(lp_utxo.clone(), lp_tokens);
+        liquidity_pool.k = liquidity_pool.pool_1 as u128 * liquidity_pool.pool_2 as u128;
+        liquidity_pool.migrate_fixed_point();
+        let mut providers = liquidity_pool.providers.clone().unwrap_or_default();
+
+        providers.insert(
+            lp_utxo.clone(),
+            LiquidityProvider {
+                provided_block_height: block_height as u32,
+                lp_tokens,
+                token_1_deposited: provided_amount,
+                token_2_deposited: paired_amount,
+                entry_growth: liquidity_pool.growth_index(self.supply),
+            },
+        );
+        liquidity_pool.providers = Some(providers);
+        self.liquidity_pool = Some(liquidity_pool);
+        self.payloads.insert(txid.to_string(), payload.to_string());
+
+        return Ok((lp_utxo, lp_tokens));
+    }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn swap_lp(
         &mut self,
         txid: &str,
@@ -1449,61 +2217,156 @@ impl SCL01Contract {
         sender_contract_id: String,
         provided_amount: u64,
         quoted: u64,
-        slipage_tolerance: f32,
+        slipage_tolerance_bps: u64,
+        swap_block: Option<(u64, i64)>,
     ) -> Result<u64, String> {
-        let sender_pool_amount;
-        let reciever_pool_amount;
         let mut liquidity_pool = match self.liquidity_pool.clone() {
             Some(liquidity_pool) => liquidity_pool,
             None => return Err("swap_lp: no liquidity pools".to_string()),
         };
 
-        if sender_contract_id == liquidity_pool.contract_id_1 {
-            sender_pool_amount = liquidity_pool.pool_1;
-            reciever_pool_amount = liquidity_pool.pool_2;
-        } else if sender_contract_id == liquidity_pool.contract_id_2 {
-            sender_pool_amount = liquidity_pool.pool_2;
-            reciever_pool_amount = liquidity_pool.pool_1;
-        } else {
-            return Err("swap_lp: contract_id not associated with this liquidity pool".to_string());
-        }
-
-        let fee_diff = provided_amount as f64 * (1.0 - liquidity_pool.fee) as f64;
-        let mut swap_amount = ((reciever_pool_amount as f64)
-            - (liquidity_pool.k as f64 / (sender_pool_amount as f64 + fee_diff)))
-            as u64;
+        let quote = match liquidity_pool.quote_swap(
+            &sender_contract_id,
+            provided_amount,
+            slipage_tolerance_bps,
+        ) {
+            Ok(quote) => quote,
+            Err(err) => return Err(format!("swap_lp: {}", err)),
+        };
 
-        if swap_amount > reciever_pool_amount {
-            swap_amount = reciever_pool_amount;
+        let swap_amount = apply_slippage_tolerance(quote.amount_out, quoted, slipage_tolerance_bps);
+        if swap_amount == 0 {
+            println!("swap_lp: swap amount is below tolerence");
+            return Ok(0);
         }
 
-        if swap_amount.abs_diff(quoted) as f32 / quoted as f32 > slipage_tolerance {
-            if swap_amount >= quoted {
-                println!("swap_lp: swap amount is above tolerence: {}", swap_amount);
-                swap_amount = (quoted as f32 * (1.0 + slipage_tolerance)) as u64;
-            } else {
-                println!("swap_lp: swap amount is below tolerence");
-                return Ok(0);
-            }
-        }
+        liquidity_pool.migrate_fixed_point();
+        self.liquidity_pool = Some(liquidity_pool);
+        return self.settle_swap(
+            txid,
+            payload,
+            &sender_contract_id,
+            provided_amount,
+            swap_amount,
+            swap_block,
+        );
+    }
 
+    /// Move `provided_amount` into the pool and `swap_amount` out of it. The amounts must
+    /// come from `LiquidityPool::quote_swap` so the constant product is never reduced.
+    pub fn settle_swap(
+        &mut self,
+        txid: &str,
+        payload: &str,
+        sender_contract_id: &str,
+        provided_amount: u64,
+        swap_amount: u64,
+        swap_block: Option<(u64, i64)>,
+    ) -> Result<u64, String> {
+        let mut liquidity_pool = match self.liquidity_pool.clone() {
+            Some(liquidity_pool) => liquidity_pool,
+            None => return Err("settle_swap: no liquidity pools".to_string()),
+        };
+
+        let fee = liquidity_pool.swap_fee(provided_amount);
+        let protocol_fee = liquidity_pool.protocol_fee_amount(fee);
         if sender_contract_id == liquidity_pool.contract_id_1 {
-            liquidity_pool.pool_1 += provided_amount;
+            if swap_amount > liquidity_pool.pool_2 {
+                return Err("settle_swap: swap amount exceeds pool".to_string());
+            }
+            liquidity_pool.pool_1 += provided_amount - protocol_fee;
             liquidity_pool.pool_2 -= swap_amount;
-        } else {
-            liquidity_pool.pool_2 += provided_amount;
+            if let Some(fees) = liquidity_pool.protocol_fee.as_mut() {
+                fees.accrued_1 += protocol_fee;
+            }
+        } else if sender_contract_id == liquidity_pool.contract_id_2 {
+            if swap_amount > liquidity_pool.pool_1 {
+                return Err("settle_swap: swap amount exceeds pool".to_string());
+            }
+
+            liquidity_pool.pool_2 += provided_amount - protocol_fee;
             liquidity_pool.pool_1 -= swap_amount;
+            if let Some(fees) = liquidity_pool.protocol_fee.as_mut() {
+                fees.accrued_2 += protocol_fee;
+            }
+        } else {
+            return Err(
+                "settle_swap: contract_id not associated with this liquidity pool".to_string(),
+            );
         }
 
         liquidity_pool.k = liquidity_pool.pool_2 as u128 * liquidity_pool.pool_1 as u128;
+        liquidity_pool.migrate_fixed_point();
         liquidity_pool
             .swaps
             .insert(txid.to_string(), (provided_amount, swap_amount));
+        // Block data is only kept once the swap is confirmed so every host stores the same
+        let mut swap_records = liquidity_pool.swap_records.clone().unwrap_or_default();
+
+        swap_records.insert(
+            txid.to_string(),
+            SwapRecord {
+                sender_contract_id: sender_contract_id.to_string(),
+                provided_amount,
+                swap_amount,
+                fee_bps: liquidity_pool.fee_bps(),
+                fee,
+                block_height: swap_block.map(|(block_height, _)| block_height),
+                block_time: swap_block.map(|(_, block_time)| block_time),
+            },
+        );
+        liquidity_pool.swap_records = Some(swap_records);
         self.liquidity_pool = Some(liquidity_pool);
         self.payloads.insert(txid.to_string(), payload.to_string());
         return Ok(swap_amount);
     }
 
+    pub fn claim_protocol_fees(
+        &mut self,
+        txid: &str,
+        payload: &str,
+        input_utxos: &[String],
+        input_address: &Option<String>,
+    ) -> Result<(u64, u64), String> {
+        let mut liquidity_pool = match self.liquidity_pool.clone() {
+            Some(liquidity_pool) => liquidity_pool,
+            None => return Err("claim_protocol_fees: no liquidity pools".to_string()),
+        };
+
+        let mut protocol_fee = match liquidity_pool.protocol_fee.clone() {
+            Some(protocol_fee) => protocol_fee,
+            None => return Err("claim_protocol_fees: pool has no protocol fee".to_string()),
+        };
+
+        let spends_recipient = input_utxos.contains(&protocol_fee.recipient);
+        let signed_by_recipient = input_address
+            .as_ref()
+            .is_some_and(|address| *address == protocol_fee.recipient);
+        if !spends_recipient && !signed_by_recipient {
+            return Err("claim_protocol_fees: claim not authorised by recipient".to_string());
+        }
+
+        if protocol_fee.accrued_1 == 0 && protocol_fee.accrued_2 == 0 {
+            return Err("claim_protocol_fees: no protocol fees accrued".to_string());
+        }
+
+        let claimed = (protocol_fee.accrued_1, protocol_fee.accrued_2);
+        protocol_fee.claimed_1 += protocol_fee.accrued_1;
+        protocol_fee.claimed_2 += protocol_fee.accrued_2;
+        protocol_fee.accrued_1 = 0;
+        protocol_fee.accrued_2 = 0;
+
+        // A spent recipient utxo moves to the claim output with the fees
+        if spends_recipient {
+            protocol_fee.recipient = format!("{}:0", txid);
+        }
+
+        liquidity_pool.protocol_fee = Some(protocol_fee);
+        self.liquidity_pool = Some(liquidity_pool);
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok(claimed);
+    }
+
     pub fn liquidate_postion_lp(
         &mut self,
         txid: &str,
@@ -1511,7 +2374,15 @@ impl SCL01Contract {
         lp_utxos: &Vec<String>,
         claim_amount: u64,
         block_height: u64,
+        liquidation_block: Option<(u64, i64)>,
     ) -> Result<(u64, u64, String, u64, bool), String> {
+        if let Some(frozen_utxo) = self.first_frozen_utxo(lp_utxos) {
+            return Err(format!(
+                "liquidate_postion_lp: utxo {} is frozen",
+                frozen_utxo
+            ));
+        }
+
         let mut liquidity_pool = match self.liquidity_pool.clone() {
             Some(liquidity_pool) => liquidity_pool,
             None => return Err("liquidate_postion_lp: no liquidity pools".to_string()),
@@ -1529,16 +2400,29 @@ impl SCL01Contract {
         }
 
         if total_tokens >= claim_amount {
+            let change_utxo: String = format!("{}:0", txid);
+            let mut position_receivers: Vec<(String, u64)> = Vec::new();
+            if total_tokens > claim_amount {
+                position_receivers.push((change_utxo.clone(), total_tokens - claim_amount));
+            }
+
+            self.transfer_lp_positions(lp_utxos, &position_receivers);
+            liquidity_pool.providers = match &self.liquidity_pool {
+                Some(pool) => pool.providers.clone(),
+                None => None,
+            };
+
             for utxo in lp_utxos {
                 self.owners.remove(utxo);
             }
 
-            let change_utxo: String = format!("{}:0", txid);
             let change_drip_present = self.transfer_drips(lp_utxos, block_height, &change_utxo);
             let change = total_tokens - claim_amount;
-            let lp_token_ratio = claim_amount as f64 / self.supply as f64;
-            let token_1 = (liquidity_pool.pool_1 as f64 * lp_token_ratio) as u64;
-            let token_2 = (liquidity_pool.pool_2 as f64 * lp_token_ratio) as u64;
+            let (token_1, token_2) =
+                match liquidity_pool.liquidation_amounts(claim_amount, self.supply) {
+                    Ok(amounts) => amounts,
+                    Err(err) => return Err(format!("liquidate_postion_lp: {}", err)),
+                };
             liquidity_pool.pool_1 -= token_1;
             liquidity_pool.pool_2 -= token_2;
 
@@ -1547,9 +2431,26 @@ impl SCL01Contract {
             }
 
             liquidity_pool.k = liquidity_pool.pool_1 as u128 * liquidity_pool.pool_2 as u128;
+            liquidity_pool.migrate_fixed_point();
             liquidity_pool
                 .liquidations
                 .insert(txid.to_string(), (token_1, token_2));
+            let mut liquidation_records = liquidity_pool
+                .liquidation_records
+                .clone()
+                .unwrap_or_default();
+
+            liquidation_records.insert(
+                txid.to_string(),
+                LiquidationRecord {
+                    token_1,
+                    token_2,
+                    block_height: liquidation_block.map(|(block_height, _)| block_height),
+                    block_time: liquidation_block.map(|(_, block_time)| block_time),
+                },
+            );
+            liquidity_pool.liquidation_records = Some(liquidation_records);
+
             self.liquidity_pool = Some(liquidity_pool);
             self.supply -= claim_amount;
             self.payloads.insert(txid.to_string(), payload.to_string());
@@ -1561,33 +2462,404 @@ impl SCL01Contract {
         }
     }
 
-    fn transfer_drips(
-        &mut self,
-        utxos: &Vec<String>,
-        block_height: u64,
-        change_utxo: &String,
-    ) -> bool {
-        let mut drips = match self.drips.clone() {
-            Some(drips) => drips,
-            None => HashMap::new(),
+    // A position only follows its LP tokens when every sender utxo is tracked, otherwise the
+    // deposits of the receivers would be unknown and the receivers are left untracked.
+    fn transfer_lp_positions(&mut self, utxos: &Vec<String>, receivers: &Vec<(String, u64)>) {
+        let mut liquidity_pool = match self.liquidity_pool.clone() {
+            Some(liquidity_pool) => liquidity_pool,
+            None => return,
         };
 
-        let mut change_drip_present = false;
-        let mut new_drips: Vec<Drip> = Vec::new();
-        for utxo in utxos.clone() {
+        let mut providers = match liquidity_pool.providers.clone() {
+            Some(providers) => providers,
+            None => return,
+        };
+
+        let mut owners_amount: u64 = 0;
+        let mut all_tracked = true;
+        let mut moved = LiquidityProvider::default();
+        for utxo in utxos {
+            let owned = match self.owners.get(utxo) {
+                Some(owned) => *owned,
+                None => continue,
+            };
+
+            owners_amount += owned;
+            match providers.remove(utxo) {
+                Some(provider) => moved.merge(&provider),
+                None => all_tracked = false,
+            }
+        }
+
+        if all_tracked && owners_amount > 0 {
+            for (receiver, amount) in receivers {
+                if *amount == 0 {
+                    continue;
+                }
+
+                let position = moved.split(*amount, owners_amount);
+                match providers.get_mut(receiver) {
+                    Some(provider) => provider.merge(&position),
+                    None => {
+                        providers.insert(receiver.clone(), position);
+                    }
+                }
+            }
+        }
+
+        liquidity_pool.providers = Some(providers);
+        self.liquidity_pool = Some(liquidity_pool);
+    }
+
+    pub fn lp_position(&self, utxo: &String) -> Result<LiquidityPosition, String> {
+        let liquidity_pool = match &self.liquidity_pool {
+            Some(liquidity_pool) => liquidity_pool,
+            None => return Err("lp_position: no liquidity pools".to_string()),
+        };
+
+        let lp_tokens = match self.owners.get(utxo) {
+            Some(lp_tokens) => *lp_tokens,
+            None => return Err("lp_position: utxo holds no lp tokens".to_string()),
+        };
+
+        let provider = match &liquidity_pool.providers {
+            Some(providers) => providers.get(utxo),
+            None => None,
+        };
+
+        return liquidity_pool.position(utxo, lp_tokens, provider, self.supply);
+    }
+
+    pub fn lp_positions(&self) -> Result<Vec<LiquidityPosition>, String> {
+        let mut positions: Vec<LiquidityPosition> = Vec::new();
+        for utxo in self.owners.keys() {
+            match self.lp_position(utxo) {
+                Ok(position) => positions.push(position),
+                Err(err) => return Err(err),
+            }
+        }
+
+        positions.sort_by(|a, b| b.lp_tokens.cmp(&a.lp_tokens).then(a.utxo.cmp(&b.utxo)));
+        return Ok(positions);
+    }
+
+    pub fn set_metadata(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        authority_utxo: &String,
+        new_authority_utxo: &str,
+    ) -> Result<(), String> {
+        match &self.metadata_authority {
+            Some(authority) if authority == authority_utxo => {}
+            Some(_) => return Err("set_metadata: utxo is not the metadata authority".to_string()),
+            None => return Err("set_metadata: contract has no metadata authority".to_string()),
+        };
+
+        if !new_authority_utxo.starts_with(&format!("{}:", txid)) {
+            return Err(
+                "set_metadata: new authority must be an output of the transaction".to_string(),
+            );
+        }
+
+        self.metadata_authority = Some(new_authority_utxo.to_owned());
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok(());
+    }
+
+    pub fn mint_supply(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        authority_utxo: &String,
+        new_authority_utxo: &str,
+        receiver_utxo: &String,
+        amount: u64,
+    ) -> Result<u64, String> {
+        match &self.mint_authority {
+            Some(authority) if authority == authority_utxo => {}
+            Some(_) => return Err("mint_supply: utxo is not the mint authority".to_string()),
+            None => return Err("mint_supply: contract has no mint authority".to_string()),
+        };
+
+        if amount == 0 {
+            return Err("mint_supply: amount must be greater than zero".to_string());
+        }
+
+        let max_supply = match self.max_supply {
+            Some(max_supply) => max_supply,
+            None => return Err("mint_supply: contract has no supply cap".to_string()),
+        };
+
+        // Supply leaves out tokens locked in drips and airdrops, so the cap is checked against
+        // everything minted so far
+        let minted = match self.minted {
+            Some(minted) => minted,
+            None => return Err("mint_supply: contract has no minted total".to_string()),
+        };
+
+        let new_minted = match minted.checked_add(amount) {
+            Some(new_minted) if new_minted <= max_supply => new_minted,
+            _ => return Err("mint_supply: amount exceeds the supply cap".to_string()),
+        };
+
+        let balance = match self.owners.get(receiver_utxo) {
+            Some(balance) => balance + amount,
+            None => amount,
+        };
+
+        self.owners.insert(receiver_utxo.clone(), balance);
+        self.supply += amount;
+        self.minted = Some(new_minted);
+        self.mint_authority = Some(new_authority_utxo.to_owned());
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok(balance);
+    }
+
+    pub fn renounce_mint(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        authority_utxo: &String,
+    ) -> Result<(), String> {
+        match &self.mint_authority {
+            Some(authority) if authority == authority_utxo => {}
+            Some(_) => return Err("renounce_mint: utxo is not the mint authority".to_string()),
+            None => return Err("renounce_mint: contract has no mint authority".to_string()),
+        };
+
+        // Once renounced the max supply is fixed at whatever has been minted so far
+        let minted = match self.minted {
+            Some(minted) => minted,
+            None => return Err("renounce_mint: contract has no minted total".to_string()),
+        };
+
+        self.mint_authority = None;
+        self.max_supply = Some(minted);
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok(());
+    }
+
+    pub fn transfer_tokens(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        sender_utxos: &Vec<String>,
+        receivers: &Vec<(String, Vec<String>)>,
+        current_block_height: u64,
+    ) -> Result<(Vec<bool>, u64), String> {
+        let nft_tokens = match self.nft_tokens.clone() {
+            Some(nft_tokens) => nft_tokens,
+            None => return Err("transfer_tokens: contract is not a collection".to_string()),
+        };
+
+        let mut assigned: Vec<&String> = Vec::new();
+        for (_, token_ids) in receivers {
+            for token_id in token_ids {
+                let token = match nft_tokens.get(token_id) {
+                    Some(token) => token,
+                    None => return Err(format!("transfer_tokens: unknown token {}", token_id)),
+                };
+
+                if !sender_utxos.contains(&token.owner) || assigned.contains(&token_id) {
+                    return Err(format!(
+                        "transfer_tokens: token {} not held by senders",
+                        token_id
+                    ));
+                }
+
+                assigned.push(token_id);
+            }
+        }
+
+        let held = nft_tokens
+            .values()
+            .filter(|token| sender_utxos.contains(&token.owner))
+            .count();
+        if held != assigned.len() {
+            return Err(
+                "transfer_tokens: every token held by the senders must be sent".to_string(),
+            );
+        }
+
+        let amounts: Vec<(String, u64)> = receivers
+            .iter()
+            .map(|(receiver, token_ids)| (receiver.clone(), token_ids.len() as u64))
+            .collect();
+        let result = self.transfer(txid, payload, sender_utxos, &amounts, current_block_height)?;
+
+        let mut nft_tokens = nft_tokens;
+        for (receiver, token_ids) in receivers {
+            for token_id in token_ids {
+                if let Some(token) = nft_tokens.get_mut(token_id) {
+                    token.owner = receiver.clone();
+                }
+            }
+        }
+
+        self.nft_tokens = Some(nft_tokens);
+        return Ok(result);
+    }
+
+    pub fn nft_token(&self, token_id: &String) -> Result<NftToken, String> {
+        let nft_tokens = match &self.nft_tokens {
+            Some(nft_tokens) => nft_tokens,
+            None => return Err("nft_token: contract is not a collection".to_string()),
+        };
+
+        match nft_tokens.get(token_id) {
+            Some(token) => return Ok(token.clone()),
+            None => return Err("nft_token: token not found".to_string()),
+        };
+    }
+
+    pub fn nft_tokens(&self) -> Result<Vec<NftToken>, String> {
+        let nft_tokens = match &self.nft_tokens {
+            Some(nft_tokens) => nft_tokens,
+            None => return Err("nft_tokens: contract is not a collection".to_string()),
+        };
+
+        let mut tokens: Vec<NftToken> = nft_tokens.values().cloned().collect();
+        tokens.sort_by(|a, b| a.token_id.cmp(&b.token_id));
+        return Ok(tokens);
+    }
+
+    pub fn freeze(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        authority_utxo: &String,
+        new_authority_utxo: &str,
+        utxos: &Vec<String>,
+    ) -> Result<(), String> {
+        match &self.freeze_authority {
+            Some(authority) if authority == authority_utxo => {}
+            Some(_) => return Err("freeze: utxo is not the freeze authority".to_string()),
+            None => return Err("freeze: contract has no freeze authority".to_string()),
+        };
+
+        let mut frozen_utxos = self.frozen_utxos.clone().unwrap_or_default();
+
+        for utxo in utxos {
+            if !self.owners.contains_key(utxo) {
+                return Err(format!("freeze: utxo {} holds no tokens", utxo));
+            }
+
+            if !frozen_utxos.contains(utxo) {
+                frozen_utxos.push(utxo.clone());
+            }
+        }
+
+        self.frozen_utxos = Some(frozen_utxos);
+        self.freeze_authority = Some(new_authority_utxo.to_owned());
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok(());
+    }
+
+    pub fn unfreeze(
+        &mut self,
+        txid: &String,
+        payload: &String,
+        authority_utxo: &String,
+        new_authority_utxo: &str,
+        utxos: &Vec<String>,
+    ) -> Result<(), String> {
+        match &self.freeze_authority {
+            Some(authority) if authority == authority_utxo => {}
+            Some(_) => return Err("unfreeze: utxo is not the freeze authority".to_string()),
+            None => return Err("unfreeze: contract has no freeze authority".to_string()),
+        };
+
+        let mut frozen_utxos = self.frozen_utxos.clone().unwrap_or_default();
+
+        for utxo in utxos {
+            if !frozen_utxos.contains(utxo) {
+                return Err(format!("unfreeze: utxo {} is not frozen", utxo));
+            }
+
+            frozen_utxos.retain(|frozen_utxo| frozen_utxo != utxo);
+        }
+
+        self.frozen_utxos = Some(frozen_utxos);
+        self.freeze_authority = Some(new_authority_utxo.to_owned());
+        self.payloads.insert(txid.to_string(), payload.to_string());
+        return Ok(());
+    }
+
+    fn first_frozen_utxo(&self, utxos: &[String]) -> Option<String> {
+        let frozen_utxos = match &self.frozen_utxos {
+            Some(frozen_utxos) => frozen_utxos,
+            None => return None,
+        };
+
+        return utxos
+            .iter()
+            .find(|utxo| frozen_utxos.contains(utxo))
+            .cloned();
+    }
+
+    // Collection tokens held by the utxos in token id order
+    fn held_nft_tokens(&self, utxos: &[String]) -> Vec<String> {
+        let mut token_ids: Vec<String> = match &self.nft_tokens {
+            Some(nft_tokens) => nft_tokens
+                .values()
+                .filter(|token| utxos.contains(&token.owner))
+                .map(|token| token.token_id.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        token_ids.sort();
+        return token_ids;
+    }
+
+    // Hands the tokens out to the receivers in order, tokens left over are burned
+    fn assign_nft_tokens(&mut self, token_ids: &[String], receivers: &Vec<(String, u64)>) {
+        let mut nft_tokens = match self.nft_tokens.clone() {
+            Some(nft_tokens) => nft_tokens,
+            None => return,
+        };
+
+        let mut remaining = token_ids.iter();
+        for (receiver, amount) in receivers {
+            for token_id in remaining.by_ref().take(*amount as usize) {
+                if let Some(token) = nft_tokens.get_mut(token_id) {
+                    token.owner = receiver.clone();
+                }
+            }
+        }
+
+        for token_id in remaining {
+            nft_tokens.remove(token_id);
+        }
+
+        self.nft_tokens = Some(nft_tokens);
+    }
+
+    fn transfer_nft_tokens(&mut self, utxos: &Vec<String>, receivers: &Vec<(String, u64)>) {
+        if self.nft_tokens.is_none() {
+            return;
+        }
+
+        let token_ids = self.held_nft_tokens(utxos);
+        self.assign_nft_tokens(&token_ids, receivers);
+    }
+
+    fn transfer_drips(
+        &mut self,
+        utxos: &[String],
+        block_height: u64,
+        change_utxo: &str,
+    ) -> bool {
+        let mut drips = self.drips.clone().unwrap_or_default();
+
+        let mut change_drip_present = false;
+        let mut new_drips: Vec<Drip> = Vec::new();
+        for utxo in utxos.to_owned() {
             if self.owners.contains_key(&utxo.clone()) {
                 if let Some(old_drips) = drips.get(&utxo) {
                     for drip in old_drips {
-                        let new_drip = Drip {
-                            block_end: drip.block_end.clone(),
-                            drip_amount: drip.drip_amount.clone(),
-                            amount: drip.amount.clone()
-                                - (block_height - drip.start_block) * drip.drip_amount,
-                            start_block: block_height,
-                            last_block_dripped: block_height,
-                        };
-
-                        new_drips.push(new_drip.clone());
+                        new_drips.push(drip.rebase(block_height));
                     }
 
                     // Remove the old drip from the vector
@@ -1598,7 +2870,7 @@ impl SCL01Contract {
 
         if !new_drips.is_empty() {
             change_drip_present = true;
-            drips.insert(change_utxo.clone(), new_drips);
+            drips.insert(change_utxo.to_owned(), new_drips);
         }
 
         self.drips = Some(drips);
@@ -1613,6 +2885,147 @@ pub struct Drip {
     pub amount: u64,
     pub start_block: u64,
     pub last_block_dripped: u64,
+    pub schedule: Option<VestingSchedule>,
+    pub revoke_authority: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct DripEvent {
+    pub utxo: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub amount: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct DripStatus {
+    pub utxo: String,
+    pub schedule: VestingSchedule,
+    pub amount: u64,
+    pub start_block: u64,
+    pub block_end: u64,
+    pub last_block_dripped: u64,
+    pub released: u64,
+    pub remaining: u64,
+    pub revocable: bool,
+}
+
+// Drip events and completed drips recorded for a utxo, kept per utxo so a lookup reads one file
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct DripHistory {
+    pub events: Vec<DripEvent>,
+    pub completed: Vec<DripStatus>,
+}
+
+// Drips without a schedule are the original linear drips
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum VestingSchedule {
+    Linear,
+    Cliff { cliff_blocks: u64 },
+    Step { step_blocks: u64 },
+}
+
+impl VestingSchedule {
+    // Schedule payloads are C:blocks for a cliff, S:blocks for steps and M for monthly steps
+    pub fn from_payload(value: &str, duration: u64) -> Result<VestingSchedule, String> {
+        let fields: Vec<&str> = value.trim().split(":").collect();
+        let blocks = match fields.get(1) {
+            Some(blocks) => match blocks.parse::<u64>() {
+                Ok(blocks) => Some(blocks),
+                Err(_) => return Err(format!("Invalid vesting schedule: {}", value)),
+            },
+            None => None,
+        };
+
+        let schedule = match (fields[0], blocks) {
+            ("L", None) => VestingSchedule::Linear,
+            ("C", Some(cliff_blocks)) if cliff_blocks < duration => {
+                VestingSchedule::Cliff { cliff_blocks }
+            }
+            ("S", Some(step_blocks)) if step_blocks > 0 && step_blocks <= duration => {
+                VestingSchedule::Step { step_blocks }
+            }
+            ("M", None) if BLOCKS_PER_MONTH <= duration => VestingSchedule::Step {
+                step_blocks: BLOCKS_PER_MONTH,
+            },
+            _ => return Err(format!("Invalid vesting schedule: {}", value)),
+        };
+
+        return Ok(schedule);
+    }
+}
+
+impl Drip {
+    pub fn status(&self, utxo: &str) -> DripStatus {
+        let released = self.vested(self.last_block_dripped);
+        return DripStatus {
+            utxo: utxo.to_owned(),
+            schedule: match &self.schedule {
+                Some(schedule) => schedule.clone(),
+                None => VestingSchedule::Linear,
+            },
+            amount: self.amount,
+            start_block: self.start_block,
+            block_end: self.block_end,
+            last_block_dripped: self.last_block_dripped,
+            released,
+            remaining: self.amount - released,
+            revocable: self.revoke_authority.is_some(),
+        };
+    }
+
+    // Total amount released by the end of the given block, the start block releases straight away
+    pub fn vested(&self, block_height: u64) -> u64 {
+        if block_height < self.start_block {
+            return 0;
+        }
+
+        if block_height >= self.block_end {
+            return self.amount;
+        }
+
+        let blocks = block_height - self.start_block + 1;
+        let linear = std::cmp::min(self.amount, blocks * self.drip_amount);
+        match &self.schedule {
+            None | Some(VestingSchedule::Linear) => return linear,
+            Some(VestingSchedule::Cliff { cliff_blocks }) => {
+                if blocks <= *cliff_blocks {
+                    return 0;
+                }
+
+                return linear;
+            }
+            Some(VestingSchedule::Step { step_blocks }) => {
+                let duration = self.block_end - self.start_block + 1;
+                let steps = duration.div_ceil(*step_blocks);
+                let steps_done = blocks / step_blocks;
+                return (self.amount as u128 * steps_done as u128 / steps as u128) as u64;
+            }
+        };
+    }
+
+    pub fn remaining(&self, block_height: u64) -> u64 {
+        return self.amount - self.vested(block_height);
+    }
+
+    // Linear drips restart from the block they move at, other schedules keep their original start.
+    // A drip not yet settled up to that block also keeps its start so the blocks owed still release
+    pub fn rebase(&self, block_height: u64) -> Drip {
+        match &self.schedule {
+            None | Some(VestingSchedule::Linear) if self.last_block_dripped == block_height => {}
+            _ => return self.clone(),
+        };
+
+        return Drip {
+            block_end: self.block_end,
+            drip_amount: self.drip_amount,
+            amount: self.amount - (block_height - self.start_block) * self.drip_amount,
+            start_block: block_height,
+            last_block_dripped: block_height,
+            schedule: self.schedule.clone(),
+            revoke_authority: self.revoke_authority.clone(),
+        };
+    }
 }
 
 #[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
@@ -1628,6 +3041,100 @@ pub struct DimAirdrop {
     pub last_airdrop_split: Option<Vec<String>>,
     pub single_drop: bool,
     pub claimers: HashMap<String, u64>,
+    pub claim_rules: Option<ClaimRules>,
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct MerkleAirdrop {
+    pub pool_amount: u64,
+    pub merkle_root: String,
+    pub leaf_count: u64,
+    pub amount_airdropped: u64,
+    pub claimed: String,
+}
+
+impl MerkleAirdrop {
+    // Leaves and nodes are domain separated so an inner node can never be passed off as a leaf
+    pub fn leaf_hash(index: &u64, address: &String, amount: &u64) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0x00];
+        data.extend_from_slice(format!("{}:{}:{}", index, address, amount).as_bytes());
+        return digest(Algorithm::SHA256, &data);
+    }
+
+    pub fn node_hash(left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0x01];
+        if left <= right {
+            data.extend_from_slice(left);
+            data.extend_from_slice(right);
+        } else {
+            data.extend_from_slice(right);
+            data.extend_from_slice(left);
+        }
+
+        return digest(Algorithm::SHA256, &data);
+    }
+
+    pub fn verify_proof(
+        &self,
+        index: &u64,
+        address: &String,
+        amount: &u64,
+        proof: &Vec<String>,
+    ) -> Result<bool, String> {
+        if proof.len() > MAX_MERKLE_PROOF_LENGTH {
+            return Err("merkle proof is too long".to_string());
+        }
+
+        let mut hash = MerkleAirdrop::leaf_hash(index, address, amount);
+        for sibling in proof {
+            let sibling = match hex::decode(sibling) {
+                Ok(sibling) => sibling,
+                Err(_) => return Err(format!("invalid proof hash {}", sibling)),
+            };
+
+            if sibling.len() != 32 {
+                return Err("proof hashes must be 32 bytes".to_string());
+            }
+
+            hash = MerkleAirdrop::node_hash(&hash, &sibling);
+        }
+
+        return Ok(hex::encode(hash) == self.merkle_root);
+    }
+
+    pub fn is_claimed(&self, index: u64) -> Result<bool, String> {
+        let bitmap = match hex::decode(&self.claimed) {
+            Ok(bitmap) => bitmap,
+            Err(_) => return Err("claimed bitmap is corrupt".to_string()),
+        };
+
+        return match bitmap.get((index / 8) as usize) {
+            Some(byte) => Ok(byte & (1 << (index % 8)) != 0),
+            None => Err("leaf index is out of range".to_string()),
+        };
+    }
+
+    pub fn set_claimed(&mut self, index: u64) -> Result<(), String> {
+        let mut bitmap = match hex::decode(&self.claimed) {
+            Ok(bitmap) => bitmap,
+            Err(_) => return Err("claimed bitmap is corrupt".to_string()),
+        };
+
+        match bitmap.get_mut((index / 8) as usize) {
+            Some(byte) => *byte |= 1 << (index % 8),
+            None => return Err("leaf index is out of range".to_string()),
+        }
+
+        self.claimed = hex::encode(bitmap);
+        return Ok(());
+    }
+
+    pub fn claimed_count(&self) -> u64 {
+        return match hex::decode(&self.claimed) {
+            Ok(bitmap) => bitmap.iter().map(|byte| byte.count_ones() as u64).sum(),
+            Err(_) => 0,
+        };
+    }
 }
 
 #[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
@@ -1640,16 +3147,357 @@ pub struct DGE {
     pub drip_duration: u64,
     pub single_drop: bool,
     pub donaters: HashMap<String, u64>,
+    pub claim_rules: Option<ClaimRules>,
+    pub start_block: Option<u64>,
+    pub end_block: Option<u64>,
+    pub soft_cap: Option<u64>,
+    pub hard_cap: Option<u64>,
+    pub sats_raised: Option<u64>,
+    pub refunds: Option<Vec<DGERefund>>,
+    pub held: Option<Vec<DGEAllocation>>,
+    pub soft_cap_met: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Default, Serialize, Clone)]
-pub struct Listing {
-    pub list_utxo: String,
-    pub list_amt: u64,
-    pub price: u64,
+// Allocation held until a dge with a soft cap settles after its end block
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct DGEAllocation {
+    pub txid: String,
+    pub address: String,
+    pub reciever_utxo: String,
+    pub sats: u64,
+    pub token_amount: u64,
+    pub block_height: u64,
+}
+
+impl DGE {
+    // Sats of the donation the dge honours, the tokens they buy and the reason the rest of the
+    // donation is refunded, the donation is capped by the max drop, what is left under the hard
+    // cap and what is left of the pool
+    pub fn allocation(&self, donation: u64, decimals: i32) -> (u64, u64, &'static str) {
+        let scale = 10u128.pow(decimals as u32);
+        let max_donation = (self.max_drop as u128 * self.sats_rate as u128 / scale) as u64;
+        let mut honoured = std::cmp::min(donation, max_donation);
+        let mut reason = "over_max_drop";
+        if let Some(hard_cap) = self.hard_cap {
+            let below_cap = hard_cap.saturating_sub(self.sats_raised.unwrap_or(0));
+            if below_cap < honoured {
+                honoured = below_cap;
+                reason = "over_hard_cap";
+            }
+        }
+
+        let mut token_amount = (honoured as u128 * scale / self.sats_rate as u128) as u64;
+        let remaining = self.pool_amount.saturating_sub(self.current_amount_dropped);
+        if token_amount >= remaining {
+            token_amount = remaining;
+            // Only the sats that bought tokens are kept, rounded up in favour of the dge
+            let spent = (token_amount as u128 * self.sats_rate as u128).div_ceil(scale) as u64;
+            if spent < honoured {
+                honoured = spent;
+                reason = "over_pool";
+            }
+        }
+
+        return (honoured, token_amount, reason);
+    }
+
+    pub fn add_refund(
+        &mut self,
+        txid: &String,
+        donater: &String,
+        amount: u64,
+        block_height: u64,
+        reason: &str,
+    ) {
+        let mut refunds = self.refunds.clone().unwrap_or_default();
+
+        refunds.push(DGERefund {
+            txid: txid.to_string(),
+            address: donater.to_string(),
+            amount,
+            block_height,
+            reason: reason.to_string(),
+        });
+        self.refunds = Some(refunds);
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct DGERefund {
+    pub txid: String,
+    pub address: String,
+    pub amount: u64,
+    pub block_height: u64,
+    pub reason: String,
+}
+
+pub const CLAIM_ADDRESS_TYPES: [&str; 5] = ["p2pkh", "p2sh", "v0_p2wpkh", "v0_p2wsh", "v1_p2tr"];
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct ClaimerInfo {
+    pub address: String,
+    pub address_type: String,
+    pub input_value: u64,
+    // Blocks since the address was first seen, only looked up for a confirmed claim under min_age
+    pub address_age: Option<u64>,
+    // None while the claim is still in the mempool
+    pub block_height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct ClaimRules {
+    pub max_per_address: Option<u64>,
+    pub min_input_value: Option<u64>,
+    pub min_address_age: Option<u64>,
+    pub max_claims_per_block: Option<u64>,
+    pub allowed_address_types: Option<Vec<String>>,
+    pub denied_address_types: Option<Vec<String>>,
+    pub claimed_by_address: HashMap<String, u64>,
+    pub last_claim_block: u64,
+    pub claims_in_last_block: u64,
+}
+
+impl ClaimRules {
+    // Rules are written as key=value pairs separated by ";", address types are separated by "|"
+    pub fn from_payload(value: &str) -> Result<ClaimRules, String> {
+        let mut rules = ClaimRules::default();
+        for rule in value.split(";") {
+            if rule.is_empty() {
+                continue;
+            }
+
+            let pair: Vec<&str> = rule.splitn(2, "=").collect();
+            if pair.len() != 2 {
+                return Err(format!("invalid claim rule {}", rule));
+            }
+
+            match pair[0] {
+                "cap" | "min_value" | "min_age" | "per_block" => {
+                    let number = match pair[1].parse::<u64>() {
+                        Ok(number) => number,
+                        Err(_) => return Err(format!("invalid value for claim rule {}", pair[0])),
+                    };
+
+                    if number == 0 && (pair[0] == "cap" || pair[0] == "per_block") {
+                        return Err(format!("claim rule {} must be above zero", pair[0]));
+                    }
+
+                    match pair[0] {
+                        "cap" => rules.max_per_address = Some(number),
+                        "min_value" => rules.min_input_value = Some(number),
+                        "min_age" => rules.min_address_age = Some(number),
+                        _ => rules.max_claims_per_block = Some(number),
+                    }
+                }
+                "allow" | "deny" => {
+                    let mut address_types: Vec<String> = Vec::new();
+                    for address_type in pair[1].split("|") {
+                        let address_type = address_type.to_ascii_lowercase();
+                        if !CLAIM_ADDRESS_TYPES.contains(&address_type.as_str()) {
+                            return Err(format!("unknown address type {}", address_type));
+                        }
+
+                        address_types.push(address_type);
+                    }
+
+                    if pair[0] == "allow" {
+                        rules.allowed_address_types = Some(address_types);
+                    } else {
+                        rules.denied_address_types = Some(address_types);
+                    }
+                }
+                _ => return Err(format!("unknown claim rule {}", pair[0])),
+            }
+        }
+
+        return Ok(rules);
+    }
+
+    // Returns the reason the claim breaks a rule, amount is the allocation the claim would receive.
+    // Per block and age rules need the block the claim confirmed in so pending claims skip them
+    pub fn check(&self, claimer: &ClaimerInfo, amount: u64) -> Result<(), String> {
+        if let Some(allowed) = &self.allowed_address_types {
+            if !allowed.contains(&claimer.address_type) {
+                return Err("claim_rule_address_type_not_allowed".to_string());
+            }
+        }
+
+        if let Some(denied) = &self.denied_address_types {
+            if denied.contains(&claimer.address_type) {
+                return Err("claim_rule_address_type_denied".to_string());
+            }
+        }
+
+        if let Some(min_input_value) = self.min_input_value {
+            if claimer.input_value < min_input_value {
+                return Err("claim_rule_input_value_too_low".to_string());
+            }
+        }
+
+        if let (Some(min_address_age), Some(address_age)) =
+            (self.min_address_age, claimer.address_age)
+        {
+            if address_age < min_address_age {
+                return Err("claim_rule_address_too_young".to_string());
+            }
+        }
+
+        if let (Some(max_claims_per_block), Some(block_height)) =
+            (self.max_claims_per_block, claimer.block_height)
+        {
+            if block_height == self.last_claim_block
+                && self.claims_in_last_block >= max_claims_per_block
+            {
+                return Err("claim_rule_block_limit_reached".to_string());
+            }
+        }
+
+        if let Some(max_per_address) = self.max_per_address {
+            let claimed = match self.claimed_by_address.get(&claimer.address) {
+                Some(claimed) => *claimed,
+                None => 0,
+            };
+
+            if claimed + amount > max_per_address {
+                return Err("claim_rule_address_cap_reached".to_string());
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub fn record(&mut self, claimer: &ClaimerInfo, amount: u64) {
+        let claimed = match self.claimed_by_address.get(&claimer.address) {
+            Some(claimed) => *claimed,
+            None => 0,
+        };
+
+        self.claimed_by_address
+            .insert(claimer.address.clone(), claimed + amount);
+        let block_height = match claimer.block_height {
+            Some(block_height) => block_height,
+            None => return,
+        };
+
+        if block_height == self.last_claim_block {
+            self.claims_in_last_block += 1;
+        } else {
+            self.last_claim_block = block_height;
+            self.claims_in_last_block = 1;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct NftToken {
+    pub token_id: String,
+    pub name: String,
+    pub attributes: HashMap<String, String>,
+    pub content_hash: Option<String>,
+    pub uri: Option<String>,
+    pub owner: String,
+}
+
+impl NftToken {
+    // Token payloads are id|name|content|key=value;key=value, content being a sha256 hash or a uri
+    pub fn from_payload(value: &str, owner: &str) -> Result<NftToken, String> {
+        let fields: Vec<&str> = value.split("|").map(|field| field.trim()).collect();
+        if fields.len() < 2 || fields[0].is_empty() || fields[1].is_empty() {
+            return Err(format!("Invalid collection token: {}", value));
+        }
+
+        let mut token = NftToken {
+            token_id: fields[0].to_string(),
+            name: fields[1].to_string(),
+            attributes: HashMap::new(),
+            content_hash: None,
+            uri: None,
+            owner: owner.to_owned(),
+        };
+
+        if fields.len() > 2 && !fields[2].is_empty() {
+            if fields[2].len() == 64 && fields[2].chars().all(|c| c.is_ascii_hexdigit()) {
+                token.content_hash = Some(fields[2].to_ascii_lowercase());
+            } else {
+                token.uri = Some(fields[2].to_string());
+            }
+        }
+
+        if fields.len() > 3 && !fields[3].is_empty() {
+            for attribute in fields[3].split(";") {
+                let pair: Vec<&str> = attribute.splitn(2, "=").collect();
+                if pair.len() < 2 || pair[0].is_empty() {
+                    return Err(format!("Invalid collection token attribute: {}", attribute));
+                }
+
+                token
+                    .attributes
+                    .insert(pair[0].to_string(), pair[1].to_string());
+            }
+        }
+
+        return Ok(token);
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
+pub struct Royalty {
+    pub bps: u64,
+    pub address: String,
+}
+
+impl Royalty {
+    // Royalty payloads are ROYALTY(bps;address)
+    pub fn from_payload(value: &str) -> Result<Royalty, String> {
+        let fields: Vec<&str> = value
+            .trim()
+            .trim_start_matches("ROYALTY(")
+            .trim_end_matches(")")
+            .split(";")
+            .collect();
+        if fields.len() != 2 {
+            return Err(format!("Invalid royalty: {}", value));
+        }
+
+        let bps = match fields[0].trim().parse::<u64>() {
+            Ok(bps) if bps > 0 && bps <= MAX_ROYALTY_BPS => bps,
+            _ => return Err(format!("Invalid royalty basis points: {}", fields[0])),
+        };
+
+        let address = fields[1].trim().to_string();
+        if address.parse::<Address>().is_err() {
+            return Err(format!("Invalid royalty address: {}", address));
+        }
+
+        return Ok(Royalty { bps, address });
+    }
+
+    pub fn amount(&self, trade_value: u64) -> u64 {
+        return (trade_value as u128 * self.bps as u128 / BPS_SCALE as u128) as u64;
+    }
+
+    // Nothing is owed when the seller is the royalty recipient
+    pub fn owed(&self, trade_value: u64, seller_address: &String) -> u64 {
+        if self.address == *seller_address {
+            return 0;
+        }
+
+        return self.amount(trade_value);
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone)]
+pub struct Listing {
+    pub list_utxo: String,
+    pub list_amt: u64,
+    pub price: u64,
     pub rec_addr: String,
     pub change_utxo: String,
     pub valid_bid_block: Option<i32>,
+    pub expiry_block: Option<u64>,
+    pub allow_partial: Option<bool>,
+    pub fills: Option<Vec<String>>,
+    pub token_ids: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, Default, Serialize, Clone)]
@@ -1661,12 +3509,64 @@ pub struct Bid {
     pub accept_tx: String,
     pub reseved_utxo: String,
     pub fullfilment_utxos: Vec<String>,
+    pub expiry_block: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct LiquidityProvider {
     pub provided_block_height: u32,
     pub lp_tokens: u64,
+    pub token_1_deposited: u64,
+    pub token_2_deposited: u64,
+    pub entry_growth: u128,
+}
+
+impl LiquidityProvider {
+    pub fn merge(&mut self, other: &LiquidityProvider) {
+        let lp_tokens = self.lp_tokens as u128 + other.lp_tokens as u128;
+        let weighted_growth = self.entry_growth * self.lp_tokens as u128
+            + other.entry_growth * other.lp_tokens as u128;
+        if let Some(entry_growth) = weighted_growth.checked_div(lp_tokens) {
+            self.entry_growth = entry_growth;
+        }
+
+        if self.lp_tokens == 0 || other.provided_block_height < self.provided_block_height {
+            self.provided_block_height = other.provided_block_height;
+        }
+
+        self.lp_tokens += other.lp_tokens;
+        self.token_1_deposited += other.token_1_deposited;
+        self.token_2_deposited += other.token_2_deposited;
+    }
+
+    pub fn split(&self, lp_tokens: u64, total: u64) -> LiquidityProvider {
+        return LiquidityProvider {
+            provided_block_height: self.provided_block_height,
+            lp_tokens,
+            token_1_deposited: (self.token_1_deposited as u128 * lp_tokens as u128 / total as u128)
+                as u64,
+            token_2_deposited: (self.token_2_deposited as u128 * lp_tokens as u128 / total as u128)
+                as u64,
+            entry_growth: self.entry_growth,
+        };
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct LiquidityPosition {
+    pub utxo: String,
+    pub lp_tokens: u64,
+    pub share_bps: u64,
+    pub token_1_value: u64,
+    pub token_2_value: u64,
+    pub position_value_token_1: u64,
+    pub provided_block_height: Option<u32>,
+    pub token_1_deposited: Option<u64>,
+    pub token_2_deposited: Option<u64>,
+    pub token_1_fees: Option<u64>,
+    pub token_2_fees: Option<u64>,
+    pub hold_value_token_1: Option<u64>,
+    pub impermanent_loss_bps: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -1680,4 +3580,1835 @@ pub struct LiquidityPool {
     pub liquidity_ratio: f64,
     pub swaps: HashMap<String, (u64, u64)>,
     pub liquidations: HashMap<String, (u64, u64)>,
+    pub swap_records: Option<HashMap<String, SwapRecord>>,
+    pub liquidation_records: Option<HashMap<String, LiquidationRecord>>,
+    pub fee_bps: Option<u64>,
+    pub ratio_fixed: Option<u128>,
+    pub providers: Option<HashMap<String, LiquidityProvider>>,
+    pub protocol_fee: Option<ProtocolFee>,
+    pub curve: Option<PoolCurve>,
+}
+
+/// A settled swap with the fee it paid at the pool's fee rate at the time. The block is only
+/// known once the swap confirms.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SwapRecord {
+    pub sender_contract_id: String,
+    pub provided_amount: u64,
+    pub swap_amount: u64,
+    pub fee_bps: u64,
+    pub fee: u64,
+    pub block_height: Option<u64>,
+    pub block_time: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct LiquidationRecord {
+    pub token_1: u64,
+    pub token_2: u64,
+    pub block_height: Option<u64>,
+    pub block_time: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum PoolCurve {
+    ConstantProduct,
+    /// Weight of token 1 in percent, token 2 holds the rest
+    Weighted(u64),
+    /// Amplification coefficient for pegged pairs
+    StableSwap(u64),
+}
+
+impl PoolCurve {
+    /// Parse the curve field of an SCL04 mint payload: CP, W{token 1 weight} or S{amplification}
+    pub fn from_payload(value: &str) -> Result<PoolCurve, String> {
+        if value.is_empty() || value == "CP" {
+            return Ok(PoolCurve::ConstantProduct);
+        }
+
+        if let Some(weight) = value.strip_prefix("W") {
+            return match weight.parse::<u64>() {
+                Ok(weight) if (1..100).contains(&weight) => Ok(PoolCurve::Weighted(weight)),
+                _ => Err("weight must be between 1 and 99".to_string()),
+            };
+        }
+
+        if let Some(amplification) = value.strip_prefix("S") {
+            return match amplification.parse::<u64>() {
+                Ok(amplification) if (1..=MAX_AMPLIFICATION).contains(&amplification) => {
+                    Ok(PoolCurve::StableSwap(amplification))
+                }
+                _ => Err("amplification must be between 1 and 10000".to_string()),
+            };
+        }
+
+        return Err("unknown pool curve".to_string());
+    }
+}
+
+/// Share of every swap fee, in basis points of the fee, that is held out of the pool for the
+/// recipient. The recipient is either a utxo that must be spent to claim or an address that
+/// must sign the first input of the claim.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ProtocolFee {
+    pub recipient: String,
+    pub share_bps: u64,
+    pub accrued_1: u64,
+    pub accrued_2: u64,
+    pub claimed_1: u64,
+    pub claimed_2: u64,
+}
+
+/// Check a settled swap amount against the amount the sender was quoted. Amounts above the
+/// tolerance are capped, amounts below it return 0 so the swap is refunded.
+pub fn apply_slippage_tolerance(swap_amount: u64, quoted: u64, tolerance_bps: u64) -> u64 {
+    if swap_amount.abs_diff(quoted) as u128 * BPS_SCALE as u128
+        <= quoted as u128 * tolerance_bps as u128
+    {
+        return swap_amount;
+    }
+
+    if swap_amount >= quoted {
+        println!("swap amount is above tolerence: {}", swap_amount);
+        return (quoted as u128 * (BPS_SCALE + tolerance_bps) as u128 / BPS_SCALE as u128) as u64;
+    }
+
+    return 0;
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SwapQuote {
+    pub from_contract_id: String,
+    pub to_contract_id: String,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+    pub protocol_fee: u64,
+    pub price_impact_bps: u64,
+    pub pool_1_after: u64,
+    pub pool_2_after: u64,
+    pub tolerance_bps: u64,
+    pub minimum_received: u64,
+}
+
+/// Split of a single sided deposit into the swap through the pool and the paired deposit.
+/// Any token 2 that cannot be paired is refunded.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ZapPlan {
+    pub sender_contract_id: String,
+    pub amount: u64,
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub provided_1: u64,
+    pub provided_2: u64,
+    pub lp_tokens: u64,
+    pub refund_2: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SwapRoute {
+    pub from_contract_id: String,
+    pub to_contract_id: String,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub minimum_received: u64,
+    pub lp_contract_ids: Vec<String>,
+    pub hops: Vec<SwapQuote>,
+    pub payload: String,
+}
+
+// Pool math is done in integers so every host settles swaps to the same unit.
+// Rounding always leaves the remainder in the pool.
+impl LiquidityPool {
+    pub fn fee_bps(&self) -> u64 {
+        match self.fee_bps {
+            Some(fee_bps) => fee_bps,
+            None => (self.fee as f64 * BPS_SCALE as f64).round() as u64,
+        }
+    }
+
+    pub fn ratio_fixed(&self) -> u128 {
+        match self.ratio_fixed {
+            Some(ratio_fixed) => ratio_fixed,
+            None => (self.liquidity_ratio * RATIO_SCALE as f64).round() as u128,
+        }
+    }
+
+    pub fn migrate_fixed_point(&mut self) {
+        self.fee_bps = Some(self.fee_bps());
+        self.ratio_fixed = Some(self.ratio_fixed());
+    }
+
+    pub fn curve(&self) -> PoolCurve {
+        match &self.curve {
+            Some(curve) => curve.clone(),
+            None => PoolCurve::ConstantProduct,
+        }
+    }
+
+    pub fn swap_output(
+        &self,
+        sender_is_token_1: bool,
+        provided_amount: u64,
+    ) -> Result<u64, String> {
+        let fee_bps = self.fee_bps();
+        if fee_bps >= BPS_SCALE {
+            return Err("fee must be below 10000 bps".to_string());
+        }
+
+        let provided_after_fee =
+            provided_amount as u128 * (BPS_SCALE - fee_bps) as u128 / BPS_SCALE as u128;
+        return self.curve_output(sender_is_token_1, provided_after_fee);
+    }
+
+    // Output of the curve for an amount that has already paid the fee
+    fn curve_output(&self, sender_is_token_1: bool, amount_in: u128) -> Result<u64, String> {
+        let (sender_pool_amount, reciever_pool_amount) = match sender_is_token_1 {
+            true => (self.pool_1 as u128, self.pool_2 as u128),
+            false => (self.pool_2 as u128, self.pool_1 as u128),
+        };
+
+        if sender_pool_amount + amount_in == 0 {
+            return Err("pool is empty".to_string());
+        }
+
+        let swap_amount = match self.curve() {
+            PoolCurve::ConstantProduct => {
+                reciever_pool_amount * amount_in / (sender_pool_amount + amount_in)
+            }
+            PoolCurve::Weighted(weight_1) => {
+                let (weight_in, weight_out) = match sender_is_token_1 {
+                    true => (weight_1, 100 - weight_1),
+                    false => (100 - weight_1, weight_1),
+                };
+
+                // out = reciever * (1 - (sender / (sender + in)) ^ (weight_in / weight_out))
+                let base =
+                    (sender_pool_amount * CURVE_SCALE).div_ceil(sender_pool_amount + amount_in);
+                let factor = pow_ratio_ceil(base, weight_in, weight_out);
+                reciever_pool_amount * (CURVE_SCALE - factor) / CURVE_SCALE
+            }
+            PoolCurve::StableSwap(amplification) => {
+                if sender_pool_amount == 0 || reciever_pool_amount == 0 {
+                    return Ok(0);
+                }
+
+                // D is floored, solving against the next unit up keeps the remainder in the pool
+                let invariant =
+                    stable_swap_invariant(sender_pool_amount, reciever_pool_amount, amplification)
+                        + 1;
+                let reciever_after =
+                    stable_swap_balance(sender_pool_amount + amount_in, invariant, amplification);
+                reciever_pool_amount.saturating_sub(reciever_after + 1)
+            }
+        };
+
+        return Ok(swap_amount.min(reciever_pool_amount) as u64);
+    }
+
+    /// Output at the current marginal price, without fee or price impact
+    pub fn spot_output(&self, sender_is_token_1: bool, amount: u128) -> u128 {
+        let (sender_pool_amount, reciever_pool_amount) = match sender_is_token_1 {
+            true => (self.pool_1 as u128, self.pool_2 as u128),
+            false => (self.pool_2 as u128, self.pool_1 as u128),
+        };
+
+        if sender_pool_amount == 0 {
+            return 0;
+        }
+
+        match self.curve() {
+            PoolCurve::ConstantProduct => mul_div(amount, reciever_pool_amount, sender_pool_amount),
+            PoolCurve::Weighted(weight_1) => {
+                let (weight_in, weight_out) = match sender_is_token_1 {
+                    true => (weight_1, 100 - weight_1),
+                    false => (100 - weight_1, weight_1),
+                };
+
+                mul_div(
+                    amount,
+                    reciever_pool_amount * weight_in as u128,
+                    sender_pool_amount * weight_out as u128,
+                )
+            }
+            PoolCurve::StableSwap(_) => {
+                // Marginal price from a swap of a ten thousandth of the pool
+                let probe = (sender_pool_amount / 10_000).max(1);
+                let probe_out = match self.curve_output(sender_is_token_1, probe) {
+                    Ok(probe_out) => probe_out as u128,
+                    Err(_) => return 0,
+                };
+
+                mul_div(amount, probe_out, probe)
+            }
+        }
+    }
+
+    /// Value of token amounts in token 1 at the current pool price
+    pub fn value_in_token_1(&self, token_1: u64, token_2: u64) -> u128 {
+        return token_1 as u128 + self.spot_output(false, token_2 as u128);
+    }
+
+    /// Quote a swap of `provided_amount` of `sender_contract_id` into the other side of the pool.
+    /// This is the same calculation `swap_lp` settles with.
+    pub fn quote_swap(
+        &self,
+        sender_contract_id: &str,
+        provided_amount: u64,
+        tolerance_bps: u64,
+    ) -> Result<SwapQuote, String> {
+        let sender_is_token_1;
+        let reciever_contract_id;
+        if sender_contract_id == self.contract_id_1 {
+            sender_is_token_1 = true;
+            reciever_contract_id = self.contract_id_2.clone();
+        } else if sender_contract_id == self.contract_id_2 {
+            sender_is_token_1 = false;
+            reciever_contract_id = self.contract_id_1.clone();
+        } else {
+            return Err("contract_id not associated with this liquidity pool".to_string());
+        }
+
+        let amount_out = self.swap_output(sender_is_token_1, provided_amount)?;
+
+        let provided_after_fee =
+            provided_amount as u128 * (BPS_SCALE - self.fee_bps()) as u128 / BPS_SCALE as u128;
+        let fee = provided_amount - provided_after_fee as u64;
+        let protocol_fee = self.protocol_fee_amount(fee);
+
+        // Price impact compares the execution price with the spot price before the swap
+        let spot = self.spot_output(sender_is_token_1, provided_after_fee);
+        let price_impact_bps = match (amount_out as u128 * BPS_SCALE as u128).checked_div(spot) {
+            Some(execution) => BPS_SCALE - execution.min(BPS_SCALE as u128) as u64,
+            None => 0,
+        };
+
+        let mut pool_1_after = self.pool_1;
+        let mut pool_2_after = self.pool_2;
+        if sender_is_token_1 {
+            pool_1_after += provided_amount - protocol_fee;
+            pool_2_after -= amount_out;
+        } else {
+            pool_2_after += provided_amount - protocol_fee;
+            pool_1_after -= amount_out;
+        }
+
+        // Settled swaps accept any tolerance, above 10000 bps there is no minimum
+        let minimum_received = (amount_out as u128
+            * BPS_SCALE.saturating_sub(tolerance_bps) as u128
+            / BPS_SCALE as u128) as u64;
+        return Ok(SwapQuote {
+            from_contract_id: sender_contract_id.to_string(),
+            to_contract_id: reciever_contract_id,
+            amount_in: provided_amount,
+            amount_out,
+            fee,
+            protocol_fee,
+            price_impact_bps,
+            pool_1_after,
+            pool_2_after,
+            tolerance_bps,
+            minimum_received,
+        });
+    }
+
+    pub fn swap_fee(&self, provided_amount: u64) -> u64 {
+        let provided_after_fee = provided_amount as u128
+            * (BPS_SCALE - self.fee_bps().min(BPS_SCALE)) as u128
+            / BPS_SCALE as u128;
+        return provided_amount - provided_after_fee as u64;
+    }
+
+    // The protocol share rounds down so any remainder of the fee stays in the pool
+    pub fn protocol_fee_amount(&self, fee: u64) -> u64 {
+        match &self.protocol_fee {
+            Some(protocol_fee) => {
+                (fee as u128 * protocol_fee.share_bps as u128 / BPS_SCALE as u128) as u64
+            }
+            None => 0,
+        }
+    }
+
+    pub fn paired_amount(&self, provided_amount: u64) -> Result<u64, String> {
+        // Weighted and stable pools take deposits at the pool balance so the price is kept,
+        // the ratio set at mint only applies to the first deposit
+        let mut paired_amount =
+            (provided_amount as u128 * self.ratio_fixed()).div_ceil(RATIO_SCALE);
+        if self.curve() != PoolCurve::ConstantProduct && self.pool_1 > 0 {
+            paired_amount =
+                (provided_amount as u128 * self.pool_2 as u128).div_ceil(self.pool_1 as u128);
+        }
+
+        if paired_amount > u64::MAX as u128 {
+            return Err("paired amount overflow".to_string());
+        }
+
+        return Ok(paired_amount as u64);
+    }
+
+    /// Plan a zap of `amount` of one token, swapping the smallest portion that still pairs the
+    /// rest of the deposit so the refund of unpaired token 2 is as small as possible
+    pub fn zap_plan(
+        &self,
+        sender_is_token_1: bool,
+        amount: u64,
+        supply: u64,
+    ) -> Result<ZapPlan, String> {
+        let sender_contract_id = match sender_is_token_1 {
+            true => self.contract_id_1.clone(),
+            false => self.contract_id_2.clone(),
+        };
+
+        if self.pool_1 == 0 || self.pool_2 == 0 {
+            return Err("zap_plan: pool is empty".to_string());
+        }
+
+        let plan_for = |swap_in: u64| -> Option<ZapPlan> {
+            let mut pool_after = self.clone();
+            let mut swap_out = 0;
+            if swap_in > 0 {
+                let quote = match self.quote_swap(&sender_contract_id, swap_in, 0) {
+                    Ok(quote) => quote,
+                    Err(_) => return None,
+                };
+
+                swap_out = quote.amount_out;
+                pool_after.pool_1 = quote.pool_1_after;
+                pool_after.pool_2 = quote.pool_2_after;
+            }
+
+            let (provided_1, available_2) = match sender_is_token_1 {
+                true => (amount - swap_in, swap_out),
+                false => (swap_out, amount - swap_in),
+            };
+
+            let provided_2 = match pool_after.paired_amount(provided_1) {
+                Ok(provided_2) => provided_2,
+                Err(_) => return None,
+            };
+
+            if provided_1 == 0 || provided_2 == 0 || provided_2 > available_2 {
+                return None;
+            }
+
+            return Some(ZapPlan {
+                sender_contract_id: sender_contract_id.clone(),
+                amount,
+                swap_in,
+                swap_out,
+                provided_1,
+                provided_2,
+                lp_tokens: pool_after.deposit_lp_tokens(provided_1, provided_2, supply),
+                refund_2: available_2 - provided_2,
+            });
+        };
+
+        // Swapping more token 1 buys more token 2 to pair, swapping more token 2 needs more
+        // token 2 to pair what it bought, so search for the first or last workable swap
+        let (mut low, mut high) = (0, amount);
+        if !sender_is_token_1 {
+            // Nothing pairs until the swap buys some token 1, so start from the smallest swap that does
+            let buys_token_1 = |swap_in: u64| {
+                return self
+                    .quote_swap(&sender_contract_id, swap_in, 0)
+                    .is_ok_and(|quote| quote.amount_out > 0);
+            };
+
+            if !buys_token_1(amount) {
+                return Err("zap_plan: amount is too small to zap".to_string());
+            }
+
+            while low < high {
+                let middle = low + (high - low) / 2;
+                match buys_token_1(middle) {
+                    true => high = middle,
+                    false => low = middle + 1,
+                }
+            }
+
+            high = amount;
+        }
+
+        while low < high {
+            let middle = match sender_is_token_1 {
+                true => low + (high - low) / 2,
+                false => low + (high - low).div_ceil(2),
+            };
+
+            match (sender_is_token_1, plan_for(middle).is_some()) {
+                (true, true) => high = middle,
+                (true, false) => low = middle + 1,
+                (false, true) => low = middle,
+                (false, false) => high = middle - 1,
+            }
+        }
+
+        match plan_for(low) {
+            Some(plan) if plan.lp_tokens > 0 => return Ok(plan),
+            _ => return Err("zap_plan: amount is too small to zap".to_string()),
+        }
+    }
+
+    pub fn deposit_lp_tokens(&self, provided_amount: u64, paired_amount: u64, supply: u64) -> u64 {
+        if self.curve() == PoolCurve::ConstantProduct || supply == 0 || self.pool_1 == 0 {
+            return provided_amount + paired_amount;
+        }
+
+        return (supply as u128 * provided_amount as u128 / self.pool_1 as u128) as u64;
+    }
+
+    pub fn liquidation_amounts(
+        &self,
+        claim_amount: u64,
+        supply: u64,
+    ) -> Result<(u64, u64), String> {
+        if supply == 0 || claim_amount > supply {
+            return Err("claim amount exceeds lp supply".to_string());
+        }
+
+        let token_1 = self.pool_1 as u128 * claim_amount as u128 / supply as u128;
+        let token_2 = self.pool_2 as u128 * claim_amount as u128 / supply as u128;
+        return Ok((token_1 as u64, token_2 as u64));
+    }
+
+    /// Curve invariant in token units: sqrt(k), the weighted geometric mean or D
+    pub fn invariant(&self) -> u128 {
+        let pool_1 = self.pool_1 as u128;
+        let pool_2 = self.pool_2 as u128;
+        match self.curve() {
+            PoolCurve::ConstantProduct => (pool_1 * pool_2).isqrt(),
+            PoolCurve::Weighted(weight_1) => {
+                if pool_1 == 0 || pool_2 == 0 {
+                    return 0;
+                }
+
+                // x^a * y^b with a + b = 1, written as y * (x / y)^a to stay in fixed point
+                if pool_1 <= pool_2 {
+                    let ratio = pool_1 * CURVE_SCALE / pool_2;
+                    pool_2 * pow_ratio_ceil(ratio, weight_1, 100) / CURVE_SCALE
+                } else {
+                    let ratio = pool_2 * CURVE_SCALE / pool_1;
+                    pool_1 * pow_ratio_ceil(ratio, 100 - weight_1, 100) / CURVE_SCALE
+                }
+            }
+            PoolCurve::StableSwap(amplification) => {
+                stable_swap_invariant(pool_1, pool_2, amplification)
+            }
+        }
+    }
+
+    // Swap fees stay in the pool, so the invariant per lp token only grows as fees accrue
+    pub fn growth_index(&self, supply: u64) -> u128 {
+        if supply == 0 {
+            return RATIO_SCALE;
+        }
+
+        return self.invariant() * RATIO_SCALE / supply as u128;
+    }
+
+    pub fn position(
+        &self,
+        utxo: &String,
+        lp_tokens: u64,
+        provider: Option<&LiquidityProvider>,
+        supply: u64,
+    ) -> Result<LiquidityPosition, String> {
+        let (token_1_value, token_2_value) = match self.liquidation_amounts(lp_tokens, supply) {
+            Ok(amounts) => amounts,
+            Err(err) => return Err(format!("position: {}", err)),
+        };
+
+        let value_in_token_1 = |token_1: u64, token_2: u64| self.value_in_token_1(token_1, token_2);
+        let mut position = LiquidityPosition {
+            utxo: utxo.to_string(),
+            lp_tokens,
+            share_bps: (lp_tokens as u128 * BPS_SCALE as u128 / supply as u128) as u64,
+            token_1_value,
+            token_2_value,
+            position_value_token_1: value_in_token_1(token_1_value, token_2_value) as u64,
+            ..Default::default()
+        };
+
+        let provider = match provider {
+            Some(provider) => provider,
+            None => return Ok(position),
+        };
+
+        let growth = self.growth_index(supply);
+        let mut token_1_fees: u64 = 0;
+        let mut token_2_fees: u64 = 0;
+        if growth > provider.entry_growth {
+            let accrued = growth - provider.entry_growth;
+            token_1_fees = (token_1_value as u128 * accrued / growth) as u64;
+            token_2_fees = (token_2_value as u128 * accrued / growth) as u64;
+        }
+
+        let hold_value = value_in_token_1(provider.token_1_deposited, provider.token_2_deposited);
+        let value_excluding_fees =
+            value_in_token_1(token_1_value - token_1_fees, token_2_value - token_2_fees);
+        let mut impermanent_loss_bps: i64 = 0;
+        if hold_value > 0 {
+            impermanent_loss_bps = ((hold_value as i128 - value_excluding_fees as i128)
+                * BPS_SCALE as i128
+                / hold_value as i128) as i64;
+        }
+
+        position.provided_block_height = Some(provider.provided_block_height);
+        position.token_1_deposited = Some(provider.token_1_deposited);
+        position.token_2_deposited = Some(provider.token_2_deposited);
+        position.token_1_fees = Some(token_1_fees);
+        position.token_2_fees = Some(token_2_fees);
+        position.hold_value_token_1 = Some(hold_value as u64);
+        position.impermanent_loss_bps = Some(impermanent_loss_bps);
+        return Ok(position);
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        return a;
+    }
+
+    return gcd(b, a % b);
+}
+
+fn mul_scaled_floor(a: u128, b: u128) -> u128 {
+    return a * b / CURVE_SCALE;
+}
+
+fn mul_scaled_ceil(a: u128, b: u128) -> u128 {
+    return (a * b).div_ceil(CURVE_SCALE);
+}
+
+/// `base^(numerator / denominator)` for a fixed point `base` of at most one, rounded up so
+/// weighted swaps never pay out more than the curve allows. The root is taken before the power
+/// so small bases do not underflow.
+fn pow_ratio_ceil(base: u128, numerator: u64, denominator: u64) -> u128 {
+    let divisor = gcd(numerator, denominator);
+    let (power, root) = (numerator / divisor, denominator / divisor);
+
+    // Smallest value whose root-th power reaches the base
+    let (mut low, mut high) = (0, CURVE_SCALE);
+    while low < high {
+        let middle = (low + high) / 2;
+        let mut value = CURVE_SCALE;
+        for _ in 0..root {
+            value = mul_scaled_floor(value, middle);
+        }
+
+        if value >= base {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    let mut raised = CURVE_SCALE;
+    for _ in 0..power {
+        raised = mul_scaled_ceil(raised, low);
+    }
+
+    return raised;
+}
+
+// Uint256 stores little endian u64 words
+fn to_uint256(value: u128) -> Uint256 {
+    return Uint256([value as u64, (value >> 64) as u64, 0, 0]);
+}
+
+fn from_uint256(value: Uint256) -> u128 {
+    return (value.0[1] as u128) << 64 | value.0[0] as u128;
+}
+
+/// `a * b / c` with a 256 bit product, saturating at u128::MAX
+fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    let result = to_uint256(a) * to_uint256(b) / to_uint256(c);
+    if result.0[2] != 0 || result.0[3] != 0 {
+        return u128::MAX;
+    }
+
+    return from_uint256(result);
+}
+
+/// StableSwap invariant D for two balances, found by Newton's method as in Curve
+fn stable_swap_invariant(balance_1: u128, balance_2: u128, amplification: u64) -> u128 {
+    let sum = balance_1 + balance_2;
+    if balance_1 == 0 || balance_2 == 0 {
+        return sum;
+    }
+
+    let two = to_uint256(2);
+    let ann = to_uint256(amplification as u128 * 4);
+    let (x, y, sum) = (
+        to_uint256(balance_1),
+        to_uint256(balance_2),
+        to_uint256(sum),
+    );
+    let mut d = sum;
+    for _ in 0..STABLE_SWAP_ITERATIONS {
+        // A single division keeps D^3 / 4xy exact to the unit for skewed balances
+        let d_p = d * d * d / (x * y * to_uint256(4));
+        let previous = d;
+        d = (ann * sum + d_p * two) * d / ((ann - to_uint256(1)) * d + d_p * to_uint256(3));
+        let difference = match d > previous {
+            true => d - previous,
+            false => previous - d,
+        };
+
+        if difference <= to_uint256(1) {
+            break;
+        }
+    }
+
+    return from_uint256(d);
+}
+
+/// Balance of the other token that keeps the invariant when one balance becomes `balance`
+fn stable_swap_balance(balance: u128, invariant: u128, amplification: u64) -> u128 {
+    let two = to_uint256(2);
+    let ann = to_uint256(amplification as u128 * 4);
+    let (x, d) = (to_uint256(balance), to_uint256(invariant));
+    let c = d * d * d / (x * ann * to_uint256(4));
+    let b = x + d / ann;
+    let mut y = d;
+    for _ in 0..STABLE_SWAP_ITERATIONS {
+        let previous = y;
+        y = (y * y + c) / (y * two + b - d);
+        let difference = match y > previous {
+            true => y - previous,
+            false => previous - y,
+        };
+
+        if difference <= to_uint256(1) {
+            break;
+        }
+    }
+
+    return from_uint256(y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOLS: [(u64, u64); 4] = [
+        (1_000, 1_000),
+        (1_000_000, 3_333_333),
+        (987_654_321, 12_345),
+        (u32::MAX as u64, 7_777_777_777),
+    ];
+    const AMOUNTS: [u64; 6] = [1, 7, 999, 123_457, 10_000_000, 5_000_000_000];
+
+    fn pool(pool_1: u64, pool_2: u64) -> LiquidityPool {
+        return LiquidityPool {
+            contract_id_1: "token_1".to_string(),
+            contract_id_2: "token_2".to_string(),
+            pool_1,
+            pool_2,
+            fee_bps: Some(30),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn swap_never_decreases_k() {
+        for (pool_1, pool_2) in POOLS {
+            for share_bps in [0, 2_500, BPS_SCALE] {
+                let mut pool = pool(pool_1, pool_2);
+                pool.protocol_fee = Some(ProtocolFee {
+                    share_bps,
+                    ..Default::default()
+                });
+
+                let k_before = pool_1 as u128 * pool_2 as u128;
+                for amount in AMOUNTS {
+                    for sender in ["token_1", "token_2"] {
+                        let quote = pool.quote_swap(sender, amount, 0).unwrap();
+                        let k_after = quote.pool_1_after as u128 * quote.pool_2_after as u128;
+                        assert!(
+                            k_after >= k_before,
+                            "k fell from {} to {} swapping {} {}",
+                            k_before,
+                            k_after,
+                            amount,
+                            sender
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quote_swap_accepts_any_tolerance() {
+        let pool = pool(1_000_000, 1_000_000);
+        let quote = pool.quote_swap("token_1", 1_000, 20_000).unwrap();
+        assert_eq!(quote.minimum_received, 0);
+        assert_eq!(quote.tolerance_bps, 20_000);
+    }
+
+    #[test]
+    fn swap_output_rounds_down() {
+        for (pool_1, pool_2) in POOLS {
+            let pool = pool(pool_1, pool_2);
+            for amount in AMOUNTS {
+                let amount_in = amount as u128 * (BPS_SCALE - 30) as u128 / BPS_SCALE as u128;
+                let amount_out = pool.swap_output(true, amount).unwrap() as u128;
+
+                // out <= pool_2 * in / (pool_1 + in), and within one unit of it
+                let numerator = pool_2 as u128 * amount_in;
+                let denominator = pool_1 as u128 + amount_in;
+                assert!(amount_out * denominator <= numerator);
+                assert!((amount_out + 1) * denominator > numerator);
+            }
+        }
+    }
+
+    #[test]
+    fn swap_output_is_zero_for_dust() {
+        let pool = pool(1_000_000, 1_000);
+        assert_eq!(pool.swap_output(true, 1).unwrap(), 0);
+        assert_eq!(pool.swap_output(true, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn swap_output_rejects_full_fee() {
+        let mut pool = pool(1_000, 1_000);
+        pool.fee_bps = Some(BPS_SCALE);
+        assert!(pool.swap_output(true, 100).is_err());
+    }
+
+    #[test]
+    fn paired_amount_rounds_up() {
+        for ratio_fixed in [
+            1,
+            333_333_333,
+            RATIO_SCALE,
+            2_718_281_828,
+            7 * RATIO_SCALE + 1,
+        ] {
+            let mut pool = pool(0, 0);
+            pool.ratio_fixed = Some(ratio_fixed);
+            for amount in AMOUNTS {
+                let paired = pool.paired_amount(amount).unwrap() as u128;
+                let exact = amount as u128 * ratio_fixed;
+                assert!(paired * RATIO_SCALE >= exact);
+                assert!((paired - 1) * RATIO_SCALE < exact);
+            }
+        }
+    }
+
+    #[test]
+    fn paired_amount_rejects_overflow() {
+        let mut pool = pool(0, 0);
+        pool.ratio_fixed = Some(2 * RATIO_SCALE);
+        assert!(pool.paired_amount(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn liquidation_amounts_round_down() {
+        for (pool_1, pool_2) in POOLS {
+            let pool = pool(pool_1, pool_2);
+            let supply = 3_000_017;
+            for claim in [1, 2, 999_999, supply - 1, supply] {
+                let (token_1, token_2) = pool.liquidation_amounts(claim, supply).unwrap();
+                assert!(token_1 as u128 * supply as u128 <= pool_1 as u128 * claim as u128);
+                assert!(token_2 as u128 * supply as u128 <= pool_2 as u128 * claim as u128);
+                assert!(token_1 <= pool_1 && token_2 <= pool_2);
+            }
+
+            assert_eq!(
+                pool.liquidation_amounts(supply, supply).unwrap(),
+                (pool_1, pool_2)
+            );
+        }
+    }
+
+    #[test]
+    fn liquidation_amounts_reject_bad_claims() {
+        let pool = pool(1_000, 1_000);
+        assert!(pool.liquidation_amounts(1, 0).is_err());
+        assert!(pool.liquidation_amounts(11, 10).is_err());
+    }
+
+    #[test]
+    fn pow_ratio_ceil_rounds_up() {
+        assert_eq!(pow_ratio_ceil(CURVE_SCALE, 3, 7), CURVE_SCALE);
+        assert_eq!(pow_ratio_ceil(CURVE_SCALE / 4, 1, 2), CURVE_SCALE / 2);
+        assert_eq!(pow_ratio_ceil(CURVE_SCALE / 3, 5, 5), CURVE_SCALE / 3);
+
+        // The smallest root whose square reaches the base
+        for base in [1, 3, 123_456_789, CURVE_SCALE / 3, CURVE_SCALE - 1] {
+            let root = pow_ratio_ceil(base, 1, 2);
+            assert!(root * root / CURVE_SCALE >= base);
+            assert!((root - 1) * (root - 1) / CURVE_SCALE < base);
+        }
+    }
+
+    // Largest swap of token 2 whose token 1 can be paired with the token 2 left over
+    fn largest_token_2_zap_swap(pool: &LiquidityPool, amount: u64) -> Option<u64> {
+        return (1..=amount).rev().find(|swap_in| {
+            let quote = match pool.quote_swap("token_2", *swap_in, 0) {
+                Ok(quote) if quote.amount_out > 0 => quote,
+                _ => return false,
+            };
+
+            let pool_after = LiquidityPool {
+                pool_1: quote.pool_1_after,
+                pool_2: quote.pool_2_after,
+                ..pool.clone()
+            };
+            return pool_after
+                .paired_amount(quote.amount_out)
+                .is_ok_and(|paired| paired <= amount - swap_in);
+        });
+    }
+
+    #[test]
+    fn token_2_zaps_search_from_the_smallest_swap_that_buys_token_1() {
+        // A unit of token 1 costs a thousand of token 2, so small swaps of token 2 buy nothing
+        let pool = LiquidityPool {
+            ratio_fixed: Some(1_000 * RATIO_SCALE),
+            ..pool(1_000, 1_000_000)
+        };
+
+        for amount in 2_000..2_100 {
+            let plan = pool.zap_plan(false, amount, 1_000_000);
+            match largest_token_2_zap_swap(&pool, amount) {
+                Some(swap_in) => assert_eq!(plan.map(|plan| plan.swap_in), Ok(swap_in)),
+                None => assert!(plan.is_err(), "{} should be too small to zap", amount),
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_swap_never_decreases_invariant() {
+        for weight_1 in [1, 20, 50, 80, 99] {
+            for (pool_1, pool_2) in POOLS {
+                let mut pool = pool(pool_1, pool_2);
+                pool.curve = Some(PoolCurve::Weighted(weight_1));
+                pool.fee_bps = Some(0);
+                let invariant_before = pool.invariant();
+                for amount in AMOUNTS {
+                    for sender in ["token_1", "token_2"] {
+                        let quote = pool.quote_swap(sender, amount, 0).unwrap();
+                        let mut pool_after = pool.clone();
+                        pool_after.pool_1 = quote.pool_1_after;
+                        pool_after.pool_2 = quote.pool_2_after;
+                        assert!(
+                            pool_after.invariant() >= invariant_before,
+                            "weight {} swapping {} {} in {:?}",
+                            weight_1,
+                            amount,
+                            sender,
+                            (pool_1, pool_2)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn even_weighted_swap_pays_at_most_constant_product() {
+        for (pool_1, pool_2) in POOLS {
+            let constant_product = pool(pool_1, pool_2);
+            let mut weighted = pool(pool_1, pool_2);
+            weighted.curve = Some(PoolCurve::Weighted(50));
+            for amount in AMOUNTS {
+                let expected = constant_product.swap_output(true, amount).unwrap();
+                let amount_out = weighted.swap_output(true, amount).unwrap();
+                assert!(amount_out <= expected);
+                assert!(expected - amount_out <= 1 + expected / 1_000_000_000);
+            }
+        }
+    }
+
+    #[test]
+    fn stable_swap_invariant_converges() {
+        for amplification in [1, 10, 100, MAX_AMPLIFICATION] {
+            assert_eq!(stable_swap_invariant(500, 500, amplification), 1_000);
+            assert_eq!(stable_swap_invariant(0, 500, amplification), 500);
+
+            for (balance_1, balance_2) in POOLS {
+                let (balance_1, balance_2) = (balance_1 as u128, balance_2 as u128);
+                let invariant = stable_swap_invariant(balance_1, balance_2, amplification);
+
+                // D sits between the constant product and constant sum invariants
+                assert!(invariant <= balance_1 + balance_2);
+                assert!(invariant >= 2 * (balance_1 * balance_2).isqrt());
+
+                // Solving back for the other balance lands within a unit of it
+                let balance = stable_swap_balance(balance_1, invariant, amplification);
+                assert!(balance.abs_diff(balance_2) <= 1 + balance_2 / 1_000_000_000);
+            }
+        }
+    }
+
+    #[test]
+    fn stable_swap_matches_exact_solution() {
+        // D = 56913219.75 and the output 39117.09 solved to 80 digits for A = 1
+        assert_eq!(stable_swap_invariant(12_345, 987_654_321, 1), 56_913_219);
+        assert_eq!(stable_swap_invariant(987_654_321, 12_345, 1), 56_913_219);
+
+        let mut pool = pool(987_654_321, 12_345);
+        pool.curve = Some(PoolCurve::StableSwap(1));
+        pool.fee_bps = Some(0);
+        let amount_out = pool.swap_output(false, 1).unwrap();
+        assert!(amount_out <= 39_117);
+        assert!(amount_out >= 39_117 - 39_117 / 1_000);
+    }
+
+    #[test]
+    fn stable_swap_never_decreases_invariant() {
+        for amplification in [1, 10, 100, MAX_AMPLIFICATION] {
+            for (pool_1, pool_2) in POOLS {
+                let mut pool = pool(pool_1, pool_2);
+                pool.curve = Some(PoolCurve::StableSwap(amplification));
+                pool.fee_bps = Some(0);
+                let invariant_before = pool.invariant();
+                for amount in AMOUNTS {
+                    for sender in ["token_1", "token_2"] {
+                        let quote = pool.quote_swap(sender, amount, 0).unwrap();
+                        let mut pool_after = pool.clone();
+                        pool_after.pool_1 = quote.pool_1_after;
+                        pool_after.pool_2 = quote.pool_2_after;
+                        assert!(
+                            pool_after.invariant() >= invariant_before,
+                            "amplification {} swapping {} {} in {:?}",
+                            amplification,
+                            amount,
+                            sender,
+                            (pool_1, pool_2)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stable_swap_pays_close_to_one_for_one_when_balanced() {
+        let mut pool = pool(1_000_000_000, 1_000_000_000);
+        pool.curve = Some(PoolCurve::StableSwap(100));
+        pool.fee_bps = Some(0);
+        let amount_out = pool.swap_output(true, 1_000_000).unwrap();
+        assert!(amount_out < 1_000_000);
+        assert!(amount_out > 999_000);
+    }
+
+    #[test]
+    fn spot_output_does_not_overflow() {
+        let pool = pool(1, u64::MAX);
+        assert_eq!(
+            pool.spot_output(true, u64::MAX as u128),
+            u64::MAX as u128 * u64::MAX as u128
+        );
+        assert_eq!(pool.spot_output(true, u128::MAX), u128::MAX);
+
+        let mut weighted = pool.clone();
+        weighted.curve = Some(PoolCurve::Weighted(99));
+        assert_eq!(weighted.spot_output(true, u128::MAX / 2), u128::MAX);
+        assert_eq!(weighted.spot_output(false, u64::MAX as u128 * 99), 1);
+    }
+
+    #[test]
+    fn settled_drip_status_is_fully_released() {
+        let drip = Drip {
+            block_end: 109,
+            drip_amount: 10,
+            amount: 100,
+            start_block: 100,
+            last_block_dripped: 104,
+            ..Default::default()
+        };
+        let utxo = "txid:0".to_string();
+        let status = drip.status(&utxo);
+        assert_eq!((status.released, status.remaining), (50, 50));
+
+        let mut settled = drip.clone();
+        settled.last_block_dripped = settled.block_end;
+        let status = settled.status(&utxo);
+        assert_eq!((status.released, status.remaining), (100, 0));
+        assert_eq!(status.schedule, VestingSchedule::Linear);
+    }
+
+    #[test]
+    fn drip_events_cover_each_settlement_run() {
+        let early = Drip {
+            block_end: 109,
+            drip_amount: 10,
+            amount: 100,
+            start_block: 100,
+            last_block_dripped: 104,
+            ..Default::default()
+        };
+        let late = Drip {
+            block_end: 115,
+            drip_amount: 5,
+            amount: 50,
+            start_block: 106,
+            last_block_dripped: 105,
+            ..Default::default()
+        };
+        let settled = Drip {
+            last_block_dripped: 109,
+            ..early.clone()
+        };
+
+        let mut contract = SCL01Contract {
+            drips: Some(HashMap::from([
+                ("a:0".to_string(), vec![early, late]),
+                ("b:0".to_string(), vec![settled]),
+            ])),
+            ..Default::default()
+        };
+
+        let event = |from_block, to_block, amount| DripEvent {
+            utxo: "a:0".to_string(),
+            from_block,
+            to_block,
+            amount,
+        };
+        assert_eq!(contract.drip_events(112), vec![event(105, 112, 85)]);
+        assert!(contract.drip(112).is_ok());
+        assert_eq!(contract.drip_events(112), Vec::new());
+        assert_eq!(contract.drip_events(120), vec![event(113, 115, 15)]);
+    }
+
+    fn linear_drip() -> Drip {
+        return Drip {
+            block_end: 109,
+            drip_amount: 10,
+            amount: 100,
+            start_block: 100,
+            last_block_dripped: 100,
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn vesting_schedule_from_payload_checks_the_duration() {
+        let schedule = |value| VestingSchedule::from_payload(value, 100);
+        assert_eq!(schedule("L"), Ok(VestingSchedule::Linear));
+        assert_eq!(
+            schedule(" C:99 "),
+            Ok(VestingSchedule::Cliff { cliff_blocks: 99 })
+        );
+        assert_eq!(
+            schedule("S:100"),
+            Ok(VestingSchedule::Step { step_blocks: 100 })
+        );
+        for value in ["C:100", "S:0", "S:101", "M", "L:5", "C", "C:x", "X:1", ""] {
+            assert!(schedule(value).is_err(), "{} should be rejected", value);
+        }
+
+        assert_eq!(
+            VestingSchedule::from_payload("M", BLOCKS_PER_MONTH),
+            Ok(VestingSchedule::Step {
+                step_blocks: BLOCKS_PER_MONTH
+            })
+        );
+    }
+
+    #[test]
+    fn cliff_releases_nothing_until_it_has_passed() {
+        let drip = Drip {
+            schedule: Some(VestingSchedule::Cliff { cliff_blocks: 3 }),
+            ..linear_drip()
+        };
+        assert_eq!(drip.vested(99), 0);
+        assert_eq!(drip.vested(102), 0);
+        assert_eq!(drip.vested(103), 40);
+        assert_eq!(drip.vested(108), 90);
+        assert_eq!(drip.vested(109), 100);
+    }
+
+    #[test]
+    fn steps_round_down_when_the_duration_is_not_a_multiple_of_the_step() {
+        let drip = Drip {
+            schedule: Some(VestingSchedule::Step { step_blocks: 3 }),
+            ..linear_drip()
+        };
+
+        // Ten blocks in steps of three is four steps, the last one only a block long
+        let vested: Vec<u64> = (100..=109).map(|block| drip.vested(block)).collect();
+        assert_eq!(vested, vec![0, 0, 25, 25, 25, 50, 50, 50, 75, 100]);
+
+        let drip = Drip { amount: 10, ..drip };
+        assert_eq!(drip.vested(102), 2);
+        assert_eq!(drip.vested(109), 10);
+    }
+
+    #[test]
+    fn linear_vesting_matches_the_original_drip_amounts() {
+        for (amount, blocks) in [(100, 10), (1_000, 7), (7, 3), (5, 9), (123_456, 1)] {
+            let drip = Drip {
+                block_end: 100 + blocks - 1,
+                drip_amount: amount / blocks,
+                amount,
+                start_block: 100,
+                last_block_dripped: 100,
+                ..Default::default()
+            };
+
+            // Drips used to release drip_amount a block and the remainder on their last block
+            let mut released = drip.drip_amount;
+            assert_eq!(drip.vested(100), released);
+            for block in 101..=drip.block_end {
+                released += drip.drip_amount;
+                if block == drip.block_end {
+                    released += amount - blocks * drip.drip_amount;
+                }
+
+                assert_eq!(
+                    drip.vested(block),
+                    released,
+                    "{} over {} blocks",
+                    amount,
+                    blocks
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rebase_keeps_what_is_left_to_release() {
+        let drip = Drip {
+            last_block_dripped: 104,
+            ..linear_drip()
+        };
+        let rebased = drip.rebase(104);
+        assert_eq!((rebased.start_block, rebased.amount), (104, 60));
+        for block in 104..=112 {
+            assert_eq!(
+                rebased.vested(block) - rebased.vested(104),
+                drip.vested(block) - drip.vested(104)
+            );
+        }
+
+        let cliff = Drip {
+            schedule: Some(VestingSchedule::Cliff { cliff_blocks: 3 }),
+            ..drip.clone()
+        };
+        assert_eq!(cliff.rebase(104), cliff);
+        assert_eq!(drip.rebase(103), drip);
+    }
+
+    #[test]
+    fn revoke_drip_returns_only_the_unvested_amount() {
+        let (authority, drip_utxo) = ("auth:0".to_string(), "drip:0".to_string());
+        let revocable = Drip {
+            last_block_dripped: 104,
+            revoke_authority: Some(authority.clone()),
+            ..linear_drip()
+        };
+        let kept = Drip {
+            last_block_dripped: 104,
+            ..linear_drip()
+        };
+
+        let mut contract = SCL01Contract {
+            supply: 100,
+            drips: Some(HashMap::from([(drip_utxo.clone(), vec![revocable, kept])])),
+            ..Default::default()
+        };
+        contract.owners.insert(drip_utxo.clone(), 100);
+
+        let (txid, payload) = ("revoke".to_string(), String::new());
+        let (new_authority, receiver) = ("revoke:1".to_string(), "revoke:0".to_string());
+        let other = "other:0".to_string();
+        assert!(contract
+            .revoke_drip(&txid, &payload, &other, &other, &drip_utxo, &receiver, 106)
+            .is_err());
+
+        let revoked = contract
+            .revoke_drip(
+                &txid,
+                &payload,
+                &authority,
+                &new_authority,
+                &drip_utxo,
+                &receiver,
+                106,
+            )
+            .unwrap();
+
+        // 20 vested between the last settlement and the revoke, the 30 still unvested goes back
+        assert_eq!(revoked, ((drip_utxo.clone(), 120, true), (receiver, 30)));
+        assert_eq!(contract.supply, 150);
+        let drips = &contract.drips.as_ref().unwrap()[&drip_utxo];
+        assert_eq!((drips.len(), drips[0].revoke_authority.clone()), (1, None));
+    }
+
+    const MERKLE_LEAF_A: &str = "41911beb5d55314c3d0a69eb160d94a26781f27eee074f6ddd6d03a4f7d777ec";
+    const MERKLE_LEAF_B: &str = "07fc4a981b56a0042b1aad534fdfdf1d3ba2db8d0545e92c39b9593697552349";
+    const MERKLE_LEAF_C: &str = "2696cd0c82985daff27d3f8f55b56c3a2ddc52cdfd749fd74e7758e111802518";
+    const MERKLE_NODE_AB: &str = "46ae2f0e63e2e0164af98b53c59c85ed1487f3faa5b090d558a48695f342f941";
+    const MERKLE_ROOT: &str = "1e2950d430b8c718705dfeb37c592f5e3499471cadcc4b874c763e6fc4ed3b74";
+
+    fn merkle_airdrop() -> MerkleAirdrop {
+        return MerkleAirdrop {
+            pool_amount: 4_000,
+            merkle_root: MERKLE_ROOT.to_string(),
+            leaf_count: 3,
+            ..Default::default()
+        };
+    }
+
+    fn proof(hashes: &[&str]) -> Vec<String> {
+        return hashes.iter().map(|hash| hash.to_string()).collect();
+    }
+
+    #[test]
+    fn leaf_hash_matches_vectors() {
+        let leaf = MerkleAirdrop::leaf_hash(&0, &"bc1qalice".to_string(), &1_000);
+        assert_eq!(hex::encode(leaf), MERKLE_LEAF_A);
+        let leaf = MerkleAirdrop::leaf_hash(&1, &"bc1qbob".to_string(), &2_500);
+        assert_eq!(hex::encode(leaf), MERKLE_LEAF_B);
+        let leaf = MerkleAirdrop::leaf_hash(&2, &"bc1qcarol".to_string(), &500);
+        assert_eq!(hex::encode(leaf), MERKLE_LEAF_C);
+    }
+
+    #[test]
+    fn node_hash_matches_vectors_in_either_order() {
+        let a = hex::decode(MERKLE_LEAF_A).unwrap();
+        let b = hex::decode(MERKLE_LEAF_B).unwrap();
+        let c = hex::decode(MERKLE_LEAF_C).unwrap();
+        assert_eq!(
+            hex::encode(MerkleAirdrop::node_hash(&a, &b)),
+            MERKLE_NODE_AB
+        );
+        assert_eq!(
+            hex::encode(MerkleAirdrop::node_hash(&b, &a)),
+            MERKLE_NODE_AB
+        );
+
+        let ab = hex::decode(MERKLE_NODE_AB).unwrap();
+        assert_eq!(hex::encode(MerkleAirdrop::node_hash(&ab, &c)), MERKLE_ROOT);
+        assert_eq!(hex::encode(MerkleAirdrop::node_hash(&c, &ab)), MERKLE_ROOT);
+    }
+
+    #[test]
+    fn verify_proof_accepts_every_leaf() {
+        let airdrop = merkle_airdrop();
+        let alice = proof(&[MERKLE_LEAF_B, MERKLE_LEAF_C]);
+        let bob = proof(&[MERKLE_LEAF_A, MERKLE_LEAF_C]);
+        let carol = proof(&[MERKLE_NODE_AB]);
+        assert_eq!(
+            airdrop.verify_proof(&0, &"bc1qalice".to_string(), &1_000, &alice),
+            Ok(true)
+        );
+        assert_eq!(
+            airdrop.verify_proof(&1, &"bc1qbob".to_string(), &2_500, &bob),
+            Ok(true)
+        );
+        assert_eq!(
+            airdrop.verify_proof(&2, &"bc1qcarol".to_string(), &500, &carol),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn verify_proof_rejects_altered_leaves() {
+        let airdrop = merkle_airdrop();
+        let alice = proof(&[MERKLE_LEAF_B, MERKLE_LEAF_C]);
+        let address = "bc1qalice".to_string();
+        assert_eq!(
+            airdrop.verify_proof(&0, &address, &1_001, &alice),
+            Ok(false)
+        );
+        assert_eq!(
+            airdrop.verify_proof(&1, &address, &1_000, &alice),
+            Ok(false)
+        );
+        assert_eq!(
+            airdrop.verify_proof(&0, &"bc1qbob".to_string(), &1_000, &alice),
+            Ok(false)
+        );
+        assert_eq!(
+            airdrop.verify_proof(&0, &address, &1_000, &proof(&[MERKLE_LEAF_B])),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn verify_proof_rejects_malformed_proofs() {
+        let airdrop = merkle_airdrop();
+        let address = "bc1qalice".to_string();
+        assert!(airdrop
+            .verify_proof(&0, &address, &1_000, &proof(&["zz"]))
+            .is_err());
+        assert!(airdrop
+            .verify_proof(&0, &address, &1_000, &proof(&["00ff"]))
+            .is_err());
+
+        let too_long = vec![MERKLE_LEAF_B.to_string(); MAX_MERKLE_PROOF_LENGTH + 1];
+        assert!(airdrop
+            .verify_proof(&0, &address, &1_000, &too_long)
+            .is_err());
+    }
+
+    #[test]
+    fn dge_allocation_caps_by_max_drop_and_pool() {
+        let dge = DGE {
+            pool_amount: 1_000,
+            sats_rate: 3,
+            max_drop: 100,
+            ..Default::default()
+        };
+        assert_eq!(dge.allocation(30, 0), (30, 10, "over_max_drop"));
+        assert_eq!(dge.allocation(500, 0), (300, 100, "over_max_drop"));
+        assert_eq!(dge.allocation(2, 0).1, 0);
+
+        let dge = DGE {
+            current_amount_dropped: 950,
+            ..dge
+        };
+        assert_eq!(dge.allocation(300, 0), (150, 50, "over_pool"));
+    }
+
+    fn mintable_contract() -> SCL01Contract {
+        let mut contract = SCL01Contract {
+            supply: 1_000,
+            max_supply: Some(2_000),
+            mint_authority: Some("mint:1".to_string()),
+            minted: Some(1_000),
+            ..Default::default()
+        };
+        contract.owners.insert("mint:0".to_string(), 1_000);
+        return contract;
+    }
+
+    #[test]
+    fn mint_supply_is_capped_by_everything_minted() {
+        let mut contract = mintable_contract();
+        let (txid, payload) = ("tx".to_string(), String::new());
+        let (authority, receiver) = ("mint:1".to_string(), "tx:1".to_string());
+
+        // Tokens locked in a drip or airdrop leave the circulating supply but not the minted total
+        contract.supply = 400;
+        assert!(contract
+            .mint_supply(
+                &txid,
+                &payload,
+                &authority,
+                &"tx:0".to_string(),
+                &receiver,
+                1_001
+            )
+            .is_err());
+        assert_eq!(
+            contract.mint_supply(
+                &txid,
+                &payload,
+                &authority,
+                &"tx:0".to_string(),
+                &receiver,
+                1_000
+            ),
+            Ok(1_000)
+        );
+        assert_eq!((contract.supply, contract.minted), (1_400, Some(2_000)));
+        assert_eq!(contract.mint_authority, Some("tx:0".to_string()));
+
+        let next = "next:0".to_string();
+        assert!(contract
+            .mint_supply(&txid, &payload, &"tx:0".to_string(), &next, &next, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn mint_supply_requires_the_authority() {
+        let mut contract = mintable_contract();
+        let (txid, payload) = ("tx".to_string(), String::new());
+        let other = "other:0".to_string();
+        assert!(contract
+            .mint_supply(&txid, &payload, &other, &other, &other, 1)
+            .is_err());
+        assert!(contract.renounce_mint(&txid, &payload, &other).is_err());
+        assert_eq!(contract.mint_authority, Some("mint:1".to_string()));
+    }
+
+    #[test]
+    fn renounce_mint_fixes_the_cap_at_the_minted_total() {
+        let mut contract = mintable_contract();
+        contract.supply = 250;
+        let (txid, payload) = ("tx".to_string(), String::new());
+        assert_eq!(
+            contract.renounce_mint(&txid, &payload, &"mint:1".to_string()),
+            Ok(())
+        );
+        assert_eq!(contract.max_supply, Some(1_000));
+        assert_eq!(contract.mint_authority, None);
+
+        let authority = "mint:1".to_string();
+        assert!(contract
+            .mint_supply(&txid, &payload, &authority, &authority, &authority, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn dge_allocation_clips_to_the_hard_cap() {
+        let dge = DGE {
+            pool_amount: 1_000,
+            sats_rate: 3,
+            max_drop: 100,
+            hard_cap: Some(100),
+            sats_raised: Some(90),
+            ..Default::default()
+        };
+        assert_eq!(dge.allocation(30, 0), (10, 3, "over_hard_cap"));
+        assert_eq!(dge.allocation(6, 0), (6, 2, "over_max_drop"));
+    }
+
+    fn soft_cap_contract(sats_raised: u64) -> SCL01Contract {
+        let mut contract = SCL01Contract::default();
+        let dge = DGE {
+            pool_amount: 1_000,
+            sats_rate: 1,
+            max_drop: 1_000,
+            drip_duration: 10,
+            end_block: Some(100),
+            soft_cap: Some(500),
+            ..Default::default()
+        };
+        let mut dges = HashMap::new();
+        dges.insert("dge:0".to_string(), dge);
+        contract.dges = Some(dges);
+
+        let (txid, payload, dge_id) = ("a".to_string(), String::new(), "dge:0".to_string());
+        let (receiver, donater) = ("a:1".to_string(), "bc1qdonor".to_string());
+        let claimed = contract
+            .claim_dge(
+                &txid,
+                &payload,
+                &dge_id,
+                &receiver,
+                &donater,
+                sats_raised,
+                &None,
+                90,
+                90,
+            )
+            .unwrap();
+        assert_eq!(claimed.2, 0);
+        assert_eq!(contract.owners.get(&receiver), None);
+        return contract;
+    }
+
+    #[test]
+    fn dge_soft_cap_releases_held_tokens_once_met() {
+        let mut contract = soft_cap_contract(500);
+        assert_eq!(contract.settle_dges(100), None);
+        assert_eq!(
+            contract.settle_dges(105),
+            Some(vec![("a:1".to_string(), 50, true)])
+        );
+        assert_eq!(contract.settle_dges(106), None);
+
+        let drip = &contract.drips.as_ref().unwrap()["a:1"][0];
+        assert_eq!(
+            (drip.start_block, drip.block_end, drip.amount),
+            (101, 110, 500)
+        );
+        let dge = &contract.dges.as_ref().unwrap()["dge:0"];
+        assert_eq!(
+            (dge.soft_cap_met, dge.held.clone(), dge.refunds.clone()),
+            (Some(true), None, None)
+        );
+    }
+
+    #[test]
+    fn dge_soft_cap_refunds_every_donation_when_missed() {
+        let mut contract = soft_cap_contract(499);
+        assert_eq!(contract.settle_dges(101), Some(Vec::new()));
+        assert_eq!(contract.supply, 0);
+        assert!(contract.drips.as_ref().unwrap().is_empty());
+
+        let dge = &contract.dges.as_ref().unwrap()["dge:0"];
+        assert_eq!(
+            (dge.soft_cap_met, dge.current_amount_dropped),
+            (Some(false), 0)
+        );
+        let refunds = dge.refunds.clone().unwrap();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(
+            (refunds[0].amount, refunds[0].reason.as_str()),
+            (499, "soft_cap_not_met")
+        );
+    }
+
+    fn collection_contract() -> SCL01Contract {
+        let owner = "owner:0".to_string();
+        let mut nft_tokens = HashMap::new();
+        for token_id in ["c", "a", "b"] {
+            let token = NftToken::from_payload(&format!("{}|Token {}", token_id, token_id), &owner);
+            nft_tokens.insert(token_id.to_string(), token.unwrap());
+        }
+
+        let mut contract = SCL01Contract {
+            supply: 3,
+            nft_tokens: Some(nft_tokens),
+            ..Default::default()
+        };
+        contract.owners.insert(owner, 3);
+        return contract;
+    }
+
+    fn owned_by(contract: &SCL01Contract, utxo: &str) -> Vec<String> {
+        return contract.held_nft_tokens(&[utxo.to_string()]);
+    }
+
+    #[test]
+    fn nft_token_from_payload_reads_hash_uri_and_attributes() {
+        let owner = "owner:0".to_string();
+        let hash = "AB".repeat(32);
+        let token =
+            NftToken::from_payload(&format!(" 7 | Seven | {} | eyes=blue;hat=", hash), &owner)
+                .unwrap();
+        assert_eq!(
+            (token.token_id.as_str(), token.name.as_str()),
+            ("7", "Seven")
+        );
+        assert_eq!(token.content_hash, Some("ab".repeat(32)));
+        assert_eq!(token.uri, None);
+        assert_eq!(token.attributes["eyes"], "blue");
+        assert_eq!(token.attributes["hat"], "");
+        assert_eq!(token.owner, owner);
+
+        let token = NftToken::from_payload("8|Eight|ipfs://eight", &owner).unwrap();
+        assert_eq!(
+            (token.content_hash, token.uri),
+            (None, Some("ipfs://eight".to_string()))
+        );
+
+        assert!(NftToken::from_payload("9", &owner).is_err());
+        assert!(NftToken::from_payload("|Nine", &owner).is_err());
+        assert!(NftToken::from_payload("9|Nine||eyes", &owner).is_err());
+        assert!(NftToken::from_payload("9|Nine||=blue", &owner).is_err());
+    }
+
+    #[test]
+    fn collection_transfers_must_send_every_held_token() {
+        let mut contract = collection_contract();
+        let (txid, payload) = ("tx".to_string(), String::new());
+        let senders = vec!["owner:0".to_string()];
+        let partial = vec![("tx:0".to_string(), 2)];
+        assert!(contract
+            .transfer(&txid, &payload, &senders, &partial, 0)
+            .is_err());
+        assert_eq!(owned_by(&contract, "owner:0"), vec!["a", "b", "c"]);
+
+        let receivers = vec![("tx:0".to_string(), 1), ("tx:1".to_string(), 2)];
+        assert!(contract
+            .transfer(&txid, &payload, &senders, &receivers, 0)
+            .is_ok());
+        assert_eq!(owned_by(&contract, "tx:0"), vec!["a"]);
+        assert_eq!(owned_by(&contract, "tx:1"), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn collection_burns_destroy_the_named_tokens() {
+        let mut contract = collection_contract();
+        let (txid, payload) = ("tx".to_string(), String::new());
+        let (burners, change) = (vec!["owner:0".to_string()], "tx:0".to_string());
+        let ids = |ids: &[&str]| Some(ids.iter().map(|id| id.to_string()).collect::<Vec<String>>());
+        assert!(contract
+            .burn(&txid, &payload, &burners, &1, &change, &None)
+            .is_err());
+        assert!(contract
+            .burn(&txid, &payload, &burners, &2, &change, &ids(&["a"]))
+            .is_err());
+        assert!(contract
+            .burn(&txid, &payload, &burners, &2, &change, &ids(&["a", "a"]))
+            .is_err());
+        assert!(contract
+            .burn(&txid, &payload, &burners, &1, &change, &ids(&["z"]))
+            .is_err());
+
+        assert_eq!(
+            contract.burn(&txid, &payload, &burners, &1, &change, &ids(&["a"])),
+            Ok(0)
+        );
+        assert_eq!(owned_by(&contract, "tx:0"), vec!["b", "c"]);
+        assert!(!contract.nft_tokens.as_ref().unwrap().contains_key("a"));
+        assert_eq!((contract.supply, contract.owners["tx:0"]), (2, 2));
+    }
+
+    fn collection_listing(
+        contract: &mut SCL01Contract,
+        token_ids: Option<Vec<String>>,
+    ) -> Result<(), String> {
+        let listing = Listing {
+            list_utxo: "list:0".to_string(),
+            list_amt: 2,
+            price: 1_000,
+            change_utxo: "list:1".to_string(),
+            allow_partial: Some(true),
+            token_ids,
+            ..Default::default()
+        };
+        let (txid, payload) = ("list".to_string(), String::new());
+        let senders = vec!["owner:0".to_string()];
+        return contract
+            .list(&txid, &payload, &senders, listing, 0)
+            .map(|_| ());
+    }
+
+    #[test]
+    fn collection_listings_hold_the_named_tokens() {
+        let mut contract = collection_contract();
+        let named =
+            |ids: &[&str]| Some(ids.iter().map(|id| id.to_string()).collect::<Vec<String>>());
+        assert!(collection_listing(&mut contract, named(&["a", "z"])).is_err());
+        assert!(collection_listing(&mut contract, named(&["a"])).is_err());
+        assert!(collection_listing(&mut contract, named(&["c", "a"])).is_ok());
+        assert_eq!(owned_by(&contract, "list:0"), vec!["a", "c"]);
+        assert_eq!(owned_by(&contract, "list:1"), vec!["b"]);
+        let listing = contract
+            .listings
+            .as_ref()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        assert_eq!(listing.token_ids, named(&["c", "a"]));
+
+        let mut contract = collection_contract();
+        assert!(collection_listing(&mut contract, None).is_ok());
+        assert_eq!(owned_by(&contract, "list:0"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn collection_partial_fills_hand_out_the_lowest_listed_ids() {
+        let mut contract = collection_contract();
+        assert!(
+            collection_listing(&mut contract, Some(vec!["c".to_string(), "a".to_string()])).is_ok()
+        );
+        let order_id = contract
+            .listings
+            .as_ref()
+            .unwrap()
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        let bid = Bid {
+            bid_price: 500,
+            bid_amount: 1,
+            order_id: order_id.clone(),
+            reseved_utxo: "bid:0".to_string(),
+            ..Default::default()
+        };
+        contract.bids = Some(HashMap::from([("bid".to_string(), bid)]));
+        contract.fulfillments = Some(HashMap::from([("bid".to_string(), order_id.clone())]));
+
+        let (txid, payload) = ("fill".to_string(), String::new());
+        let (_, _, removed, relisted) = contract
+            .fulfil(&txid, &payload, &"bid".to_string())
+            .unwrap();
+        assert_eq!(removed, "list:0");
+        assert_eq!(owned_by(&contract, "fill:0"), vec!["a"]);
+        assert_eq!(owned_by(&contract, "fill:2"), vec!["c"]);
+        let relisted = relisted.unwrap();
+        assert_eq!(
+            (relisted.list_utxo.as_str(), relisted.list_amt),
+            ("fill:2", 1)
+        );
+        assert_eq!(relisted.token_ids, Some(vec!["c".to_string()]));
+
+        let canceled =
+            contract.cancel_listing(&"cancel".to_string(), &"fill:2".to_string(), String::new());
+        assert_eq!(canceled.unwrap().0, ("cancel:0".to_string(), 1));
+        assert_eq!(owned_by(&contract, "cancel:0"), vec!["c"]);
+        assert!(contract.listings.as_ref().unwrap().is_empty());
+    }
+
+    fn claimer(address_type: &str, block_height: Option<u64>) -> ClaimerInfo {
+        return ClaimerInfo {
+            address: "bc1qclaimer".to_string(),
+            address_type: address_type.to_string(),
+            input_value: 5_000,
+            address_age: block_height.map(|_| 10),
+            block_height,
+        };
+    }
+
+    #[test]
+    fn pending_claims_are_checked_against_height_free_rules() {
+        let rules = ClaimRules::from_payload("cap=100;min_value=1000;deny=p2pkh").unwrap();
+        assert!(rules.check(&claimer("v0_p2wpkh", None), 100).is_ok());
+        assert_eq!(
+            rules.check(&claimer("v0_p2wpkh", None), 101),
+            Err("claim_rule_address_cap_reached".to_string())
+        );
+        assert_eq!(
+            rules.check(&claimer("p2pkh", None), 1),
+            Err("claim_rule_address_type_denied".to_string())
+        );
+
+        let mut poor = claimer("v0_p2wpkh", None);
+        poor.input_value = 999;
+        assert_eq!(
+            rules.check(&poor, 1),
+            Err("claim_rule_input_value_too_low".to_string())
+        );
+    }
+
+    #[test]
+    fn block_and_age_rules_wait_for_the_claim_to_confirm() {
+        let mut rules = ClaimRules::from_payload("min_age=11;per_block=1").unwrap();
+        let pending = claimer("v0_p2wpkh", None);
+        assert!(rules.check(&pending, 1).is_ok());
+        rules.record(&pending, 1);
+        assert_eq!((rules.last_claim_block, rules.claims_in_last_block), (0, 0));
+        assert_eq!(
+            rules.check(&claimer("v0_p2wpkh", Some(500)), 1),
+            Err("claim_rule_address_too_young".to_string())
+        );
+
+        let mut rules = ClaimRules::from_payload("min_age=10;per_block=1").unwrap();
+        let confirmed = claimer("v0_p2wpkh", Some(500));
+        assert!(rules.check(&confirmed, 1).is_ok());
+        rules.record(&confirmed, 1);
+        assert_eq!(
+            rules.check(&confirmed, 1),
+            Err("claim_rule_block_limit_reached".to_string())
+        );
+        assert!(rules.check(&claimer("v0_p2wpkh", Some(501)), 1).is_ok());
+        assert_eq!(rules.claimed_by_address["bc1qclaimer"], 1);
+    }
+
+    const ROYALTY_ADDRESS: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+
+    #[test]
+    fn royalty_from_payload_bounds_the_basis_points() {
+        let royalty = Royalty::from_payload(&format!("ROYALTY(250;{})", ROYALTY_ADDRESS)).unwrap();
+        assert_eq!(
+            (royalty.bps, royalty.address.as_str()),
+            (250, ROYALTY_ADDRESS)
+        );
+        let royalty = format!("ROYALTY({};{})", MAX_ROYALTY_BPS, ROYALTY_ADDRESS);
+        assert!(Royalty::from_payload(&royalty).is_ok());
+
+        let too_high = format!("ROYALTY({};{})", MAX_ROYALTY_BPS + 1, ROYALTY_ADDRESS);
+        assert!(Royalty::from_payload(&too_high).is_err());
+        assert!(Royalty::from_payload(&format!("ROYALTY(0;{})", ROYALTY_ADDRESS)).is_err());
+        assert!(Royalty::from_payload(&format!("ROYALTY(-1;{})", ROYALTY_ADDRESS)).is_err());
+        assert!(Royalty::from_payload("ROYALTY(250;not_an_address)").is_err());
+        assert!(Royalty::from_payload("ROYALTY(250)").is_err());
+    }
+
+    #[test]
+    fn royalty_is_not_owed_to_the_seller() {
+        let royalty = Royalty {
+            bps: 250,
+            address: ROYALTY_ADDRESS.to_string(),
+        };
+        assert_eq!(royalty.amount(1_039), 25);
+        assert_eq!(royalty.owed(1_039, &"bc1qseller".to_string()), 25);
+        assert_eq!(royalty.owed(1_039, &ROYALTY_ADDRESS.to_string()), 0);
+    }
 }
@@ -1,18 +1,26 @@
 use crate::utils::record_failed_transaction;
-use super::scl01_contract::{Bid, LiquidityPool, Listing, SCL01Contract};
+use super::scl01_contract::{
+    apply_slippage_tolerance, Bid, DripEvent, DripHistory, DripStatus, LiquidityPool, Listing,
+    NftToken, PoolCurve, ProtocolFee, Royalty, SCL01Contract, SwapQuote, SwapRoute,
+    VestingSchedule, BPS_SCALE, MAX_PROTOCOL_FEE_SHARE_BPS, MAX_SWAP_HOPS, SCL04_FEE_TIERS_BPS,
+};
 use crate::{
-    scl01::scl01_contract::{DimAirdrop, DGE},
+    scl01::scl01_contract::{ClaimRules, ClaimerInfo, DimAirdrop, DGE},
     utils::{
-        check_utxo_inputs, extract_contract_id, get_current_block_height, get_tx_inputs,
-        get_txid_from_hash, get_utxos_from_hash, handle_get_request, read_contract_interactions,
-        read_from_file, read_server_config, read_server_lookup, replace_payload_special_characters,
-        save_contract_interactions, save_server_lookup, write_contract_directory, write_to_file,
-        Config, ContractImport, FulfilledSummary, Lookups, TradeTx, TxInfo,
+        append_to_file, check_utxo_inputs, extract_contract_id, get_address_first_seen_height,
+        get_current_block_height, get_transaction, get_tx_block_info, get_tx_first_input_address,
+        get_tx_inputs, get_txid_from_hash, get_utxos_from_hash, handle_get_request,
+        parse_fixed_point, read_contract_interactions, read_from_file, read_server_config,
+        read_server_lookup, replace_payload_special_characters, save_contract_interactions,
+        save_contract_metadata, save_server_lookup, write_contract_directory, write_to_file,
+        Config, ContractImport, ContractMetadata, FulfilledSummary, TradeTx, TxInfo,
     },
 };
 use bitcoin::{consensus::deserialize, Address, Transaction};
 use hex::decode;
+use magic_crypt::{new_magic_crypt, MagicCryptTrait};
 use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashMap;
 use std::fs;
 
@@ -25,12 +33,29 @@ pub fn perform_minting_scl01(txid: &str, payload: &str) {
         }
     };
 
-    if let Ok(captures) = handle_mint_payload(payload, txid) {
+    let (mint_payload, metadata_authority) = split_authority(txid, payload, "AUTHORITY");
+    let (mint_payload, freeze_authority) = split_authority(txid, &mint_payload, "FREEZE_AUTHORITY");
+    let (mint_payload, mint_authority) = split_authority(txid, &mint_payload, "MINT_AUTHORITY");
+
+    if let Ok(captures) = handle_mint_payload(&mint_payload, txid) {
         let ticker = &captures.0;
         let txid_n = &captures.1;
         let max_supply = &captures.2;
         let decimals = &captures.3;
 
+        // Without a mint authority the supply is fixed at mint, with one it can grow to the cap
+        let mut supply_cap = *max_supply;
+        let mut mint_authority_utxo: Option<String> = None;
+        if let Some(authority) = mint_authority {
+            match handle_mint_authority(&authority) {
+                Ok((authority_utxo, cap)) if cap >= *max_supply && &authority_utxo != txid_n => {
+                    supply_cap = cap;
+                    mint_authority_utxo = Some(authority_utxo);
+                }
+                _ => println!("Invalid mint authority, minting with a fixed supply"),
+            };
+        }
+
         let mut owners_map: HashMap<String, u64> = HashMap::new();
         owners_map.insert(txid_n.clone().to_string(), max_supply.clone());
         let mut payloads: HashMap<String, String> = HashMap::new();
@@ -54,10 +79,18 @@ pub fn perform_minting_scl01(txid: &str, payload: &str) {
             pending_claims: None,
             last_airdrop_split: None,
             right_to_mint: None,
-            max_supply: Some(*max_supply),
+            max_supply: Some(supply_cap),
             liquidated_tokens: None,
             liquidity_pool: None,
             token_data: None,
+            nft_tokens: None,
+            royalty: None,
+            metadata_authority,
+            mint_authority: mint_authority_utxo,
+            minted: Some(*max_supply),
+            freeze_authority,
+            frozen_utxos: None,
+            merkle_airdrops: None,
         };
         let data = format!("{}:O-,{}", &new_contract.contractid, &max_supply);
         match fs::write(
@@ -103,6 +136,7 @@ pub fn perform_minting_scl01(txid: &str, payload: &str) {
                     rest_url: url.to_string(),
                     contract_type: "SCL01".to_string(),
                     decimals: new_contract.decimals,
+                    metadata: None,
                 };
                 let result = match serde_json::to_string(&import) {
                     Ok(result) => result,
@@ -129,6 +163,9 @@ pub fn perform_minting_scl02(txid: &str, payload: &str) {
         }
     };
 
+    let (mint_payload, metadata_authority) = split_authority(txid, payload, "AUTHORITY");
+    let (mint_payload, freeze_authority) = split_authority(txid, &mint_payload, "FREEZE_AUTHORITY");
+
 
     let re = match Regex::new(r"\[([^,]+),([^,]+),([^,]+),([^]]+)]") {
         Ok(re) => re,
@@ -138,7 +175,7 @@ pub fn perform_minting_scl02(txid: &str, payload: &str) {
         }
     };
 
-    if let Some(captures) = re.captures(&payload) {
+    if let Some(captures) = re.captures(&mint_payload) {
 
         let ticker = match captures.get(1) {
             Some(ticker) => ticker.as_str(),
@@ -225,6 +262,14 @@ pub fn perform_minting_scl02(txid: &str, payload: &str) {
             liquidated_tokens: None,
             liquidity_pool: None,
             token_data: None,
+            nft_tokens: None,
+            royalty: None,
+            metadata_authority,
+            mint_authority: None,
+            minted: None,
+            freeze_authority,
+            frozen_utxos: None,
+            merkle_airdrops: None,
         };
 
         match serde_json::to_string(&new_contract) {
@@ -259,6 +304,7 @@ pub fn perform_minting_scl02(txid: &str, payload: &str) {
                     rest_url: url,
                     contract_type: "SCL02".to_string(),
                     decimals: new_contract.decimals,
+                    metadata: None,
                 };
                 let result = match serde_json::to_string(&import) {
                     Ok(result) => result,
@@ -285,7 +331,10 @@ pub fn perform_minting_scl03(txid: &str, payload: &str) {
         }
     };
 
-    if let Ok(captures) = handle_mint_rtm_payload(payload, txid) {
+    let (mint_payload, metadata_authority) = split_authority(txid, payload, "AUTHORITY");
+    let (mint_payload, freeze_authority) = split_authority(txid, &mint_payload, "FREEZE_AUTHORITY");
+
+    if let Ok(captures) = handle_mint_rtm_payload(&mint_payload, txid) {
         let mut max_supply = 0;
         for (_, value) in captures.2.clone() {
             max_supply += value;
@@ -316,6 +365,14 @@ pub fn perform_minting_scl03(txid: &str, payload: &str) {
             liquidated_tokens: None,
             liquidity_pool: None,
             token_data: None,
+            nft_tokens: None,
+            royalty: None,
+            metadata_authority,
+            mint_authority: None,
+            minted: None,
+            freeze_authority,
+            frozen_utxos: None,
+            merkle_airdrops: None,
         };
 
         match serde_json::to_string(&new_contract) {
@@ -350,6 +407,7 @@ pub fn perform_minting_scl03(txid: &str, payload: &str) {
                     rest_url: url,
                     contract_type: "SCL03".to_string(),
                     decimals: new_contract.decimals,
+                    metadata: None,
                 };
                 let result = match serde_json::to_string(&import) {
                     Ok(result) => result,
@@ -545,11 +603,29 @@ pub async fn perform_transfer(txid: &str, command: &str, payload: &str, pending:
         return;
     }
 
-    let results = match handle_transfer_payload(txid, command) {
-        Ok(results) => results,
-        Err(_) => {
-            record_failed_transaction(txid, "handle_transfer_payload_failed");
-            return;
+    let mut token_receivers: Option<Vec<(String, Vec<String>)>> = None;
+    let results = if command.contains("TRANSFER_TOKENS") {
+        match handle_transfer_tokens_payload(txid, command) {
+            Ok((senders, receivers)) => {
+                let amounts: Vec<(String, u64)> = receivers
+                    .iter()
+                    .map(|(receiver, token_ids)| (receiver.clone(), token_ids.len() as u64))
+                    .collect();
+                token_receivers = Some(receivers);
+                (senders, amounts, String::new())
+            }
+            Err(_) => {
+                record_failed_transaction(txid, "handle_transfer_tokens_payload_failed");
+                return;
+            }
+        }
+    } else {
+        match handle_transfer_payload(txid, command) {
+            Ok(results) => results,
+            Err(_) => {
+                record_failed_transaction(txid, "handle_transfer_payload_failed");
+                return;
+            }
         }
     };
 
@@ -566,13 +642,24 @@ pub async fn perform_transfer(txid: &str, command: &str, payload: &str, pending:
         }
     };
 
-    let drip = match contract.transfer(
-        &txid.to_string(),
-        &payload.to_string(),
-        &results.0,
-        &results.1,
-        block_height as u64,
-    ) {
+    let transferred = match &token_receivers {
+        Some(receivers) => contract.transfer_tokens(
+            &txid.to_string(),
+            &payload.to_string(),
+            &results.0,
+            receivers,
+            block_height as u64,
+        ),
+        None => contract.transfer(
+            &txid.to_string(),
+            &payload.to_string(),
+            &results.0,
+            &results.1,
+            block_height as u64,
+        ),
+    };
+
+    let drip = match transferred {
         Ok(res) => res,
         Err(_) => {
             record_failed_transaction(txid, "contract_transfer_failed");
@@ -684,6 +771,7 @@ pub async fn perform_burn(txid: &str, command: &str, payload: &str, pending: boo
             &result.0,
             &result.1,
             &result.2,
+            &handle_burn_token_ids_payload(payload),
         ) {
             Ok(_) => {}
             Err(_) => {
@@ -760,6 +848,10 @@ pub async fn perform_list(txid: &str, command: &str, payload: &str, pending: boo
             list_amt: result.4,
             price: result.5,
             valid_bid_block: None,
+            expiry_block: result.6,
+            allow_partial: Some(result.7),
+            fills: None,
+            token_ids: handle_list_token_ids_payload(command),
         };
 
         let new_owner = match contract.list(
@@ -939,6 +1031,14 @@ pub async fn perform_bid(
         return;
     }
 
+    let block_height = match get_current_block_height().await {
+        Ok(block_height) => block_height,
+        Err(_) => {
+            record_failed_transaction(txid, "get_current_block_height_failed");
+            return;
+        }
+    };
+
     let mut bids: Vec<Bid> = Vec::new();
     let mut bidding_ids: Vec<String> = Vec::new();
     let mut order_id_split = String::new();
@@ -995,6 +1095,25 @@ pub async fn perform_bid(
         let mut res_utxo_str = replace_payload_special_characters(&bid_info[3].to_string());
         res_utxo_str = res_utxo_str.replace("TXID", txid);
 
+        let mut expiry_block: Option<u64> = None;
+        if bid_info.len() > 4 {
+            let expiry_str = replace_payload_special_characters(&bid_info[4].to_string());
+            let expiry = match expiry_str.parse::<u64>() {
+                Ok(expiry) => expiry,
+                Err(_) => {
+                    record_failed_transaction(txid, "expiry_parse_failed");
+                    continue;
+                }
+            };
+
+            if expiry <= block_height as u64 {
+                record_failed_transaction(txid, "bid_expiry_passed");
+                continue;
+            }
+
+            expiry_block = Some(expiry);
+        }
+
         let txid = match get_txid_from_hash(&fulfil_tx) {
             Ok(txid) => txid,
             Err(_) => {
@@ -1041,6 +1160,17 @@ pub async fn perform_bid(
             continue;
         }
 
+        // The royalty must be paid by the fulfilment before the bid can be accepted
+        if let Some(royalty) = &contract.royalty {
+            if let Err(err) =
+                check_royalty_payment(royalty, &fulfil_tx, &listing.rec_addr, payed_amt as u64)
+            {
+                println!("{}", err);
+                record_failed_transaction(&txid, "royalty_not_paid");
+                continue;
+            }
+        }
+
         bidding_ids.push(txid.clone());
 
         let fullfilment_utxos = match get_utxos_from_hash(&fulfil_tx) {
@@ -1064,21 +1194,12 @@ pub async fn perform_bid(
             fulfill_tx: fulfil_tx.to_string(),
             accept_tx: accept_tx.to_string(),
             reseved_utxo: res_utxo_str,
-            fullfilment_utxos: fullfilment_utxos,
+            fullfilment_utxos,
+            expiry_block,
         };
         bids.push(bid);
     }
 
-
-    let block_height = match get_current_block_height().await {
-        Ok(block_height) => block_height,
-        Err(_) => {
-            record_failed_transaction(txid, "get_current_block_height_failed");
-            0
-        }
-    };
-
-
     match contract.bid(
         &txid.to_string(),
         &payload.to_string(),
@@ -1223,6 +1344,22 @@ pub async fn perform_accept_bid(txid: &str, payload: &str, pending: bool) {
         return;
     }
 
+    if let Some(royalty) = contract.royalty.clone() {
+        let bid = &bids_available[&bid_id];
+        let trade_value = (bid.bid_amount as u128 * bid.bid_price as u128
+            / 10u128.pow(contract.decimals as u32)) as u64;
+        if let Err(err) = check_royalty_payment(
+            &royalty,
+            &bid.fulfill_tx,
+            &listings_available[&order_id].rec_addr,
+            trade_value,
+        ) {
+            println!("{}", err);
+            record_failed_transaction(txid, "royalty_not_paid");
+            return;
+        }
+    }
+
     match contract.accept_bid(&txid.to_string(), &payload.to_string(), &bid_id) {
         Ok(_) => {}
         Err(_) => {
@@ -1233,7 +1370,7 @@ pub async fn perform_accept_bid(txid: &str, payload: &str, pending: bool) {
 
     if pending {
         let fulfill_payload = format!("{{{}:FULFIL_TRADE}}", contract_id);
-        let (new_owners, _, _) =
+        let (new_owners, _, _, relisted) =
             match contract.fulfil(&bid_id, &fulfill_payload.to_string(), &bid_id) {
                 Ok(n) => n,
                 Err(_) => return,
@@ -1242,6 +1379,10 @@ pub async fn perform_accept_bid(txid: &str, payload: &str, pending: bool) {
             let data = format!("{}:P-O-,{}", &contract.contractid, value);
             write_to_file(format!("./Json/UTXOS/{}.txt", &key), data.clone());
         }
+
+        if let Some(remaining_listing) = relisted {
+            let _ = update_list_utxos(remaining_listing, contract.clone(), true, &order_id);
+        }
     }
 
     let _ = save_contract(&contract, payload, txid, true);
@@ -1302,14 +1443,40 @@ pub async fn perform_fulfil_bid(txid: &str, payload: &str, pending: bool) {
     }
 
     let order_id = fulfillments[txid].clone();
-    let fulfillment = FulfilledSummary {
-        bid_price: bids[txid].bid_price.clone(),
-        bid_amount: bids[txid].bid_amount.clone(),
-        listing_amount: listings[&order_id].list_amt.clone(),
-        listing_price: listings[&order_id].price.clone(),
-    };
+    let mut fulfillment = FulfilledSummary {
+        bid_price: bids[txid].bid_price,
+        bid_amount: bids[txid].bid_amount,
+        listing_amount: listings[&order_id].list_amt,
+        listing_price: listings[&order_id].price,
+        txid: Some(txid.to_string()),
+        block_height: None,
+        block_time: None,
+        royalty_amount: None,
+        royalty_address: None,
+    };
+
+    if let Some(royalty) = contract.royalty.clone() {
+        let trade_value = (bids[txid].bid_amount as u128 * bids[txid].bid_price as u128
+            / 10u128.pow(contract.decimals as u32)) as u64;
+        let royalty_amount = match check_royalty_payment(
+            &royalty,
+            &bids[txid].fulfill_tx,
+            &listings[&order_id].rec_addr,
+            trade_value,
+        ) {
+            Ok(royalty_amount) => royalty_amount,
+            Err(err) => {
+                println!("{}", err);
+                record_failed_transaction(txid, "royalty_not_paid");
+                return;
+            }
+        };
 
-    let (new_owners, bids, listing) =
+        fulfillment.royalty_amount = Some(royalty_amount);
+        fulfillment.royalty_address = Some(royalty.address.clone());
+    }
+
+    let (new_owners, bids, listing, relisted) =
         match contract.fulfil(&txid.to_string(), &payload.to_string(), &txid.to_string()) {
             Ok(n) => n,
             Err(_) => {
@@ -1341,11 +1508,20 @@ pub async fn perform_fulfil_bid(txid: &str, payload: &str, pending: bool) {
             Err(_) => {}
         };
 
+        if let Some(remaining_listing) = relisted.clone() {
+            let _ = update_list_utxos(remaining_listing, contract.clone(), false, &order_id);
+        }
+
         let mut interactions = match read_contract_interactions(&contract_id) {
             Ok(interactions) => interactions,
             Err(_) => return,
         };
 
+        if let Some((block_height, block_time)) = get_tx_block_info(txid).await {
+            fulfillment.block_height = Some(block_height);
+            fulfillment.block_time = Some(block_time);
+        }
+
         interactions.fulfillment_summaries.push(fulfillment);
         match save_contract_interactions(&interactions, &contract_id) {
             Ok(_) => interactions,
@@ -1356,10 +1532,58 @@ pub async fn perform_fulfil_bid(txid: &str, payload: &str, pending: bool) {
             let data = format!("{}:P-O-,{}", &contract.contractid, value);
             write_to_file(format!("./Json/UTXOS/{}.txt", &key), data.clone());
         }
+
+        if let Some(remaining_listing) = relisted {
+            let _ = update_list_utxos(remaining_listing, contract.clone(), true, &order_id);
+        }
     }
 }
 
-pub async fn perform_drip_start(txid: &str, command: &str, payload: &str, pending: bool) {
+// Returns the royalty owed on a trade after checking the fulfilment transaction pays it
+pub fn check_royalty_payment(
+    royalty: &Royalty,
+    fulfil_tx: &String,
+    seller_address: &String,
+    trade_value: u64,
+) -> Result<u64, String> {
+    let royalty_amount = royalty.owed(trade_value, seller_address);
+    if royalty_amount == 0 {
+        return Ok(0);
+    }
+
+    let tx_bytes = match decode(fulfil_tx) {
+        Ok(tx_bytes) => tx_bytes,
+        Err(_) => return Err("Unable to decode fulfilment transaction".to_string()),
+    };
+
+    let transaction: Transaction = match deserialize(&tx_bytes) {
+        Ok(transaction) => transaction,
+        Err(_) => return Err("Unable to deserialize fulfilment transaction".to_string()),
+    };
+
+    let royalty_address: Address = match royalty.address.parse::<Address>() {
+        Ok(address) => address,
+        Err(_) => return Err("Invalid royalty address".to_string()),
+    };
+
+    let mut paid: u64 = 0;
+    for output in transaction.output {
+        if output.script_pubkey == royalty_address.script_pubkey() {
+            paid += output.value;
+        }
+    }
+
+    if paid < royalty_amount {
+        return Err(format!(
+            "Royalty of {} sats to {} not paid, found {}",
+            royalty_amount, royalty.address, paid
+        ));
+    }
+
+    return Ok(royalty_amount);
+}
+
+pub async fn perform_set_metadata(txid: &str, command: &str, payload: &str, pending: bool) {
     let contract_id = match extract_contract_id(command) {
         Ok(contract_id) => contract_id,
         Err(_) => {
@@ -1381,95 +1605,42 @@ pub async fn perform_drip_start(txid: &str, command: &str, payload: &str, pendin
         return;
     }
 
-    let results = match handle_drip_payload(txid, command) {
-        Ok(results) => results,
-        Err(_) => {
-            record_failed_transaction(txid, "handle_drip_payload_failed");
-            return;
-        }
-    };
+    let (authority_utxo, new_authority_utxo, metadata) =
+        match handle_metadata_payload(txid, command) {
+            Ok(results) => results,
+            Err(_) => {
+                record_failed_transaction(txid, "handle_metadata_payload_failed");
+                return;
+            }
+        };
 
-    if !check_utxo_inputs(&results.0, &txid).await {
+    if !check_utxo_inputs(&vec![authority_utxo.clone()], txid).await {
         record_failed_transaction(txid, "check_utxo_inputs_failed");
         return;
     }
 
-    let current_block_height = match get_current_block_height().await {
-        Ok(current_block_height) => current_block_height,
-        Err(_) => {
-            record_failed_transaction(txid, "get_current_block_height_failed");
-            return;
-        }
-    };
-
-    let new_owners = match contract.start_drip(
+    match contract.set_metadata(
         &txid.to_string(),
         &payload.to_string(),
-        &results.0,
-        &results.1,
-        &results.2,
-        current_block_height as u64,
+        &authority_utxo,
+        &new_authority_utxo,
     ) {
-        Ok(res) => res,
-        Err(_) => {
-            record_failed_transaction(txid, "start_drip_failed");
+        Ok(_) => {}
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "set_metadata_failed");
             return;
         }
     };
 
     let _ = save_contract(&contract, payload, txid, true);
     if !pending {
-        for s in &results.0 {
-            let file_path = format!("./Json/UTXOS/{}.txt", s);
-            match fs::remove_file(file_path) {
-                Ok(_) => {}
-                Err(_) => {}
-            }
-        }
-
-        for (key, value) in new_owners.0.clone() {
-            let data = format!("{}:DO-,{}", &contract.contractid, value);
-            match fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone()) {
-                Ok(_) => {}
-                Err(_) => {}
-            };
-        }
-        let data = format!("{}:O-,{}", &contract.contractid, &new_owners.1 .1);
-        match fs::write(
-            format!("./Json/UTXOS/{}.txt", &new_owners.1 .0.clone()),
-            data.clone(),
-        ) {
-            Ok(_) => {}
-            Err(_) => return,
-        };
-
         let _ = save_contract(&contract, payload, txid, false);
-    } else {
-        let data = format!("{}:P-O-,{}", &contract.contractid, &new_owners.1 .1);
-        match fs::write(
-            format!("./Json/UTXOS/{}.txt", &new_owners.1 .0.clone()),
-            data.clone(),
-        ) {
-            Ok(_) => {}
-            Err(_) => {}
-        };
-
-        for (key, value) in new_owners.0 {
-            let data = format!("{}:P-DO-,{}", &contract.contractid, value);
-            match fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone()) {
-                Ok(_) => {}
-                Err(_) => {}
-            };
-        }
+        save_contract_metadata(&contract_id, &metadata);
     }
 }
 
-pub async fn perform_create_diminishing_airdrop(
-    txid: &str,
-    command: &str,
-    payload: &str,
-    pending: bool,
-) {
+pub async fn perform_mint_supply(txid: &str, command: &str, payload: &str, pending: bool) {
     let contract_id = match extract_contract_id(command) {
         Ok(contract_id) => contract_id,
         Err(_) => {
@@ -1491,81 +1662,48 @@ pub async fn perform_create_diminishing_airdrop(
         return;
     }
 
-    let results = match handle_create_diminishing_airdrop_payload(txid, command) {
-        Ok(results) => results,
-        Err(_) => {
-            record_failed_transaction(txid, "handle_create_diminishing_airdrop_payload_failed");
-            return;
-        }
-    };
+    let (authority_utxo, new_authority_utxo, receiver_utxo, amount) =
+        match handle_mint_supply_payload(txid, command) {
+            Ok(results) => results,
+            Err(_) => {
+                record_failed_transaction(txid, "handle_mint_supply_payload_failed");
+                return;
+            }
+        };
 
-    if !check_utxo_inputs(&results.0, &txid).await {
+    if !check_utxo_inputs(&vec![authority_utxo.clone()], txid).await {
         record_failed_transaction(txid, "check_utxo_inputs_failed");
         return;
     }
 
-    let current_block_height = match get_current_block_height().await {
-        Ok(current_block_height) => current_block_height as u64,
-        Err(_) => {
-            record_failed_transaction(txid, "get_current_block_height_failed");
-            return;
-        }
-    };
-
-    let new_owners = match contract.create_dim_airdrop(
+    let balance = match contract.mint_supply(
         &txid.to_string(),
         &payload.to_string(),
-        &results.0,
-        &results.1,
-        &results.2,
-        &results.3,
-        &results.4,
-        &results.5,
-        &results.6,
-        &results.7,
-        current_block_height,
+        &authority_utxo,
+        &new_authority_utxo,
+        &receiver_utxo,
+        amount,
     ) {
-        Ok(res) => res,
-        Err(_) => {
-            record_failed_transaction(txid, "create_dim_airdrop_failed");
+        Ok(balance) => balance,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "mint_supply_failed");
             return;
         }
     };
 
     let _ = save_contract(&contract, payload, txid, true);
     if !pending {
-        for s in &results.0 {
-            let file_path = format!("./Json/UTXOS/{}.txt", s);
-            match fs::remove_file(file_path) {
-                Ok(_) => {}
-                Err(_) => {}
-            }
-        }
-
-        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
-        if new_owners.2 {
-            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
-        }
-
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
         let _ = save_contract(&contract, payload, txid, false);
+        let data = format!("{}:O-,{}", &contract.contractid, balance);
+        write_to_file(format!("./Json/UTXOS/{}.txt", &receiver_utxo), data);
     } else {
-        let mut data = format!("{}:P-O-,{}", &contract.contractid, new_owners.1);
-        if new_owners.2 {
-            data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
-        }
-
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+        let data = format!("{}:P-O-,{}", &contract.contractid, balance);
+        write_to_file(format!("./Json/UTXOS/{}.txt", &receiver_utxo), data);
     }
 }
 
-pub async fn perform_claim_diminishing_airdrop(
-    txid: &str,
-    command: &str,
-    payload: &str,
-    pending: bool,
-    esplora: String,
-) {
+pub async fn perform_renounce_mint(txid: &str, command: &str, payload: &str, pending: bool) {
     let contract_id = match extract_contract_id(command) {
         Ok(contract_id) => contract_id,
         Err(_) => {
@@ -1574,96 +1712,54 @@ pub async fn perform_claim_diminishing_airdrop(
         }
     };
 
-    let results = match handle_claim_diminishing_airdrop_payload(txid, command) {
-        Ok(results) => results,
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
         Err(_) => {
-            record_failed_transaction(txid, "handle_claim_diminishing_airdrop_payload_failed");
+            record_failed_transaction(txid, "read_contract_failed");
             return;
         }
     };
 
-    let contract_pending = match read_contract(contract_id.as_str(), true) {
-        Ok(contract) => contract,
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
+        return;
+    }
+
+    let authority_utxo = match handle_renounce_mint_payload(command) {
+        Ok(authority_utxo) => authority_utxo,
         Err(_) => {
-            record_failed_transaction(txid, "read_contract_pending_failed");
+            record_failed_transaction(txid, "handle_renounce_mint_payload_failed");
             return;
         }
     };
 
-    let dims = match contract_pending.diminishing_airdrops.clone() {
-        Some(dims) => dims,
-        None => {
-            record_failed_transaction(txid, "no_diminishing_airdrops");
-            return;
-        }
-    };
+    if !check_utxo_inputs(&vec![authority_utxo.clone()], txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
+        return;
+    }
 
-    let dim: DimAirdrop = match dims.get(&results.0) {
-        Some(dim) => dim.clone(),
-        None => {
-            record_failed_transaction(txid, "dim_airdrop_not_found");
+    match contract.renounce_mint(&txid.to_string(), &payload.to_string(), &authority_utxo) {
+        Ok(_) => {}
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "renounce_mint_failed");
             return;
         }
     };
 
-    let mut donater_pub_address: String = String::new();
-
-    if dim.single_drop {
-        let url: String = esplora.to_string() + "tx/" + &txid;
-        let response = match handle_get_request(url).await {
-            Some(response) => response,
-            None => {
-                record_failed_transaction(txid, "handle_get_request_failed");
-                return;
-            }
-        };
-
-        let tx_info: TxInfo = match serde_json::from_str::<TxInfo>(&response) {
-            Ok(tx_info) => tx_info,
-            Err(_) => {
-                record_failed_transaction(txid, "serde_json_parse_txinfo_failed");
-                return;
-            }
-        };
-
-        let vin = match tx_info.vin {
-            Some(vin) => vin,
-            None => {
-                record_failed_transaction(txid, "no_vin_in_txinfo");
-                return;
-            }
-        };
-
-        if vin.len() == 0 {
-            record_failed_transaction(txid, "vin_empty");
-            return;
-        }
-
-        let prev_outputs = match &vin[0].prevout {
-            Some(prev) => prev,
-            None => {
-                record_failed_transaction(txid, "no_prevout_in_vin");
-                return;
-            }
-        };
-
-        donater_pub_address = match &prev_outputs.scriptpubkey_address {
-            Some(donater_pub_address) => donater_pub_address.clone(),
-            None => {
-                record_failed_transaction(txid, "no_scriptpubkey_address_in_prevout");
-                return;
-            }
-        };
+    let _ = save_contract(&contract, payload, txid, true);
+    if !pending {
+        let _ = save_contract(&contract, payload, txid, false);
+    }
+}
 
-        if dim.claimers.contains_key(&donater_pub_address) {
-            record_failed_transaction(txid, "claimer_already_exists");
+pub async fn perform_freeze(txid: &str, command: &str, payload: &str, pending: bool) {
+    let contract_id = match extract_contract_id(command) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
             return;
         }
-    }
-
-    let p_c: HashMap<String, u64> = match contract_pending.pending_claims.clone() {
-        Some(p_c) => p_c,
-        None => HashMap::new(),
     };
 
     let mut contract = match read_contract(contract_id.as_str(), pending) {
@@ -1674,48 +1770,58 @@ pub async fn perform_claim_diminishing_airdrop(
         }
     };
 
-    contract.pending_claims = Some(p_c);
     if contract.payloads.iter().any(|(tx, _)| tx == txid) {
         record_failed_transaction(txid, "duplicate_txid_in_payloads");
         return;
     }
 
-    let new_owners = match contract.claim_dim_airdrop(
-        &txid.to_string(),
-        &payload.to_string(),
-        &results.0,
-        &results.1,
-        pending,
-        &donater_pub_address,
-    ) {
-        Ok(res) => res,
+    let (authority_utxo, new_authority_utxo, utxos) = match handle_freeze_payload(txid, command) {
+        Ok(results) => results,
         Err(_) => {
-            record_failed_transaction(txid, "claim_dim_airdrop_failed");
+            record_failed_transaction(txid, "handle_freeze_payload_failed");
             return;
         }
     };
 
-    let _ = save_contract(&contract, payload, txid, true);
-    if !pending {
-        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
-        if new_owners.2 {
-            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
-        }
-
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+    if !check_utxo_inputs(&vec![authority_utxo.clone()], txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
+        return;
+    }
 
-        let _ = save_contract(&contract, payload, txid, false);
+    let result = if command.contains("UNFREEZE") {
+        contract.unfreeze(
+            &txid.to_string(),
+            &payload.to_string(),
+            &authority_utxo,
+            &new_authority_utxo,
+            &utxos,
+        )
     } else {
-        let mut data = format!("{}:P-C-,{}", &contract.contractid, new_owners.1);
-        if new_owners.2 {
-            data = format!("{}:P-DC-,{}", &contract.contractid, new_owners.1);
+        contract.freeze(
+            &txid.to_string(),
+            &payload.to_string(),
+            &authority_utxo,
+            &new_authority_utxo,
+            &utxos,
+        )
+    };
+
+    match result {
+        Ok(_) => {}
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "freeze_failed");
+            return;
         }
+    };
 
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+    let _ = save_contract(&contract, payload, txid, true);
+    if !pending {
+        let _ = save_contract(&contract, payload, txid, false);
     }
 }
 
-pub async fn perform_create_dge(txid: &str, command: &str, payload: &str, pending: bool) {
+pub async fn perform_drip_start(txid: &str, command: &str, payload: &str, pending: bool) {
     let contract_id = match extract_contract_id(command) {
         Ok(contract_id) => contract_id,
         Err(_) => {
@@ -1737,10 +1843,11 @@ pub async fn perform_create_dge(txid: &str, command: &str, payload: &str, pendin
         return;
     }
 
-    let results = match handle_create_dge_payload(txid, command) {
+    let (drip_command, revoke_authority) = split_authority(txid, command, "REVOKE_AUTHORITY");
+    let results = match handle_drip_payload(txid, &drip_command) {
         Ok(results) => results,
         Err(_) => {
-            record_failed_transaction(txid, "handle_create_dge_payload_failed");
+            record_failed_transaction(txid, "handle_drip_payload_failed");
             return;
         }
     };
@@ -1750,74 +1857,66 @@ pub async fn perform_create_dge(txid: &str, command: &str, payload: &str, pendin
         return;
     }
 
-    let dge: DGE = DGE {
-        pool_amount: results.1,
-        sats_rate: results.2,
-        max_drop: results.3,
-        current_amount_dropped: 0,
-        donations_address: results.5,
-        drip_duration: results.4,
-        donaters: HashMap::new(),
-        single_drop: results.7,
-    };
-
     let current_block_height = match get_current_block_height().await {
-        Ok(current_block_height) => current_block_height as u64,
+        Ok(current_block_height) => current_block_height,
         Err(_) => {
             record_failed_transaction(txid, "get_current_block_height_failed");
             return;
         }
     };
 
-    let new_owners = match contract.create_dge(
+    let new_owners = match contract.start_drip(
         &txid.to_string(),
         &payload.to_string(),
         &results.0,
-        dge,
-        &results.6,
-        current_block_height,
+        &results.1,
+        &results.2,
+        &revoke_authority,
+        current_block_height as u64,
     ) {
         Ok(res) => res,
         Err(_) => {
-            record_failed_transaction(txid, "create_dge_failed");
+            record_failed_transaction(txid, "start_drip_failed");
             return;
         }
     };
 
     let _ = save_contract(&contract, payload, txid, true);
-
     if !pending {
         for s in &results.0 {
             let file_path = format!("./Json/UTXOS/{}.txt", s);
-            match fs::remove_file(file_path) {
-                Ok(_) => {}
-                Err(_) => {}
-            }
+            let _ = fs::remove_file(file_path);
         }
 
-        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
-        if new_owners.2 {
-            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
+        for (key, value) in new_owners.0.clone() {
+            let data = format!("{}:DO-,{}", &contract.contractid, value);
+            let _ = fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone());
         }
+        let data = format!("{}:O-,{}", &contract.contractid, &new_owners.1 .1);
+        match fs::write(
+            format!("./Json/UTXOS/{}.txt", &new_owners.1 .0.clone()),
+            data.clone(),
+        ) {
+            Ok(_) => {}
+            Err(_) => return,
+        };
 
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
         let _ = save_contract(&contract, payload, txid, false);
     } else {
-        let mut data = format!("{}:P-O-,{}", &contract.contractid, new_owners.1);
-        if new_owners.2 {
-            data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
+        let data = format!("{}:P-O-,{}", &contract.contractid, &new_owners.1 .1);
+        let _ = fs::write(
+            format!("./Json/UTXOS/{}.txt", &new_owners.1 .0.clone()),
+            data.clone(),
+        );
+
+        for (key, value) in new_owners.0 {
+            let data = format!("{}:P-DO-,{}", &contract.contractid, value);
+            let _ = fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone());
         }
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
 }
 
-pub async fn perform_claim_dge(
-    txid: &str,
-    command: &str,
-    payload: &str,
-    pending: bool,
-    esplora: String,
-) {
+pub async fn perform_revoke_drip(txid: &str, command: &str, payload: &str, pending: bool) {
     let contract_id = match extract_contract_id(command) {
         Ok(contract_id) => contract_id,
         Err(_) => {
@@ -1839,199 +1938,297 @@ pub async fn perform_claim_dge(
         return;
     }
 
-    let results = match handle_claim_dge_payload(txid, command) {
-        Ok(results) => results,
-        Err(_) => {
-            record_failed_transaction(txid, "handle_claim_dge_payload_failed");
-            return;
-        }
-    };
+    let (authority_utxo, new_authority_utxo, drip_utxo, receiver_utxo) =
+        match handle_revoke_drip_payload(txid, command) {
+            Ok(results) => results,
+            Err(_) => {
+                record_failed_transaction(txid, "handle_revoke_drip_payload_failed");
+                return;
+            }
+        };
 
-    let dges = match contract.dges.clone() {
-        Some(dges) => dges,
+    if !check_utxo_inputs(&vec![authority_utxo.clone()], txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
+        return;
+    }
+
+    // Revoked drips split at the block the revoke confirmed in, a pending revoke can confirm
+    // in the next block at the earliest and is replayed with its real height once confirmed
+    let revoke_block_height = match get_tx_block_info(txid).await {
+        Some((block_height, _)) => block_height,
+        None if pending => match get_current_block_height().await {
+            Ok(current_block_height) => current_block_height as u64 + 1,
+            Err(_) => {
+                record_failed_transaction(txid, "get_current_block_height_failed");
+                return;
+            }
+        },
         None => {
-            record_failed_transaction(txid, "no_dges");
+            record_failed_transaction(txid, "get_tx_block_info_failed");
             return;
         }
     };
 
-    let dge: DGE = match dges.get(&results.0) {
-        Some(dge) => dge.clone(),
-        None => {
-            record_failed_transaction(txid, "dge_not_found");
+    let (drip_owner, receiver) = match contract.revoke_drip(
+        &txid.to_string(),
+        &payload.to_string(),
+        &authority_utxo,
+        &new_authority_utxo,
+        &drip_utxo,
+        &receiver_utxo,
+        revoke_block_height,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "revoke_drip_failed");
             return;
         }
     };
 
-    let url: String = esplora.to_string() + "tx/" + &txid;
-    let response = match handle_get_request(url).await {
-        Some(response) => response,
-        None => {
-            record_failed_transaction(txid, "handle_get_request_failed");
+    let _ = save_contract(&contract, payload, txid, true);
+    let mut prefix = "";
+    if pending {
+        prefix = "P-";
+    }
+
+    let mut flag = "O-";
+    if drip_owner.2 {
+        flag = "DO-";
+    }
+
+    let data = format!(
+        "{}:{}{},{}",
+        &contract.contractid, prefix, flag, drip_owner.1
+    );
+    write_to_file(format!("./Json/UTXOS/{}.txt", &drip_owner.0), data);
+    if receiver.1 > 0 {
+        let data = format!("{}:{}O-,{}", &contract.contractid, prefix, receiver.1);
+        write_to_file(format!("./Json/UTXOS/{}.txt", &receiver.0), data);
+    }
+
+    if !pending {
+        let _ = save_contract(&contract, payload, txid, false);
+    }
+}
+
+pub async fn perform_create_diminishing_airdrop(
+    txid: &str,
+    command: &str,
+    payload: &str,
+    pending: bool,
+) {
+    let contract_id = match extract_contract_id(command) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
             return;
         }
     };
 
-    let tx_info: TxInfo = match serde_json::from_str::<TxInfo>(&response) {
-        Ok(tx_info) => tx_info,
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
         Err(_) => {
-            record_failed_transaction(txid, "serde_json_parse_txinfo_failed");
+            record_failed_transaction(txid, "read_contract_failed");
             return;
         }
     };
 
-    let vin = match tx_info.vin {
-        Some(vin) => vin,
-        None => {
-            record_failed_transaction(txid, "no_vin_in_txinfo");
-            return;
-        }
-    };
-
-    if vin.len() == 0 {
-        record_failed_transaction(txid, "vin_empty");
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
         return;
     }
 
-    let prev_outputs = match &vin[0].prevout {
-        Some(prev) => prev,
-        None => {
-            record_failed_transaction(txid, "no_prevout_in_vin");
-            return;
-        }
-    };
-
-    let donater_pub_address = match &prev_outputs.scriptpubkey_address {
-        Some(donater_pub_address) => donater_pub_address.clone(),
-        None => {
-            record_failed_transaction(txid, "no_scriptpubkey_address_in_prevout");
+    let (dim_command, claim_rules) = match split_claim_rules(command) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "claim_rules_invalid");
             return;
         }
     };
 
-    let vout = match tx_info.vout {
-        Some(vout) => vout,
-        None => {
-            record_failed_transaction(txid, "no_vout_in_txinfo");
+    let results = match handle_create_diminishing_airdrop_payload(txid, &dim_command) {
+        Ok(results) => results,
+        Err(_) => {
+            record_failed_transaction(txid, "handle_create_diminishing_airdrop_payload_failed");
             return;
         }
     };
 
-    let mut donation_amout = 0;
-    for output in vout {
-        let address = match output.scriptpubkey_address {
-            Some(address) => address,
-            None => continue,
-        };
-
-        let value = match output.value {
-            Some(value) => value,
-            None => continue,
-        };
-
-        if address == dge.donations_address {
-            donation_amout += value;
-        }
-    }
-
-    if donation_amout == 0 {
-        record_failed_transaction(txid, "donation_amount_zero");
-        return;
-    }
-
-    if dge.single_drop && dge.donaters.contains_key(&donater_pub_address) {
-        record_failed_transaction(txid, "donater_already_exists");
+    if !check_utxo_inputs(&results.0, txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
         return;
     }
 
-    let current_block = match get_current_block_height().await {
-        Ok(current_block) => current_block as u64,
+    let current_block_height = match get_current_block_height().await {
+        Ok(current_block_height) => current_block_height as u64,
         Err(_) => {
             record_failed_transaction(txid, "get_current_block_height_failed");
             return;
         }
     };
 
-    let new_owners = match contract.claim_dge(
+    let new_owners = match contract.create_dim_airdrop(
         &txid.to_string(),
         &payload.to_string(),
         &results.0,
         &results.1,
-        &donater_pub_address,
-        donation_amout,
-        current_block,
+        &results.2,
+        &results.3,
+        &results.4,
+        &results.5,
+        &results.6,
+        &results.7,
+        &claim_rules,
+        current_block_height,
     ) {
         Ok(res) => res,
         Err(_) => {
-            record_failed_transaction(txid, "claim_dge_failed");
+            record_failed_transaction(txid, "create_dim_airdrop_failed");
             return;
         }
     };
 
     let _ = save_contract(&contract, payload, txid, true);
     if !pending {
-        let data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
-        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+        for s in &results.0 {
+            let file_path = format!("./Json/UTXOS/{}.txt", s);
+            let _ = fs::remove_file(file_path);
+        }
 
+        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
+        }
+
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
         let _ = save_contract(&contract, payload, txid, false);
     } else {
-        let data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
+        let mut data = format!("{}:P-O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
+        }
+
         write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
 }
 
-pub fn perform_drips(contract_id: String, block_height: u64, pending: bool) {
-    let mut contract = match read_contract(contract_id.as_str(), pending) {
+pub async fn perform_claim_diminishing_airdrop(
+    txid: &str,
+    command: &str,
+    payload: &str,
+    pending: bool,
+    esplora: String,
+) {
+    let contract_id = match extract_contract_id(command) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
+            return;
+        }
+    };
+
+    let results = match handle_claim_diminishing_airdrop_payload(txid, command) {
+        Ok(results) => results,
+        Err(_) => {
+            record_failed_transaction(txid, "handle_claim_diminishing_airdrop_payload_failed");
+            return;
+        }
+    };
+
+    let contract_pending = match read_contract(contract_id.as_str(), true) {
         Ok(contract) => contract,
-        Err(_) => return,
+        Err(_) => {
+            record_failed_transaction(txid, "read_contract_pending_failed");
+            return;
+        }
     };
 
-    let new_owners = match contract.drip(block_height as u64) {
-        Ok(res) => res,
-        Err(_) => return,
+    let dims = match contract_pending.diminishing_airdrops.clone() {
+        Some(dims) => dims,
+        None => {
+            record_failed_transaction(txid, "no_diminishing_airdrops");
+            return;
+        }
     };
 
-    if new_owners.len() == 0 {
-        return;
-    }
+    let dim: DimAirdrop = match dims.get(&results.0) {
+        Some(dim) => dim.clone(),
+        None => {
+            record_failed_transaction(txid, "dim_airdrop_not_found");
+            return;
+        }
+    };
 
-    let _ = save_contract(&contract, "", "", true);
+    let mut donater_pub_address: String = String::new();
 
-    if !pending {
-        for (key, value, drip) in new_owners.clone() {
-            let mut data = format!("{}:O-,{}", &contract.contractid, value);
-            if drip {
-                data = format!("{}:DO-,{}", &contract.contractid, value);
+    if dim.single_drop {
+        let url: String = esplora.to_string() + "tx/" + txid;
+        let response = match handle_get_request(url).await {
+            Some(response) => response,
+            None => {
+                record_failed_transaction(txid, "handle_get_request_failed");
+                return;
             }
-            match fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone()) {
-                Ok(_) => {}
-                Err(_) => {}
-            };
-        }
+        };
 
-        let _ = save_contract(&contract, "", "", false);
-    } else {
-        for (key, value, drip) in new_owners.clone() {
-            let mut data = format!("{}:P-O-,{}", &contract.contractid, value);
-            if drip {
-                data = format!("{}:P-DO-,{}", &contract.contractid, value);
+        let tx_info: TxInfo = match serde_json::from_str::<TxInfo>(&response) {
+            Ok(tx_info) => tx_info,
+            Err(_) => {
+                record_failed_transaction(txid, "serde_json_parse_txinfo_failed");
+                return;
             }
-            match fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone()) {
-                Ok(_) => {}
-                Err(_) => {}
-            };
+        };
+
+        let vin = match tx_info.vin {
+            Some(vin) => vin,
+            None => {
+                record_failed_transaction(txid, "no_vin_in_txinfo");
+                return;
+            }
+        };
+
+        if vin.is_empty() {
+            record_failed_transaction(txid, "vin_empty");
+            return;
         }
-    }
-}
 
-pub async fn perform_listing_cancel(txid: &str, payload: &str, pending: bool) {
-    let contract_id = match extract_contract_id(payload) {
-        Ok(contract_id) => contract_id,
-        Err(_) => {
-            record_failed_transaction(txid, "extract_contract_id_failed");
+        let prev_outputs = match &vin[0].prevout {
+            Some(prev) => prev,
+            None => {
+                record_failed_transaction(txid, "no_prevout_in_vin");
+                return;
+            }
+        };
+
+        donater_pub_address = match &prev_outputs.scriptpubkey_address {
+            Some(donater_pub_address) => donater_pub_address.clone(),
+            None => {
+                record_failed_transaction(txid, "no_scriptpubkey_address_in_prevout");
+                return;
+            }
+        };
+
+        if dim.claimers.contains_key(&donater_pub_address) {
+            record_failed_transaction(txid, "claimer_already_exists");
             return;
         }
-    };
+    }
+
+    let mut claimer: Option<ClaimerInfo> = None;
+    if let Some(claim_rules) = &dim.claim_rules {
+        claimer = match get_claimer_info(txid, claim_rules, pending).await {
+            Ok(claimer) => Some(claimer),
+            Err(err) => {
+                println!("{}", err);
+                record_failed_transaction(txid, "get_claimer_info_failed");
+                return;
+            }
+        };
+    }
+
+    let p_c: HashMap<String, u64> = contract_pending.pending_claims.clone().unwrap_or_default();
 
     let mut contract = match read_contract(contract_id.as_str(), pending) {
         Ok(contract) => contract,
@@ -2041,63 +2238,59 @@ pub async fn perform_listing_cancel(txid: &str, payload: &str, pending: bool) {
         }
     };
 
+    contract.pending_claims = Some(p_c);
     if contract.payloads.iter().any(|(tx, _)| tx == txid) {
         record_failed_transaction(txid, "duplicate_txid_in_payloads");
         return;
     }
 
-    let words: Vec<&str> = payload.split("CANCELLISTING").collect();
-    if words.len() < 2 {
-        record_failed_transaction(txid, "malformed_cancel_listing_command");
-        return;
-    }
-
-    let listing_utxo = replace_payload_special_characters(&words[1].to_string());
-    let utxos: Vec<String> = vec![listing_utxo.clone()];
-    if !check_utxo_inputs(&utxos, &txid).await {
-        record_failed_transaction(txid, "check_utxo_inputs_failed");
-        return;
-    }
-
-    let file_path = format!("./Json/UTXOS/{}.txt", listing_utxo);
-    // Attempt to remove the file
-    match fs::remove_file(file_path) {
-        Ok(_) => {}
-        Err(_) => {}
-    }
-
-    let (owner, bids) = match contract.cancel_listing(
+    let new_owners = match contract.claim_dim_airdrop(
         &txid.to_string(),
-        &listing_utxo.to_string(),
-        payload.to_string(),
+        &payload.to_string(),
+        &results.0,
+        &results.1,
+        pending,
+        &donater_pub_address,
+        &claimer,
     ) {
-        Ok(owner) => owner,
-        Err(_) => {
-            record_failed_transaction(txid, "cancel_listing_failed");
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(
+                txid,
+                &claim_failure_reason(&err, "claim_dim_airdrop_failed"),
+            );
             return;
         }
     };
 
-    let _ = save_contract(&contract, payload, &txid, pending);
+    let _ = save_contract(&contract, payload, txid, true);
     if !pending {
-        let _ = save_contract(&contract, payload, &txid, false);
-        let data = format!("{}:O-,{}", &contract.contractid, owner.1);
-        write_to_file(format!("./Json/UTXOS/{}.txt", &owner.0), data.clone());
-        for s in &bids {
-            let file_path = format!("./Json/UTXOS/{}.txt", s);
-            match fs::remove_file(file_path) {
-                Ok(_) => {}
-                Err(_) => {}
-            }
+        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
         }
+
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+
+        let _ = save_contract(&contract, payload, txid, false);
     } else {
-        let data = format!("{}:P-O-,{}", &contract.contractid, owner.1);
-        write_to_file(format!("./Json/UTXOS/{}.txt", owner.0), data.clone());
+        let mut data = format!("{}:P-C-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:P-DC-,{}", &contract.contractid, new_owners.1);
+        }
+
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
 }
 
-pub async fn perform_bid_cancel(txid: &str, payload: &str, pending: bool) {
-    let contract_id = match extract_contract_id(payload) {
+pub async fn perform_create_merkle_airdrop(
+    txid: &str,
+    command: &str,
+    payload: &str,
+    pending: bool,
+) {
+    let contract_id = match extract_contract_id(command) {
         Ok(contract_id) => contract_id,
         Err(_) => {
             record_failed_transaction(txid, "extract_contract_id_failed");
@@ -2118,999 +2311,2922 @@ pub async fn perform_bid_cancel(txid: &str, payload: &str, pending: bool) {
         return;
     }
 
-    let words: Vec<&str> = payload.split("CANCELBID").collect();
-    if words.len() < 2 {
-        return;
-    }
+    let results = match handle_create_merkle_airdrop_payload(txid, command) {
+        Ok(results) => results,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "handle_create_merkle_airdrop_payload_failed");
+            return;
+        }
+    };
 
-    let bidding_utxo = replace_payload_special_characters(&words[1].to_string());
-    let utxos: Vec<String> = vec![bidding_utxo.clone()];
-    if !check_utxo_inputs(&utxos, &txid).await {
+    if !check_utxo_inputs(&results.0, txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
         return;
     }
 
-    let file_path = format!("./Json/UTXOS/{}.txt", bidding_utxo);
-    // Attempt to remove the file
-    match fs::remove_file(file_path) {
-        Ok(_) => {}
-        Err(_) => {}
-    }
+    let current_block_height = match get_current_block_height().await {
+        Ok(current_block_height) => current_block_height as u64,
+        Err(_) => {
+            record_failed_transaction(txid, "get_current_block_height_failed");
+            return;
+        }
+    };
 
-    match contract.cancel_bid(
+    let new_owners = match contract.create_merkle_airdrop(
         &txid.to_string(),
-        &bidding_utxo.to_string(),
-        payload.to_string(),
+        &payload.to_string(),
+        &results.0,
+        &results.1,
+        &results.2,
+        &results.3,
+        &results.4,
+        current_block_height,
     ) {
-        Ok(_) => {}
-        Err(_) => return,
-    }
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "create_merkle_airdrop_failed");
+            return;
+        }
+    };
 
-    let _ = save_contract(&contract, payload, &txid, pending);
+    let _ = save_contract(&contract, payload, txid, true);
     if !pending {
-        let _ = save_contract(&contract, payload, &txid, false);
-    }
-}
+        for s in &results.0 {
+            let file_path = format!("./Json/UTXOS/{}.txt", s);
+            match fs::remove_file(file_path) {
+                Ok(_) => {}
+                Err(_) => {}
+            }
+        }
 
-pub fn handle_create_diminishing_airdrop_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(Vec<String>, u64, u64, u64, u64, u64, String, bool), String> {
-    let words: Vec<&str> = payload.split("DIMAIRDROP").collect();
-    if words.len() < 2 {
-        return Err("Invalid dim airdrop payload".to_string());
-    }
+        if new_owners.1 > 0 {
+            let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
+            if new_owners.2 {
+                data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
+            }
 
-    let sendsplit: Vec<&str> = words[1].split("],").collect();
-    if sendsplit.len() < 2 {
-        return Err("Invalid drip payload".to_string());
-    }
+            write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+        }
 
-    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
-    let mut senders: Vec<String> = Vec::new();
-    for sendi in sender_strs {
-        let sender = replace_payload_special_characters(&sendi.to_string());
-        senders.push(sender);
-    }
-    let split: Vec<&str> = sendsplit[1].split(",").collect();
-    if split.len() < 7 {
-        return Err("Invalid drip payload".to_string());
+        let _ = save_contract(&contract, payload, txid, false);
+    } else if new_owners.1 > 0 {
+        let mut data = format!("{}:P-O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
+        }
+
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
+}
 
-    let pool_split = replace_payload_special_characters(&split[0].to_string());
-    let pool = match pool_split.parse::<u64>() {
-        Ok(pool) => pool,
-        Err(_) => return Err("Failed to parse pool amount".to_string()),
+pub async fn perform_claim_merkle_airdrop(txid: &str, command: &str, payload: &str, pending: bool) {
+    let contract_id = match extract_contract_id(command) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
+            return;
+        }
     };
 
-    let step_amount_split = replace_payload_special_characters(&split[1].to_string());
-    let step_amount = match step_amount_split.parse::<u64>() {
-        Ok(step_amount) => step_amount,
-        Err(_) => return Err("Failed to parse step down amount".to_string()),
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => {
+            record_failed_transaction(txid, "read_contract_failed");
+            return;
+        }
     };
 
-    let step_period_split = replace_payload_special_characters(&split[2].to_string());
-    let step_period = match step_period_split.parse::<u64>() {
-        Ok(step_period) => step_period,
-        Err(_) => return Err("Failed to parse min airdrop amount".to_string()),
-    };
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
+        return;
+    }
 
-    let max_airdrop_split = replace_payload_special_characters(&split[3].to_string());
-    let max_airdrop = match max_airdrop_split.parse::<u64>() {
-        Ok(max_airdrop) => max_airdrop,
-        Err(_) => return Err("Failed to parse max airdrop amount".to_string()),
+    let results = match handle_claim_merkle_airdrop_payload(txid, command) {
+        Ok(results) => results,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "handle_claim_merkle_airdrop_payload_failed");
+            return;
+        }
     };
 
-    let min_airdrop_split = replace_payload_special_characters(&split[4].to_string());
-    let min_airdrop = match min_airdrop_split.parse::<u64>() {
-        Ok(min_airdrop) => min_airdrop,
-        Err(_) => return Err("Failed to parse min airdrop amount".to_string()),
+    // The leaf is bound to the address spending the first input, so a proof cannot be replayed by
+    // anyone else
+    let claimer_address = match get_tx_first_input_address(txid).await {
+        Ok(Some(claimer_address)) => claimer_address,
+        Ok(None) => {
+            record_failed_transaction(txid, "no_scriptpubkey_address_in_prevout");
+            return;
+        }
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "get_tx_first_input_address_failed");
+            return;
+        }
     };
 
-    let change_split = replace_payload_special_characters(&split[5].to_string());
-    let change = change_split.replace("TXID", txid);
+    let new_owners = match contract.claim_merkle_airdrop(
+        &txid.to_string(),
+        &payload.to_string(),
+        &results.0,
+        &results.1,
+        &results.2,
+        &results.4,
+        &claimer_address,
+        &results.3,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "claim_merkle_airdrop_failed");
+            return;
+        }
+    };
 
-    let single_drop_str = replace_payload_special_characters(&split[6].to_string());
-    let single_drop: bool = single_drop_str.to_ascii_lowercase().contains("true");
-    return Ok((
-        senders,
-        pool,
-        step_amount,
-        step_period,
-        max_airdrop,
-        min_airdrop,
-        change,
-        single_drop,
-    ));
-}
+    let _ = save_contract(&contract, payload, txid, true);
+    if !pending {
+        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
+        }
 
-pub fn handle_claim_diminishing_airdrop_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(String, String), String> {
-    let words: Vec<&str> = payload.split("CLAIM_DIMAIRDROP").collect();
-    if words.len() < 2 {
-        return Err("Invalid dim airdrop claim payload".to_string());
-    }
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+        let _ = save_contract(&contract, payload, txid, false);
+    } else {
+        let mut data = format!("{}:P-O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
+        }
 
-    let sendsplit: Vec<&str> = words[1].split(",").collect();
-    if sendsplit.len() < 2 {
-        return Err("Invalid dim airdrop claim payload".to_string());
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
-
-    let claim_id = replace_payload_special_characters(&sendsplit[0].to_string());
-    let reciever_split = replace_payload_special_characters(&sendsplit[1].to_string());
-    let reciever = reciever_split.replace("TXID", txid);
-    return Ok((claim_id, reciever));
 }
 
-pub fn handle_claim_dge_payload(txid: &str, payload: &str) -> Result<(String, String), String> {
-    let words: Vec<&str> = payload.split("CLAIM_DGE").collect();
-    if words.len() < 2 {
-        return Err("Invalid dge claim payload".to_string());
-    }
+pub async fn perform_create_dge(txid: &str, command: &str, payload: &str, pending: bool) {
+    let contract_id = match extract_contract_id(command) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
+            return;
+        }
+    };
 
-    let sendsplit: Vec<&str> = words[1].split(",").collect();
-    if sendsplit.len() < 2 {
-        return Err("Invalid dge claim payload".to_string());
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => {
+            record_failed_transaction(txid, "read_contract_failed");
+            return;
+        }
+    };
+
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
+        return;
     }
 
-    let claim_id = replace_payload_special_characters(&sendsplit[0].to_string());
-    let reciever_split = replace_payload_special_characters(&sendsplit[1].to_string());
-    let reciever = reciever_split.replace("TXID", txid);
-    return Ok((claim_id, reciever));
-}
+    let (dge_command, claim_rules) = match split_claim_rules(command) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, "claim_rules_invalid");
+            return;
+        }
+    };
 
-pub fn handle_create_dge_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(Vec<String>, u64, u64, u64, u64, String, String, bool), String> {
-    let words: Vec<&str> = payload.split("DGE").collect();
-    if words.len() < 2 {
-        return Err("Invalid dge creation payload".to_string());
-    }
-
-    let sendsplit: Vec<&str> = words[1].split("],").collect();
-    if sendsplit.len() < 2 {
-        return Err("Invalid dge creation payload".to_string());
-    }
+    let results = match handle_create_dge_payload(txid, &dge_command) {
+        Ok(results) => results,
+        Err(_) => {
+            record_failed_transaction(txid, "handle_create_dge_payload_failed");
+            return;
+        }
+    };
 
-    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
-    let mut senders: Vec<String> = Vec::new();
-    for sendi in sender_strs {
-        let sender = replace_payload_special_characters(&sendi.to_string());
-        senders.push(sender);
-    }
-    let split: Vec<&str> = sendsplit[1].split(",").collect();
-    if split.len() < 7 {
-        return Err("Invalid dge creation payload".to_string());
+    if !check_utxo_inputs(&results.0, txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
+        return;
     }
 
-    let pool_split = replace_payload_special_characters(&split[0].to_string());
-    let pool = match pool_split.parse::<u64>() {
-        Ok(pool) => pool,
-        Err(_) => return Err("Failed to parse pool amount".to_string()),
-    };
-
-    let sats_rate_split = replace_payload_special_characters(&split[1].to_string());
-    let sats_rate = match sats_rate_split.parse::<u64>() {
-        Ok(sats_rate) => sats_rate,
-        Err(_) => return Err("Failed to parse sats rate".to_string()),
+    let (start_block, end_block, soft_cap, hard_cap) = results.8;
+    let dge: DGE = DGE {
+        pool_amount: results.1,
+        sats_rate: results.2,
+        max_drop: results.3,
+        current_amount_dropped: 0,
+        donations_address: results.5,
+        drip_duration: results.4,
+        donaters: HashMap::new(),
+        single_drop: results.7,
+        claim_rules,
+        start_block,
+        end_block,
+        soft_cap,
+        hard_cap,
+        sats_raised: None,
+        refunds: None,
+        held: None,
+        soft_cap_met: None,
     };
 
-    let max_drop_split = replace_payload_special_characters(&split[2].to_string());
-    let max_drop = match max_drop_split.parse::<u64>() {
-        Ok(max_drop) => max_drop,
-        Err(_) => return Err("Failed to parse max drop".to_string()),
+    let current_block_height = match get_current_block_height().await {
+        Ok(current_block_height) => current_block_height as u64,
+        Err(_) => {
+            record_failed_transaction(txid, "get_current_block_height_failed");
+            return;
+        }
     };
 
-    let drip_duration_split = replace_payload_special_characters(&split[3].to_string());
-    let drip_duration = match drip_duration_split.parse::<u64>() {
-        Ok(drip_duration) => drip_duration,
-        Err(_) => return Err("Failed to parse drip duration".to_string()),
+    let new_owners = match contract.create_dge(
+        &txid.to_string(),
+        &payload.to_string(),
+        &results.0,
+        dge,
+        &results.6,
+        current_block_height,
+    ) {
+        Ok(res) => res,
+        Err(_) => {
+            record_failed_transaction(txid, "create_dge_failed");
+            return;
+        }
     };
 
-    let address_split = replace_payload_special_characters(&split[4].to_string());
+    let _ = save_contract(&contract, payload, txid, true);
 
-    let change_split = replace_payload_special_characters(&split[5].to_string());
-    let change = change_split.replace("TXID", txid);
+    if !pending {
+        for s in &results.0 {
+            let file_path = format!("./Json/UTXOS/{}.txt", s);
+            let _ = fs::remove_file(file_path);
+        }
 
-    let single_drop_str = replace_payload_special_characters(&split[6].to_string());
-    let single_drop: bool = single_drop_str.to_ascii_lowercase().contains("true");
-    return Ok((
-        senders,
-        pool,
-        sats_rate,
-        max_drop,
-        drip_duration,
-        address_split,
-        change,
-        single_drop,
-    ));
-}
+        let mut data = format!("{}:O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
+        }
 
-pub fn read_contract(contract_id: &str, pending: bool) -> Result<SCL01Contract, String> {
-    let mut path = "./Json/Contracts/".to_string() + "/" + contract_id + "/state.txt";
-    if pending {
-        path = "./Json/Contracts/".to_string() + "/" + contract_id + "/pending.txt";
-    }
-    match read_from_file(path) {
-        Some(contract_obj) => {
-            let parsed_data: Result<SCL01Contract, serde_json::Error> =
-                serde_json::from_str(&contract_obj);
-            match parsed_data {
-                Ok(data) => return Ok(data),
-                Err(_) => return Err("Failed to deserialize contract".to_string()),
-            }
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+        let _ = save_contract(&contract, payload, txid, false);
+    } else {
+        let mut data = format!("{}:P-O-,{}", &contract.contractid, new_owners.1);
+        if new_owners.2 {
+            data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
         }
-        None => return Err("Could not find contract".to_string()),
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
 }
 
-pub fn save_contract(
-    contract: &SCL01Contract,
-    _payload: &str,
-    _txid: &str,
+pub async fn perform_claim_dge(
+    txid: &str,
+    command: &str,
+    payload: &str,
     pending: bool,
-) -> Result<String, String> {
-    let path: String;
-    if !pending {
-        path = format!(
-            "{}/{}/state.txt",
-            "./Json/Contracts/",
-            contract.contractid.to_string()
-        );
-    } else {
-        path = format!(
-            "{}/{}/pending.txt",
-            "./Json/Contracts/", contract.contractid
-        );
-    }
-
-    match serde_json::to_string(&contract) {
-        Ok(state_string) => write_to_file(path, state_string),
-        Err(_) => return Err("Failed to save updated contract".to_string()),
+    esplora: String,
+) {
+    let contract_id = match extract_contract_id(command) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
+            return;
+        }
     };
 
-    return Ok("Success".to_string());
-}
-
-pub fn handle_mint_payload(
-    payload: &str,
-    txid: &str,
-) -> Result<(String, String, u64, u64), String> {
-    let mut mint_strings: Vec<String> = Vec::new();
-    let mut mint_values: Vec<u64> = Vec::new();
-    let re = match Regex::new(r"\[([^,]+),([^,]+),([^,]+),([^]]+)]") {
-        Ok(re) => re,
-        Err(_) => return Err("Not mint valid payload".to_string()),
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => {
+            record_failed_transaction(txid, "read_contract_failed");
+            return;
+        }
     };
 
-    if let Some(captures) = re.captures(payload) {
-        let ticker = match captures.get(1) {
-            Some(ticker) => ticker.as_str(),
-            None => return Err("Not mint valid payload".to_string()),
-        };
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
+        return;
+    }
 
-        let max_supply_str = match captures.get(2) {
-            Some(max_supply_str) => max_supply_str.as_str(),
-            None => return Err("Not mint valid payload".to_string()),
-        };
+    let results = match handle_claim_dge_payload(txid, command) {
+        Ok(results) => results,
+        Err(_) => {
+            record_failed_transaction(txid, "handle_claim_dge_payload_failed");
+            return;
+        }
+    };
 
-        let decimals_str = match captures.get(3) {
-            Some(decimals_str) => decimals_str.as_str(),
-            None => return Err("Not mint valid payload".to_string()),
-        };
+    let dges = match contract.dges.clone() {
+        Some(dges) => dges,
+        None => {
+            record_failed_transaction(txid, "no_dges");
+            return;
+        }
+    };
 
-        let txid_n = match captures.get(4) {
-            Some(txid_n) => txid_n.as_str(),
-            None => return Err("Not mint valid payload".to_string()),
-        };
+    let dge: DGE = match dges.get(&results.0) {
+        Some(dge) => dge.clone(),
+        None => {
+            record_failed_transaction(txid, "dge_not_found");
+            return;
+        }
+    };
 
-        let temp: Vec<_> = txid_n.split(":").collect();
-        let mut t_n = txid_n.to_string();
-        if temp.len() == 2 {
-            let index = temp[1];
-            t_n = format!("{}:{}", &txid, index);
+    let url: String = esplora.to_string() + "tx/" + txid;
+    let response = match handle_get_request(url).await {
+        Some(response) => response,
+        None => {
+            record_failed_transaction(txid, "handle_get_request_failed");
+            return;
         }
+    };
 
-        // Parse strings to numeric types
-        let max_supply = match max_supply_str.parse() {
-            Ok(max_supply) => max_supply,
-            Err(_) => return Err("Not mint valid payload".to_string()),
-        };
+    let tx_info: TxInfo = match serde_json::from_str::<TxInfo>(&response) {
+        Ok(tx_info) => tx_info,
+        Err(_) => {
+            record_failed_transaction(txid, "serde_json_parse_txinfo_failed");
+            return;
+        }
+    };
 
-        let decimals = match decimals_str.parse() {
-            Ok(decimals) => decimals,
-            Err(_) => return Err("Not mint valid payload".to_string()),
-        };
+    let vin = match tx_info.vin {
+        Some(vin) => vin,
+        None => {
+            record_failed_transaction(txid, "no_vin_in_txinfo");
+            return;
+        }
+    };
 
-        mint_strings.push(ticker.to_string());
-        mint_strings.push(t_n.to_string());
-        mint_values.push(max_supply);
-        mint_values.push(decimals);
-        return Ok((ticker.to_string(), t_n, max_supply, decimals));
+    if vin.is_empty() {
+        record_failed_transaction(txid, "vin_empty");
+        return;
     }
-    return Err("Not mint valid payload".to_string());
-}
 
-pub fn handle_mint_rtm_payload(
-    payload: &str,
-    txid: &str,
-) -> Result<(String, u64, HashMap<String, u64>), String> {
-    let words: Vec<&str> = payload.split("SCL03:").collect();
-    if words.len() < 2 {
-        return Err("Invalid transfer payload".to_string());
-    }
-
-    let mint_split: Vec<&str> = words[1].split(",").collect();
-    if mint_split.len() < 2 {
-        return Err("Invalid mint rtm payload".to_string());
-    }
+    let prev_outputs = match &vin[0].prevout {
+        Some(prev) => prev,
+        None => {
+            record_failed_transaction(txid, "no_prevout_in_vin");
+            return;
+        }
+    };
 
-    let ticker = replace_payload_special_characters(&mint_split[0].to_string());
-    let decimal_split = replace_payload_special_characters(&mint_split[1].to_string());
+    let donater_pub_address = match &prev_outputs.scriptpubkey_address {
+        Some(donater_pub_address) => donater_pub_address.clone(),
+        None => {
+            record_failed_transaction(txid, "no_scriptpubkey_address_in_prevout");
+            return;
+        }
+    };
 
-    // Parse strings to numeric types
-    let decimals = match decimal_split.parse() {
-        Ok(decimals) => decimals,
-        Err(_) => return Err("Not mint valid payload".to_string()),
+    let vout = match tx_info.vout {
+        Some(vout) => vout,
+        None => {
+            record_failed_transaction(txid, "no_vout_in_txinfo");
+            return;
+        }
     };
 
-    let rights_split: Vec<&str> = words[1].split(",[").collect();
-    if rights_split.len() < 2 {
-        return Err("Invalid mint rtm payload".to_string());
+    let mut donation_amout = 0;
+    for output in vout {
+        let address = match output.scriptpubkey_address {
+            Some(address) => address,
+            None => continue,
+        };
+
+        let value = match output.value {
+            Some(value) => value,
+            None => continue,
+        };
+
+        if address == dge.donations_address {
+            donation_amout += value;
+        }
     }
 
-    let rights: Vec<&str> = rights_split[1].split(",").collect();
-    let mut rights_recievers: HashMap<String, u64> = HashMap::new();
-    if rights.len() < 1 {
-        return Err("Invalid mint rtm payload".to_string());
+    if donation_amout == 0 {
+        record_failed_transaction(txid, "donation_amount_zero");
+        return;
     }
-    for reci in rights {
-        let rec_str = reci.replace("TXID", txid);
-        let recievers = replace_payload_special_characters(&rec_str.to_string());
-        let data: Vec<&str> = recievers.split("(").collect();
-        if data.len() < 2 {
-            return Err("Invalid Mint payload".to_string());
-        }
 
-        let rec_utxo = data[0];
-        let rec_amt_str = data[1].replace(")", "");
-        let rec_amt = match rec_amt_str.parse::<u64>() {
-            Ok(rec_amt) => rec_amt,
-            Err(_) => return Err("Failed to parse recieved amount".to_string()),
+    let mut claimer: Option<ClaimerInfo> = None;
+    if let Some(claim_rules) = &dge.claim_rules {
+        claimer = match get_claimer_info(txid, claim_rules, pending).await {
+            Ok(claimer) => Some(claimer),
+            Err(err) => {
+                println!("{}", err);
+                record_failed_transaction(txid, "get_claimer_info_failed");
+                return;
+            }
         };
-
-        rights_recievers.insert(rec_utxo.to_string(), rec_amt);
     }
 
-    return Ok((ticker.to_string(), decimals, rights_recievers));
-}
+    let current_block = match get_current_block_height().await {
+        Ok(current_block) => current_block as u64,
+        Err(_) => {
+            record_failed_transaction(txid, "get_current_block_height_failed");
+            return;
+        }
+    };
 
-pub fn handle_rtm_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(String, String, String, u64), String> {
-    let words: Vec<&str> = payload.split("RIGHTTOMINT").collect();
-    if words.len() < 2 {
-        return Err("Invalid rtm payload".to_string());
-    }
+    let donation_block = match get_tx_block_info(txid).await {
+        Some((donation_block, _)) => donation_block,
+        None => current_block,
+    };
 
-    let sendsplit: Vec<&str> = words[1].split(",").collect();
-    if sendsplit.len() < 4 {
-        return Err("Invalid rtm payload".to_string());
+    let new_owners = match contract.claim_dge(
+        &txid.to_string(),
+        &payload.to_string(),
+        &results.0,
+        &results.1,
+        &donater_pub_address,
+        donation_amout,
+        &claimer,
+        donation_block,
+        current_block,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            record_failed_transaction(txid, &claim_failure_reason(&err, "claim_dge_failed"));
+            return;
+        }
+    };
+
+    // A donation refunded in full allocates no tokens so the reciever utxo is left untouched
+    let _ = save_contract(&contract, payload, txid, true);
+    if !pending {
+        if new_owners.2 > 0 {
+            let data = format!("{}:DO-,{}", &contract.contractid, new_owners.1);
+            write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
+        }
+
+        let _ = save_contract(&contract, payload, txid, false);
+    } else if new_owners.2 > 0 {
+        let data = format!("{}:P-DO-,{}", &contract.contractid, new_owners.1);
+        write_to_file(format!("./Json/UTXOS/{}.txt", &new_owners.0), data.clone());
     }
+}
 
-    let rights_utxo = replace_payload_special_characters(&sendsplit[0].to_string());
-    let reciever_split = replace_payload_special_characters(&sendsplit[1].to_string());
-    let reciever = reciever_split.replace("TXID", txid);
-    let change_split = replace_payload_special_characters(&sendsplit[2].to_string());
-    let change = change_split.replace("TXID", txid);
-    let mint_amt_split = replace_payload_special_characters(&sendsplit[3].to_string());
-    let mint_amt = match mint_amt_split.parse::<u64>() {
-        Ok(mint_amt_split) => mint_amt_split,
-        Err(_) => return Err("Failed to parse mint amount".to_string()),
+pub fn perform_drips(contract_id: String, block_height: u64, pending: bool) {
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
     };
 
-    return Ok((rights_utxo, reciever, change, mint_amt));
-}
+    let events = contract.drip_events(block_height);
+    let completed = contract.completed_drips(block_height);
+    let new_owners = match contract.drip(block_height) {
+        Ok(res) => res,
+        Err(_) => return,
+    };
 
-pub fn handle_transfer_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(Vec<String>, Vec<(String, u64)>, String), String> {
-    let words: Vec<&str> = payload.split("TRANSFER").collect();
-    if words.len() < 2 {
-        return Err("Invalid transfer payload".to_string());
+    if new_owners.is_empty() {
+        return;
     }
 
-    let sendsplit: Vec<&str> = words[1].split("],[").collect();
-    if sendsplit.len() < 2 {
-        return Err("Invalid transfer payload".to_string());
-    }
+    let _ = save_contract(&contract, "", "", true);
 
-    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
-    let rec_split: Vec<&str> = sendsplit[1].split(",").collect();
-    let mut senders: Vec<String> = Vec::new();
-    for sendi in sender_strs {
-        let sender = replace_payload_special_characters(&sendi.to_string());
-        senders.push(sender);
-    }
-    let mut last_output = String::new();
-    let mut rec_dict: Vec<(String, u64)> = Vec::new();
-    for reci in &rec_split {
-        let rec_str = reci.replace("TXID", txid);
-        let recievers = replace_payload_special_characters(&rec_str.to_string());
-        let data: Vec<&str> = recievers.split("(").collect();
-        if data.len() < 2 {
-            return Err("Invalid transfer payload".to_string());
+    if !pending {
+        for (key, value, drip) in new_owners.clone() {
+            let mut data = format!("{}:O-,{}", &contract.contractid, value);
+            if drip {
+                data = format!("{}:DO-,{}", &contract.contractid, value);
+            }
+            let _ = fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone());
         }
-        let rec_utxo = data[0];
-        let rec_amt_str = data[1].replace(")", "");
-        let rec_amt = match rec_amt_str.parse::<u64>() {
-            Ok(rec_amt) => rec_amt,
-            Err(_) => {
-                return Err("Failed to parse recieved amount".to_string());
+
+        // Recorded once the settled contract is saved so a run that fails to save is not logged
+        if save_contract(&contract, "", "", false).is_ok() {
+            record_drip_history(&contract_id, &events, &completed);
+        }
+    } else {
+        for (key, value, drip) in new_owners.clone() {
+            let mut data = format!("{}:P-O-,{}", &contract.contractid, value);
+            if drip {
+                data = format!("{}:P-DO-,{}", &contract.contractid, value);
             }
-        };
-        if &rec_split.last().unwrap() == &reci {
-            last_output = rec_utxo.to_string();
+            let _ = fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone());
         }
-        rec_dict.push((rec_utxo.to_string(), rec_amt));
     }
+}
 
-    return Ok((senders, rec_dict, last_output));
+// Drip events and completed drips are appended a line at a time and never rewritten
+pub fn read_drip_history(contract_id: &str, utxo: &str) -> DripHistory {
+    let directory = format!("./Json/Contracts/{}", contract_id);
+    return DripHistory {
+        events: read_drip_records(format!("{}/DripEvents/{}.txt", directory, utxo)),
+        completed: read_drip_records(format!("{}/DripCompleted/{}.txt", directory, utxo)),
+    };
 }
 
-pub fn handle_drip_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(Vec<String>, HashMap<String, (u64, u64)>, String), String> {
-    let words: Vec<&str> = payload.split("DRIP").collect();
-    if words.len() < 2 {
-        return Err("Invalid drip payload".to_string());
+fn read_drip_records<T: DeserializeOwned>(path: String) -> Vec<T> {
+    let records_str = match read_from_file(path) {
+        Some(records_str) => records_str,
+        None => return Vec::new(),
+    };
+
+    return records_str
+        .lines()
+        .filter_map(|line| serde_json::from_str::<T>(line).ok())
+        .collect();
+}
+
+pub fn record_drip_history(
+    contract_id: &str,
+    events: &Vec<DripEvent>,
+    completed: &Vec<DripStatus>,
+) {
+    let directory = format!("./Json/Contracts/{}", contract_id);
+    if !events.is_empty() {
+        let _ = fs::create_dir_all(format!("{}/DripEvents", directory));
     }
 
-    let sendsplit: Vec<&str> = words[1].split("],").collect();
-    if sendsplit.len() < 3 {
-        return Err("Invalid drip payload".to_string());
+    if !completed.is_empty() {
+        let _ = fs::create_dir_all(format!("{}/DripCompleted", directory));
     }
 
-    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
-    let rec_split: Vec<&str> = sendsplit[1].split("),").collect();
-    let change_split = replace_payload_special_characters(&sendsplit[2].to_string());
-    let change = change_split.replace("TXID", txid);
-    let mut senders: Vec<String> = Vec::new();
-    for sendi in sender_strs {
-        let sender = replace_payload_special_characters(&sendi.to_string());
-        senders.push(sender);
+    for event in events {
+        append_drip_record(
+            format!("{}/DripEvents/{}.txt", directory, event.utxo),
+            event,
+        );
     }
 
-    let mut rec_dict: HashMap<String, (u64, u64)> = HashMap::new();
-    for reci in rec_split {
-        let rec_str = reci.replace("TXID", txid);
-        let recievers = replace_payload_special_characters(&rec_str.to_string());
-        let data: Vec<&str> = recievers.split("(").collect();
-        if data.len() < 2 {
-            return Err("Invalid Drip payload".to_string());
-        }
+    for status in completed {
+        append_drip_record(
+            format!("{}/DripCompleted/{}.txt", directory, status.utxo),
+            status,
+        );
+    }
+}
 
-        let rec_utxo = data[0];
-        let rec_amt_str = data[1].replace(")", "");
-        let tuple_str: Vec<_> = rec_amt_str.split(",").collect();
-        if tuple_str.len() < 2 {
-            continue;
+fn append_drip_record<T: Serialize>(path: String, record: &T) {
+    match serde_json::to_string(record) {
+        Ok(record_str) => {
+            append_to_file(path, format!("{}\n", record_str));
         }
-        let rec_amt = match tuple_str[0].parse::<u64>() {
-            Ok(rec_amt) => rec_amt,
-            Err(_) => return Err("Failed to parse recieved amount".to_string()),
-        };
-        let dur_amt = match tuple_str[1].parse::<u64>() {
-            Ok(dur_amt) => dur_amt,
-            Err(_) => return Err("Failed to parse duration amount".to_string()),
-        };
+        Err(_) => println!("Unable to save drip history"),
+    };
+}
+
+pub fn perform_dge_settlement(contract_id: String, block_height: u64) {
+    let mut contract = match read_contract(contract_id.as_str(), false) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    let new_owners = match contract.settle_dges(block_height) {
+        Some(new_owners) => new_owners,
+        None => return,
+    };
 
-        rec_dict.insert(rec_utxo.to_string(), (rec_amt, dur_amt));
+    let _ = save_contract(&contract, "", "", true);
+    for (key, value, _) in new_owners {
+        let data = format!("{}:DO-,{}", &contract.contractid, value);
+        write_to_file(format!("./Json/UTXOS/{}.txt", &key), data);
     }
-    return Ok((senders, rec_dict, change));
+
+    let _ = save_contract(&contract, "", "", false);
 }
 
-pub fn handle_burn_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(Vec<String>, u64, String), String> {
-    let words: Vec<&str> = payload.split("BURN").collect();
-    if words.len() < 2 {
-        return Err("Invalid burn payload".to_string());
-    }
+pub fn perform_order_expiry(contract_id: String, block_height: u64) {
+    let mut contract = match read_contract(contract_id.as_str(), false) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
 
-    let burn_split: Vec<&str> = words[1].split("],").collect();
-    if burn_split.len() < 2 {
-        return Err("Invalid burn payload".to_string());
+    let (new_owners, bids_removed) = match contract.expire_orders(block_height) {
+        Ok(res) => res,
+        Err(_) => return,
+    };
+
+    if new_owners.is_empty() && bids_removed.is_empty() {
+        return;
     }
-    let burners_split: Vec<&str> = burn_split[0].split(",").collect();
-    let mut burners: Vec<String> = Vec::new();
-    for burner in burners_split.clone() {
-        let clean_burner = replace_payload_special_characters(&burner.to_string());
-        burners.push(clean_burner);
+
+    let _ = save_contract(&contract, "", "", true);
+    for (key, value) in new_owners {
+        let data = format!("{}:O-,{}", &contract.contractid, value);
+        let _ = fs::write(format!("./Json/UTXOS/{}.txt", &key), data.clone());
     }
 
-    let burn_info: Vec<&str> = burn_split[1].split(",").collect();
-    if burn_info.len() < 2 {
-        return Err("Invalid burn payload".to_string());
+    for s in bids_removed {
+        let file_path = format!("./Json/UTXOS/{}.txt", s);
+        let _ = fs::remove_file(file_path);
     }
 
-    let burn_amount_str = replace_payload_special_characters(&burn_info[0].to_string());
+    let _ = save_contract(&contract, "", "", false);
+}
 
-    let amt = match burn_amount_str.parse::<u64>() {
-        Ok(amt) => amt,
-        Err(_) => return Err("Invalid burn payload".to_string()),
+pub async fn perform_listing_cancel(txid: &str, payload: &str, pending: bool) {
+    let contract_id = match extract_contract_id(payload) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
+            return;
+        }
     };
 
-    let mut change_str = replace_payload_special_characters(&burn_info[1].to_string());
-    change_str = change_str.replace("TXID", txid);
-    for s in &burners {
-        let file_path = format!("./Json/UTXOS/{}.txt", s);
-        // Attempt to remove the file
-        match fs::remove_file(file_path) {
-            Ok(_) => {}
-            Err(_) => {}
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => {
+            record_failed_transaction(txid, "read_contract_failed");
+            return;
         }
+    };
+
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
+        return;
     }
-    return Ok((burners, amt, change_str));
-}
 
-pub fn handle_list_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<(Vec<String>, String, String, String, u64, u64), String> {
-    let words: Vec<&str> = payload.split("LIST").collect();
+    let words: Vec<&str> = payload.split("CANCELLISTING").collect();
     if words.len() < 2 {
-        return Err("Invalid List payload".to_string());
+        record_failed_transaction(txid, "malformed_cancel_listing_command");
+        return;
     }
 
-    let list_split: Vec<&str> = words[1].split("],").collect();
-    if list_split.len() < 2 {
-        return Err("Invalid List payload".to_string());
+    let listing_utxo = replace_payload_special_characters(&words[1].to_string());
+    let utxos: Vec<String> = vec![listing_utxo.clone()];
+    if !check_utxo_inputs(&utxos, txid).await {
+        record_failed_transaction(txid, "check_utxo_inputs_failed");
+        return;
     }
 
-    let lists_split: Vec<&str> = list_split[0].split(",").collect();
-    let listing_info: Vec<&str> = list_split[1].split(",").collect();
-    let mut listings_senders: Vec<String> = Vec::new();
-    for lising in lists_split.clone() {
-        let temp1 = lising.to_string().replace("[", "");
-        let temp2 = temp1.replace("]", "");
-        listings_senders.push(temp2);
-    }
+    let file_path = format!("./Json/UTXOS/{}.txt", listing_utxo);
+    // Attempt to remove the file
+    let _ = fs::remove_file(file_path);
 
-    if listing_info.len() < 5 {
-        return Err("Invalid List payload".to_string());
+    let (owner, bids) = match contract.cancel_listing(
+        &txid.to_string(),
+        &listing_utxo.to_string(),
+        payload.to_string(),
+    ) {
+        Ok(owner) => owner,
+        Err(_) => {
+            record_failed_transaction(txid, "cancel_listing_failed");
+            return;
+        }
+    };
+
+    let _ = save_contract(&contract, payload, txid, pending);
+    if !pending {
+        let _ = save_contract(&contract, payload, txid, false);
+        let data = format!("{}:O-,{}", &contract.contractid, owner.1);
+        write_to_file(format!("./Json/UTXOS/{}.txt", &owner.0), data.clone());
+        for s in &bids {
+            let file_path = format!("./Json/UTXOS/{}.txt", s);
+            let _ = fs::remove_file(file_path);
+        }
+    } else {
+        let data = format!("{}:P-O-,{}", &contract.contractid, owner.1);
+        write_to_file(format!("./Json/UTXOS/{}.txt", owner.0), data.clone());
     }
+}
 
-    let mut change_str = replace_payload_special_characters(&listing_info[0].to_string());
-    change_str = change_str.replace("TXID", txid);
-    let mut listing_utxo_str = replace_payload_special_characters(&listing_info[1].to_string());
-    listing_utxo_str = listing_utxo_str.replace("TXID", txid);
-    let list_amount_str = replace_payload_special_characters(&listing_info[2].to_string());
-    let sell_price_str = replace_payload_special_characters(&listing_info[3].to_string());
-    let pay_address_str = replace_payload_special_characters(&listing_info[4].to_string());
-    let listing_amt = match list_amount_str.parse::<u64>() {
-        Ok(amt) => amt,
-        Err(_) => return Err("Invalid List payload. Listing amount invalid".to_string()),
+pub async fn perform_bid_cancel(txid: &str, payload: &str, pending: bool) {
+    let contract_id = match extract_contract_id(payload) {
+        Ok(contract_id) => contract_id,
+        Err(_) => {
+            record_failed_transaction(txid, "extract_contract_id_failed");
+            return;
+        }
     };
 
-    let sell_price = match sell_price_str.parse::<u64>() {
-        Ok(amt) => amt,
-        Err(_) => return Err("Invalid List payload. Sell price invalid".to_string()),
+    let mut contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => {
+            record_failed_transaction(txid, "read_contract_failed");
+            return;
+        }
     };
 
-    return Ok((
-        listings_senders,
-        change_str,
-        listing_utxo_str,
-        pay_address_str,
-        listing_amt,
-        sell_price,
-    ));
-}
-
-pub fn handle_bid_payload(
-    txid: &str,
-    payload: &str,
-) -> Result<Vec<(String, u64, u64, String)>, String> {
-    let words: Vec<&str> = payload.split("BID").collect();
-    let bid_split: Vec<&str> = words[1].split("],").collect();
-    if bid_split.len() < 1 {
-        return Err("Invalid Bid payload. Sell price invalid".to_string());
+    if contract.payloads.iter().any(|(tx, _)| tx == txid) {
+        record_failed_transaction(txid, "duplicate_txid_in_payloads");
+        return;
     }
 
-    let mut bid_results: Vec<(String, u64, u64, String)> = Vec::new();
-    for split in bid_split {
-        let bid_info: Vec<&str> = split.split(",").collect();
-        if bid_info.len() < 4 {
-            continue;
-        }
+    let words: Vec<&str> = payload.split("CANCELBID").collect();
+    if words.len() < 2 {
+        return;
+    }
 
-        let order_id_split = replace_payload_special_characters(&bid_info[0].to_string());
-        let amount_split = replace_payload_special_characters(&bid_info[1].to_string());
-        let amt = match amount_split.parse::<u64>() {
-            Ok(amt) => amt,
-            Err(_) => continue,
-        };
+    let bidding_utxo = replace_payload_special_characters(&words[1].to_string());
+    let utxos: Vec<String> = vec![bidding_utxo.clone()];
+    if !check_utxo_inputs(&utxos, txid).await {
+        return;
+    }
 
-        let price_split = replace_payload_special_characters(&bid_info[2].to_string());
-        let price = match price_split.parse::<u64>() {
-            Ok(amt) => amt,
-            Err(_) => continue,
-        };
+    let file_path = format!("./Json/UTXOS/{}.txt", bidding_utxo);
+    // Attempt to remove the file
+    let _ = fs::remove_file(file_path);
 
-        let mut res_utxo_str = replace_payload_special_characters(&bid_info[3].to_string());
-        res_utxo_str = res_utxo_str.replace("TXID", txid);
-        bid_results.push((order_id_split, amt, price, res_utxo_str));
+    match contract.cancel_bid(
+        &txid.to_string(),
+        &bidding_utxo.to_string(),
+        payload.to_string(),
+    ) {
+        Ok(_) => {}
+        Err(_) => return,
     }
 
-    return Ok(bid_results);
+    let _ = save_contract(&contract, payload, txid, pending);
+    if !pending {
+        let _ = save_contract(&contract, payload, txid, false);
+    }
 }
 
-pub fn handle_payload_extra_trade_info(payload: &str) -> Result<(String, u64, u64), String> {
-    let words: Vec<&str> = payload.split("-ExtraInfo-").collect();
+pub fn handle_create_diminishing_airdrop_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(Vec<String>, u64, u64, u64, u64, u64, String, bool), String> {
+    let words: Vec<&str> = payload.split("DIMAIRDROP").collect();
     if words.len() < 2 {
-        return Err("Invalid payload".to_string());
+        return Err("Invalid dim airdrop payload".to_string());
     }
-    let bid_split: Vec<&str> = words[1].split(",").collect();
-    if bid_split.len() < 3 {
-        return Err("Invalid payload".to_string());
+
+    let sendsplit: Vec<&str> = words[1].split("],").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid drip payload".to_string());
     }
 
-    let bid_id_split = replace_payload_special_characters(&bid_split[0].to_string());
-    let amount_split = replace_payload_special_characters(&bid_split[1].to_string());
-    let amount = match amount_split.parse::<u64>() {
-        Ok(amount) => amount,
-        Err(_) => return Err("Invalid payload".to_string()),
+    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
+    let mut senders: Vec<String> = Vec::new();
+    for sendi in sender_strs {
+        let sender = replace_payload_special_characters(&sendi.to_string());
+        senders.push(sender);
+    }
+    let split: Vec<&str> = sendsplit[1].split(",").collect();
+    if split.len() < 7 {
+        return Err("Invalid drip payload".to_string());
+    }
+
+    let pool_split = replace_payload_special_characters(&split[0].to_string());
+    let pool = match pool_split.parse::<u64>() {
+        Ok(pool) => pool,
+        Err(_) => return Err("Failed to parse pool amount".to_string()),
     };
 
-    let price_split = replace_payload_special_characters(&bid_split[2].to_string());
-    let price = match price_split.parse::<u64>() {
-        Ok(amt) => amt,
-        Err(_) => return Err("Invalid payload".to_string()),
+    let step_amount_split = replace_payload_special_characters(&split[1].to_string());
+    let step_amount = match step_amount_split.parse::<u64>() {
+        Ok(step_amount) => step_amount,
+        Err(_) => return Err("Failed to parse step down amount".to_string()),
     };
 
-    return Ok((bid_id_split, amount, price));
-}
+    let step_period_split = replace_payload_special_characters(&split[2].to_string());
+    let step_period = match step_period_split.parse::<u64>() {
+        Ok(step_period) => step_period,
+        Err(_) => return Err("Failed to parse min airdrop amount".to_string()),
+    };
 
-pub fn convert_old_contracts() {
-    let directory_path = "./Json/Contracts/"; // Your directory path here
-    if let Ok(entries) = fs::read_dir(directory_path) {
-        for entry in entries.flatten() {
-            if let Ok(metadata) = entry.metadata() {
-                if metadata.is_dir() {
-                    if let Some(folder_name) = entry.file_name().into_string().ok() {
-                        let file_path = format!("./Json/Contracts/{}/state.txt", &folder_name);
-                        let user_data_str = match fs::read_to_string(&file_path) {
-                            Ok(user_data_str) => user_data_str,
-                            Err(_) => continue,
-                        };
+    let max_airdrop_split = replace_payload_special_characters(&split[3].to_string());
+    let max_airdrop = match max_airdrop_split.parse::<u64>() {
+        Ok(max_airdrop) => max_airdrop,
+        Err(_) => return Err("Failed to parse max airdrop amount".to_string()),
+    };
 
-                        // Deserialize user data from JSON
-                        let mut user_data: SCL01Contract =
-                            match serde_json::from_str(&user_data_str) {
-                                Ok(user_data) => user_data,
-                                Err(_) => continue,
-                            };
+    let min_airdrop_split = replace_payload_special_characters(&split[4].to_string());
+    let min_airdrop = match min_airdrop_split.parse::<u64>() {
+        Ok(min_airdrop) => min_airdrop,
+        Err(_) => return Err("Failed to parse min airdrop amount".to_string()),
+    };
 
-                        let airdrop_amount = match user_data.airdrop_amount {
-                            Some(airdrop_amount) => airdrop_amount,
-                            None => continue,
-                        };
+    let change_split = replace_payload_special_characters(&split[5].to_string());
+    let change = change_split.replace("TXID", txid);
 
-                        let current_airdrops = match user_data.current_airdrops {
-                            Some(current_airdrops) => current_airdrops,
-                            None => continue,
-                        };
+    let single_drop_str = replace_payload_special_characters(&split[6].to_string());
+    let single_drop: bool = single_drop_str.to_ascii_lowercase().contains("true");
+    return Ok((
+        senders,
+        pool,
+        step_amount,
+        step_period,
+        max_airdrop,
+        min_airdrop,
+        change,
+        single_drop,
+    ));
+}
 
-                        let total_airdrops = match user_data.total_airdrops {
-                            Some(total_airdrops) => total_airdrops,
-                            None => continue,
-                        };
+pub fn handle_claim_diminishing_airdrop_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, String), String> {
+    let words: Vec<&str> = payload.split("CLAIM_DIMAIRDROP").collect();
+    if words.len() < 2 {
+        return Err("Invalid dim airdrop claim payload".to_string());
+    }
 
-                        user_data.max_supply = Some(total_airdrops * airdrop_amount);
-                        user_data.supply = current_airdrops * airdrop_amount;
+    let sendsplit: Vec<&str> = words[1].split(",").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid dim airdrop claim payload".to_string());
+    }
 
-                        let serialised_user_data = match serde_json::to_string(&user_data) {
-                            Ok(serialised_user_data) => serialised_user_data,
-                            Err(_) => continue,
-                        };
+    let claim_id = replace_payload_special_characters(&sendsplit[0].to_string());
+    let reciever_split = replace_payload_special_characters(&sendsplit[1].to_string());
+    let reciever = reciever_split.replace("TXID", txid);
+    return Ok((claim_id, reciever));
+}
 
-                        write_to_file(
-                            format!("./Json/Contracts/{}/state.txt", &folder_name),
-                            serialised_user_data.clone(),
-                        );
-                        write_to_file(
-                            format!("./Json/Contracts/{}/pending.txt", &folder_name),
-                            serialised_user_data,
-                        );
-                    }
+pub fn handle_create_merkle_airdrop_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(Vec<String>, u64, String, u64, String), String> {
+    let words: Vec<&str> = payload.split("MERKLEAIRDROP").collect();
+    if words.len() < 2 {
+        return Err("Invalid merkle airdrop payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split("],").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid merkle airdrop payload".to_string());
+    }
+
+    let mut senders: Vec<String> = Vec::new();
+    for sendi in sendsplit[0].split(",") {
+        let sender = replace_payload_special_characters(&sendi.to_string());
+        senders.push(sender);
+    }
+
+    let split: Vec<&str> = sendsplit[1].split(",").collect();
+    if split.len() < 4 {
+        return Err("Invalid merkle airdrop payload".to_string());
+    }
+
+    let pool_split = replace_payload_special_characters(&split[0].to_string());
+    let pool = match pool_split.parse::<u64>() {
+        Ok(pool) => pool,
+        Err(_) => return Err("Failed to parse pool amount".to_string()),
+    };
+
+    let merkle_root = replace_payload_special_characters(&split[1].to_string());
+
+    let leaf_count_split = replace_payload_special_characters(&split[2].to_string());
+    let leaf_count = match leaf_count_split.parse::<u64>() {
+        Ok(leaf_count) => leaf_count,
+        Err(_) => return Err("Failed to parse leaf count".to_string()),
+    };
+
+    let change_split = replace_payload_special_characters(&split[3].to_string());
+    let change = change_split.replace("TXID", txid);
+    return Ok((senders, pool, merkle_root, leaf_count, change));
+}
+
+pub fn handle_claim_merkle_airdrop_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, u64, u64, String, Vec<String>), String> {
+    let words: Vec<&str> = payload.split("CLAIM_MERKLEAIRDROP").collect();
+    if words.len() < 2 {
+        return Err("Invalid merkle airdrop claim payload".to_string());
+    }
+
+    let split: Vec<&str> = words[1].split(",").collect();
+    if split.len() < 4 {
+        return Err("Invalid merkle airdrop claim payload".to_string());
+    }
+
+    let claim_id = replace_payload_special_characters(&split[0].to_string());
+
+    let index_split = replace_payload_special_characters(&split[1].to_string());
+    let index = match index_split.parse::<u64>() {
+        Ok(index) => index,
+        Err(_) => return Err("Failed to parse leaf index".to_string()),
+    };
+
+    let amount_split = replace_payload_special_characters(&split[2].to_string());
+    let amount = match amount_split.parse::<u64>() {
+        Ok(amount) => amount,
+        Err(_) => return Err("Failed to parse claim amount".to_string()),
+    };
+
+    let reciever_split = replace_payload_special_characters(&split[3].to_string());
+    let reciever = reciever_split.replace("TXID", txid);
+    if !reciever.starts_with(&format!("{}:", txid)) {
+        return Err("Reciever must be an output of the claim transaction".to_string());
+    }
+
+    // A single leaf tree has an empty proof
+    let mut proof: Vec<String> = Vec::new();
+    if split.len() > 4 {
+        let proof_split = replace_payload_special_characters(&split[4].to_string());
+        for hash in proof_split.split(";") {
+            if !hash.is_empty() {
+                proof.push(hash.to_ascii_lowercase());
+            }
+        }
+    }
+
+    return Ok((claim_id, index, amount, reciever, proof));
+}
+
+pub fn handle_claim_dge_payload(txid: &str, payload: &str) -> Result<(String, String), String> {
+    let words: Vec<&str> = payload.split("CLAIM_DGE").collect();
+    if words.len() < 2 {
+        return Err("Invalid dge claim payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split(",").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid dge claim payload".to_string());
+    }
+
+    let claim_id = replace_payload_special_characters(&sendsplit[0].to_string());
+    let reciever_split = replace_payload_special_characters(&sendsplit[1].to_string());
+    let reciever = reciever_split.replace("TXID", txid);
+    return Ok((claim_id, reciever));
+}
+
+pub fn handle_create_dge_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<
+    (
+        Vec<String>,
+        u64,
+        u64,
+        u64,
+        u64,
+        String,
+        String,
+        bool,
+        (Option<u64>, Option<u64>, Option<u64>, Option<u64>),
+    ),
+    String,
+> {
+    let words: Vec<&str> = payload.split("DGE").collect();
+    if words.len() < 2 {
+        return Err("Invalid dge creation payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split("],").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid dge creation payload".to_string());
+    }
+
+    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
+    let mut senders: Vec<String> = Vec::new();
+    for sendi in sender_strs {
+        let sender = replace_payload_special_characters(&sendi.to_string());
+        senders.push(sender);
+    }
+    let split: Vec<&str> = sendsplit[1].split(",").collect();
+    if split.len() < 7 {
+        return Err("Invalid dge creation payload".to_string());
+    }
+
+    let pool_split = replace_payload_special_characters(&split[0].to_string());
+    let pool = match pool_split.parse::<u64>() {
+        Ok(pool) => pool,
+        Err(_) => return Err("Failed to parse pool amount".to_string()),
+    };
+
+    let sats_rate_split = replace_payload_special_characters(&split[1].to_string());
+    let sats_rate = match sats_rate_split.parse::<u64>() {
+        Ok(sats_rate) => sats_rate,
+        Err(_) => return Err("Failed to parse sats rate".to_string()),
+    };
+
+    let max_drop_split = replace_payload_special_characters(&split[2].to_string());
+    let max_drop = match max_drop_split.parse::<u64>() {
+        Ok(max_drop) => max_drop,
+        Err(_) => return Err("Failed to parse max drop".to_string()),
+    };
+
+    let drip_duration_split = replace_payload_special_characters(&split[3].to_string());
+    let drip_duration = match drip_duration_split.parse::<u64>() {
+        Ok(drip_duration) => drip_duration,
+        Err(_) => return Err("Failed to parse drip duration".to_string()),
+    };
+
+    let address_split = replace_payload_special_characters(&split[4].to_string());
+
+    let change_split = replace_payload_special_characters(&split[5].to_string());
+    let change = change_split.replace("TXID", txid);
+
+    let single_drop_str = replace_payload_special_characters(&split[6].to_string());
+    let single_drop: bool = single_drop_str.to_ascii_lowercase().contains("true");
+
+    // The start block, end block, soft cap and hard cap are optional, a missing or zero value
+    // leaves it unset
+    let mut window: Vec<Option<u64>> = Vec::new();
+    for index in 7..11 {
+        let mut value: Option<u64> = None;
+        if let Some(field) = split.get(index) {
+            let field = replace_payload_special_characters(&field.to_string());
+            if !field.is_empty() {
+                match field.parse::<u64>() {
+                    Ok(0) => {}
+                    Ok(number) => value = Some(number),
+                    Err(_) => return Err("Failed to parse dge window".to_string()),
                 }
             }
         }
+
+        window.push(value);
     }
+
+    return Ok((
+        senders,
+        pool,
+        sats_rate,
+        max_drop,
+        drip_duration,
+        address_split,
+        change,
+        single_drop,
+        (window[0], window[1], window[2], window[3]),
+    ));
 }
 
-// Liquidity Pools
-pub fn perform_minting_scl04(txid: &str, payload: &str) {
-    match read_contract(txid, false) {
-        Ok(_) => return,
-        Err(_) => {}
+pub fn read_contract(contract_id: &str, pending: bool) -> Result<SCL01Contract, String> {
+    let mut path = "./Json/Contracts/".to_string() + "/" + contract_id + "/state.txt";
+    if pending {
+        path = "./Json/Contracts/".to_string() + "/" + contract_id + "/pending.txt";
+    }
+    match read_from_file(path) {
+        Some(contract_obj) => {
+            let parsed_data: Result<SCL01Contract, serde_json::Error> =
+                serde_json::from_str(&contract_obj);
+            match parsed_data {
+                Ok(data) => return Ok(data),
+                Err(_) => return Err("Failed to deserialize contract".to_string()),
+            }
+        }
+        None => return Err("Could not find contract".to_string()),
+    }
+}
+
+pub fn save_contract(
+    contract: &SCL01Contract,
+    _payload: &str,
+    _txid: &str,
+    pending: bool,
+) -> Result<String, String> {
+    let path: String;
+    if !pending {
+        path = format!("{}/{}/state.txt", "./Json/Contracts/", contract.contractid);
+    } else {
+        path = format!(
+            "{}/{}/pending.txt",
+            "./Json/Contracts/", contract.contractid
+        );
+    }
+
+    match serde_json::to_string(&contract) {
+        Ok(state_string) => write_to_file(path, state_string),
+        Err(_) => return Err("Failed to save updated contract".to_string()),
     };
 
-    let words: Vec<&str> = payload.split("SCL04:").collect();
-    if words.len() < 2 {
-        println!("Invalid liquidity pool payload");
-        return;
+    return Ok("Success".to_string());
+}
+
+// Payloads can name outputs of their transaction as authorities, e.g. AUTHORITY(utxo) for metadata
+// or MINT_AUTHORITY(utxo;cap), these are taken out of the payload before the fields are parsed
+pub fn split_authority(txid: &str, payload: &str, keyword: &str) -> (String, Option<String>) {
+    let re = match Regex::new(&format!(r",?\s*\b{}\(([^)]*)\)", keyword)) {
+        Ok(re) => re,
+        Err(_) => return (payload.to_string(), None),
+    };
+
+    let authority = match re.captures(payload) {
+        Some(captures) => match captures.get(1) {
+            Some(authority) => {
+                replace_payload_special_characters(&authority.as_str().replace("TXID", txid))
+            }
+            None => return (payload.to_string(), None),
+        },
+        None => return (payload.to_string(), None),
+    };
+
+    let mint_payload = re.replace(payload, "").to_string();
+    if !authority.starts_with(&format!("{}:", txid)) {
+        println!("{} must be an output of the transaction", keyword);
+        return (mint_payload, None);
+    }
+
+    return (mint_payload, Some(authority));
+}
+
+pub fn split_claim_rules(payload: &str) -> Result<(String, Option<ClaimRules>), String> {
+    let re = match Regex::new(r",?\s*\bRULES\(([^)]*)\)") {
+        Ok(re) => re,
+        Err(_) => return Ok((payload.to_string(), None)),
+    };
+
+    let rules = match re.captures(payload) {
+        Some(captures) => match captures.get(1) {
+            Some(rules) => replace_payload_special_characters(&rules.as_str().to_string()),
+            None => return Ok((payload.to_string(), None)),
+        },
+        None => return Ok((payload.to_string(), None)),
+    };
+
+    let claim_rules = ClaimRules::from_payload(&rules)?;
+
+    return Ok((re.replace(payload, "").to_string(), Some(claim_rules)));
+}
+
+pub async fn get_claimer_info(
+    txid: &str,
+    claim_rules: &ClaimRules,
+    pending: bool,
+) -> Result<ClaimerInfo, String> {
+    let tx_info: TxInfo = match get_transaction(txid, false).await {
+        Ok(tx_info) => tx_info,
+        Err(_) => return Err("Unable to get inputs for txid".to_string()),
+    };
+
+    let vin = match tx_info.vin {
+        Some(vin) => vin,
+        None => return Err("Unable to get inputs for txid".to_string()),
+    };
+
+    if vin.is_empty() {
+        return Err("Claim transaction has no inputs".to_string());
+    }
+
+    let prevout = match &vin[0].prevout {
+        Some(prevout) => prevout,
+        None => return Err("Claim transaction first input has no prevout".to_string()),
+    };
+
+    let address = match &prevout.scriptpubkey_address {
+        Some(address) => address.clone(),
+        None => return Err("Claim transaction first input has no address".to_string()),
+    };
+
+    let address_type = match &prevout.scriptpubkey_type {
+        Some(address_type) => address_type.to_ascii_lowercase(),
+        None => String::new(),
+    };
+
+    let mut input_value: u64 = 0;
+    for input in &vin {
+        if let Some(prevout) = &input.prevout {
+            input_value += prevout.value.unwrap_or(0);
+        }
+    }
+
+    let mut claimer = ClaimerInfo {
+        address,
+        address_type,
+        input_value,
+        address_age: None,
+        block_height: None,
+    };
+
+    // A pending claim is checked without the rules that need the block it confirms in
+    if pending {
+        return Ok(claimer);
+    }
+
+    let block_height = match get_tx_block_info(txid).await {
+        Some((block_height, _)) => block_height,
+        None => return Err("Claim transaction is unconfirmed".to_string()),
+    };
+
+    claimer.block_height = Some(block_height);
+    if let Some(min_address_age) = claim_rules.min_address_age {
+        let seen_by = block_height.saturating_sub(min_address_age);
+        let first_seen =
+            match get_address_first_seen_height(&claimer.address, block_height, seen_by).await {
+                Ok(first_seen) => first_seen.unwrap_or(block_height),
+                Err(err) => return Err(err),
+            };
+
+        claimer.address_age = Some(block_height - first_seen);
+    }
+
+    return Ok(claimer);
+}
+
+pub fn claim_failure_reason(err: &str, default_reason: &str) -> String {
+    if err.starts_with("claim_rule_") {
+        return err.to_string();
+    }
+
+    return default_reason.to_string();
+}
+
+pub fn handle_mint_authority(authority: &str) -> Result<(String, u64), String> {
+    let fields: Vec<&str> = authority.split(";").collect();
+    if fields.len() != 2 {
+        return Err("Invalid mint authority".to_string());
+    }
+
+    let cap = match fields[1].parse::<u64>() {
+        Ok(cap) => cap,
+        Err(_) => return Err("Invalid mint authority cap".to_string()),
+    };
+
+    return Ok((fields[0].to_string(), cap));
+}
+
+pub fn handle_mint_supply_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, String, String, u64), String> {
+    let words: Vec<&str> = payload.split("MINT_SUPPLY").collect();
+    if words.len() < 2 {
+        return Err("Invalid mint supply payload".to_string());
+    }
+
+    let fields: Vec<String> = words[1]
+        .split(",")
+        .map(|field| replace_payload_special_characters(&field.to_string()))
+        .collect();
+    if fields.len() != 4 {
+        return Err("Invalid mint supply payload".to_string());
+    }
+
+    let authority_utxo = fields[0].clone();
+    let new_authority_utxo = fields[1].replace("TXID", txid);
+    let receiver_utxo = fields[2].replace("TXID", txid);
+    let amount = match fields[3].parse::<u64>() {
+        Ok(amount) => amount,
+        Err(_) => return Err("Invalid mint supply amount".to_string()),
+    };
+
+    // Both new outputs have to be created by the mint transaction and can't share an output
+    let prefix = format!("{}:", txid);
+    if !new_authority_utxo.starts_with(&prefix)
+        || !receiver_utxo.starts_with(&prefix)
+        || new_authority_utxo == receiver_utxo
+    {
+        return Err("Invalid mint supply outputs".to_string());
+    }
+
+    return Ok((authority_utxo, new_authority_utxo, receiver_utxo, amount));
+}
+
+pub fn handle_renounce_mint_payload(payload: &str) -> Result<String, String> {
+    let words: Vec<&str> = payload.split("RENOUNCE_MINT").collect();
+    if words.len() < 2 {
+        return Err("Invalid renounce mint payload".to_string());
+    }
+
+    let authority_utxo = replace_payload_special_characters(&words[1].to_string());
+    if authority_utxo.is_empty() {
+        return Err("Invalid renounce mint payload".to_string());
+    }
+
+    return Ok(authority_utxo);
+}
+
+pub fn handle_freeze_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, String, Vec<String>), String> {
+    let words: Vec<&str> = payload.split("FREEZE").collect();
+    if words.len() < 2 {
+        return Err("Invalid freeze payload".to_string());
+    }
+
+    let fields: Vec<String> = words[1]
+        .split(",")
+        .map(|field| replace_payload_special_characters(&field.to_string()))
+        .collect();
+    if fields.len() != 3 {
+        return Err("Invalid freeze payload".to_string());
+    }
+
+    let authority_utxo = fields[0].clone();
+    let new_authority_utxo = fields[1].replace("TXID", txid);
+    if !new_authority_utxo.starts_with(&format!("{}:", txid)) {
+        return Err("Invalid freeze outputs".to_string());
+    }
+
+    let utxos: Vec<String> = fields[2]
+        .split(";")
+        .filter(|utxo| !utxo.is_empty())
+        .map(|utxo| utxo.to_string())
+        .collect();
+    if utxos.is_empty() {
+        return Err("Invalid freeze payload".to_string());
+    }
+
+    return Ok((authority_utxo, new_authority_utxo, utxos));
+}
+
+// Free text metadata fields are hex encoded UTF-8 so they cannot contain payload separators
+fn decode_metadata_field(value: &str) -> Result<Option<String>, String> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    let bytes = match decode(value) {
+        Ok(bytes) => bytes,
+        Err(_) => return Err("Metadata fields must be hex encoded".to_string()),
+    };
+
+    match String::from_utf8(bytes) {
+        Ok(text) => return Ok(Some(text)),
+        Err(_) => return Err("Metadata fields must be UTF-8".to_string()),
+    }
+}
+
+pub fn handle_metadata_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, String, ContractMetadata), String> {
+    let words: Vec<&str> = payload.splitn(2, ":METADATA[").collect();
+    if words.len() < 2 {
+        return Err("Invalid metadata payload".to_string());
+    }
+
+    let fields: Vec<String> = words[1]
+        .trim_end_matches("}")
+        .trim_end_matches("]")
+        .split(",")
+        .map(|field| field.trim().to_string())
+        .collect();
+    if fields.len() < 2 || fields.len() > 7 {
+        return Err("Invalid metadata payload".to_string());
+    }
+
+    let authority_utxo = fields[0].replace(" ", "");
+    let new_authority_utxo = fields[1].replace(" ", "").replace("TXID", txid);
+    let mut text_fields: Vec<Option<String>> = Vec::new();
+    for index in 2..6 {
+        match decode_metadata_field(fields.get(index).map_or("", |field| field.as_str())) {
+            Ok(value) => text_fields.push(value),
+            Err(err) => return Err(err),
+        };
+    }
+
+    // Social links are key=hex_link pairs split by semicolons
+    let mut socials: HashMap<String, String> = HashMap::new();
+    if let Some(socials_str) = fields.get(6).filter(|socials_str| !socials_str.is_empty()) {
+        for social in socials_str.split(";") {
+            let pair: Vec<&str> = social.splitn(2, "=").collect();
+            let key = pair[0].trim();
+            if pair.len() < 2
+                || key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err("Invalid metadata social link".to_string());
+            }
+
+            let link = match decode_metadata_field(pair[1].trim()) {
+                Ok(Some(link)) => link,
+                Ok(None) => return Err("Invalid metadata social link".to_string()),
+                Err(err) => return Err(err),
+            };
+
+            socials.insert(key.to_string(), link);
+        }
+    }
+
+    let metadata = ContractMetadata {
+        name: text_fields[0].clone(),
+        description: text_fields[1].clone(),
+        icon: text_fields[2].clone(),
+        website: text_fields[3].clone(),
+        socials,
+    };
+
+    return Ok((authority_utxo, new_authority_utxo, metadata));
+}
+
+pub fn handle_mint_payload(
+    payload: &str,
+    txid: &str,
+) -> Result<(String, String, u64, u64), String> {
+    let mut mint_strings: Vec<String> = Vec::new();
+    let mut mint_values: Vec<u64> = Vec::new();
+    let re = match Regex::new(r"\[([^,]+),([^,]+),([^,]+),([^]]+)]") {
+        Ok(re) => re,
+        Err(_) => return Err("Not mint valid payload".to_string()),
+    };
+
+    if let Some(captures) = re.captures(payload) {
+        let ticker = match captures.get(1) {
+            Some(ticker) => ticker.as_str(),
+            None => return Err("Not mint valid payload".to_string()),
+        };
+
+        let max_supply_str = match captures.get(2) {
+            Some(max_supply_str) => max_supply_str.as_str(),
+            None => return Err("Not mint valid payload".to_string()),
+        };
+
+        let decimals_str = match captures.get(3) {
+            Some(decimals_str) => decimals_str.as_str(),
+            None => return Err("Not mint valid payload".to_string()),
+        };
+
+        let txid_n = match captures.get(4) {
+            Some(txid_n) => txid_n.as_str(),
+            None => return Err("Not mint valid payload".to_string()),
+        };
+
+        let temp: Vec<_> = txid_n.split(":").collect();
+        let mut t_n = txid_n.to_string();
+        if temp.len() == 2 {
+            let index = temp[1];
+            t_n = format!("{}:{}", &txid, index);
+        }
+
+        // Parse strings to numeric types
+        let max_supply = match max_supply_str.parse() {
+            Ok(max_supply) => max_supply,
+            Err(_) => return Err("Not mint valid payload".to_string()),
+        };
+
+        let decimals = match decimals_str.parse() {
+            Ok(decimals) => decimals,
+            Err(_) => return Err("Not mint valid payload".to_string()),
+        };
+
+        mint_strings.push(ticker.to_string());
+        mint_strings.push(t_n.to_string());
+        mint_values.push(max_supply);
+        mint_values.push(decimals);
+        return Ok((ticker.to_string(), t_n, max_supply, decimals));
+    }
+    return Err("Not mint valid payload".to_string());
+}
+
+pub fn handle_mint_rtm_payload(
+    payload: &str,
+    txid: &str,
+) -> Result<(String, u64, HashMap<String, u64>), String> {
+    let words: Vec<&str> = payload.split("SCL03:").collect();
+    if words.len() < 2 {
+        return Err("Invalid transfer payload".to_string());
+    }
+
+    let mint_split: Vec<&str> = words[1].split(",").collect();
+    if mint_split.len() < 2 {
+        return Err("Invalid mint rtm payload".to_string());
+    }
+
+    let ticker = replace_payload_special_characters(&mint_split[0].to_string());
+    let decimal_split = replace_payload_special_characters(&mint_split[1].to_string());
+
+    // Parse strings to numeric types
+    let decimals = match decimal_split.parse() {
+        Ok(decimals) => decimals,
+        Err(_) => return Err("Not mint valid payload".to_string()),
+    };
+
+    let rights_split: Vec<&str> = words[1].split(",[").collect();
+    if rights_split.len() < 2 {
+        return Err("Invalid mint rtm payload".to_string());
+    }
+
+    let rights: Vec<&str> = rights_split[1].split(",").collect();
+    let mut rights_recievers: HashMap<String, u64> = HashMap::new();
+    if rights.is_empty() {
+        return Err("Invalid mint rtm payload".to_string());
+    }
+    for reci in rights {
+        let rec_str = reci.replace("TXID", txid);
+        let recievers = replace_payload_special_characters(&rec_str.to_string());
+        let data: Vec<&str> = recievers.split("(").collect();
+        if data.len() < 2 {
+            return Err("Invalid Mint payload".to_string());
+        }
+
+        let rec_utxo = data[0];
+        let rec_amt_str = data[1].replace(")", "");
+        let rec_amt = match rec_amt_str.parse::<u64>() {
+            Ok(rec_amt) => rec_amt,
+            Err(_) => return Err("Failed to parse recieved amount".to_string()),
+        };
+
+        rights_recievers.insert(rec_utxo.to_string(), rec_amt);
+    }
+
+    return Ok((ticker.to_string(), decimals, rights_recievers));
+}
+
+pub fn handle_rtm_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, String, String, u64), String> {
+    let words: Vec<&str> = payload.split("RIGHTTOMINT").collect();
+    if words.len() < 2 {
+        return Err("Invalid rtm payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split(",").collect();
+    if sendsplit.len() < 4 {
+        return Err("Invalid rtm payload".to_string());
+    }
+
+    let rights_utxo = replace_payload_special_characters(&sendsplit[0].to_string());
+    let reciever_split = replace_payload_special_characters(&sendsplit[1].to_string());
+    let reciever = reciever_split.replace("TXID", txid);
+    let change_split = replace_payload_special_characters(&sendsplit[2].to_string());
+    let change = change_split.replace("TXID", txid);
+    let mint_amt_split = replace_payload_special_characters(&sendsplit[3].to_string());
+    let mint_amt = match mint_amt_split.parse::<u64>() {
+        Ok(mint_amt_split) => mint_amt_split,
+        Err(_) => return Err("Failed to parse mint amount".to_string()),
+    };
+
+    return Ok((rights_utxo, reciever, change, mint_amt));
+}
+
+pub fn handle_transfer_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(Vec<String>, Vec<(String, u64)>, String), String> {
+    let words: Vec<&str> = payload.split("TRANSFER").collect();
+    if words.len() < 2 {
+        return Err("Invalid transfer payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split("],[").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid transfer payload".to_string());
+    }
+
+    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
+    let rec_split: Vec<&str> = sendsplit[1].split(",").collect();
+    let mut senders: Vec<String> = Vec::new();
+    for sendi in sender_strs {
+        let sender = replace_payload_special_characters(&sendi.to_string());
+        senders.push(sender);
+    }
+    let mut last_output = String::new();
+    let mut rec_dict: Vec<(String, u64)> = Vec::new();
+    for reci in &rec_split {
+        let rec_str = reci.replace("TXID", txid);
+        let recievers = replace_payload_special_characters(&rec_str.to_string());
+        let data: Vec<&str> = recievers.split("(").collect();
+        if data.len() < 2 {
+            return Err("Invalid transfer payload".to_string());
+        }
+        let rec_utxo = data[0];
+        let rec_amt_str = data[1].replace(")", "");
+        let rec_amt = match rec_amt_str.parse::<u64>() {
+            Ok(rec_amt) => rec_amt,
+            Err(_) => {
+                return Err("Failed to parse recieved amount".to_string());
+            }
+        };
+        if rec_split.last().unwrap() == reci {
+            last_output = rec_utxo.to_string();
+        }
+        rec_dict.push((rec_utxo.to_string(), rec_amt));
+    }
+
+    return Ok((senders, rec_dict, last_output));
+}
+
+#[allow(clippy::type_complexity)]
+pub fn handle_transfer_tokens_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(Vec<String>, Vec<(String, Vec<String>)>), String> {
+    let words: Vec<&str> = payload.split("TRANSFER_TOKENS").collect();
+    if words.len() < 2 {
+        return Err("Invalid token transfer payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split("],[").collect();
+    if sendsplit.len() < 2 {
+        return Err("Invalid token transfer payload".to_string());
+    }
+
+    let mut senders: Vec<String> = Vec::new();
+    for sendi in sendsplit[0].split(",") {
+        senders.push(replace_payload_special_characters(&sendi.to_string()));
+    }
+
+    let mut receivers: Vec<(String, Vec<String>)> = Vec::new();
+    for reci in sendsplit[1].split(",") {
+        let rec_str = reci.replace("TXID", txid);
+        let reciever = replace_payload_special_characters(&rec_str);
+        let data: Vec<&str> = reciever.split("(").collect();
+        if data.len() < 2 {
+            return Err("Invalid token transfer payload".to_string());
+        }
+
+        let token_ids: Vec<String> = data[1]
+            .replace(")", "")
+            .split(";")
+            .filter(|token_id| !token_id.is_empty())
+            .map(|token_id| token_id.to_string())
+            .collect();
+        if token_ids.is_empty() {
+            return Err("Invalid token transfer payload".to_string());
+        }
+
+        receivers.push((data[0].to_string(), token_ids));
+    }
+
+    return Ok((senders, receivers));
+}
+
+pub fn handle_drip_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<
+    (
+        Vec<String>,
+        HashMap<String, (u64, u64, VestingSchedule)>,
+        String,
+    ),
+    String,
+> {
+    let words: Vec<&str> = payload.split("DRIP").collect();
+    if words.len() < 2 {
+        return Err("Invalid drip payload".to_string());
+    }
+
+    let sendsplit: Vec<&str> = words[1].split("],").collect();
+    if sendsplit.len() < 3 {
+        return Err("Invalid drip payload".to_string());
+    }
+
+    let sender_strs: Vec<&str> = sendsplit[0].split(",").collect();
+    let rec_split: Vec<&str> = sendsplit[1].split("),").collect();
+    let change_split = replace_payload_special_characters(&sendsplit[2].to_string());
+    let change = change_split.replace("TXID", txid);
+    let mut senders: Vec<String> = Vec::new();
+    for sendi in sender_strs {
+        let sender = replace_payload_special_characters(&sendi.to_string());
+        senders.push(sender);
+    }
+
+    let mut rec_dict: HashMap<String, (u64, u64, VestingSchedule)> = HashMap::new();
+    for reci in rec_split {
+        let rec_str = reci.replace("TXID", txid);
+        let recievers = replace_payload_special_characters(&rec_str.to_string());
+        let data: Vec<&str> = recievers.split("(").collect();
+        if data.len() < 2 {
+            return Err("Invalid Drip payload".to_string());
+        }
+
+        let rec_utxo = data[0];
+        let rec_amt_str = data[1].replace(")", "");
+        let tuple_str: Vec<_> = rec_amt_str.split(",").collect();
+        if tuple_str.len() < 2 {
+            continue;
+        }
+        let rec_amt = match tuple_str[0].parse::<u64>() {
+            Ok(rec_amt) => rec_amt,
+            Err(_) => return Err("Failed to parse recieved amount".to_string()),
+        };
+        let dur_amt = match tuple_str[1].parse::<u64>() {
+            Ok(dur_amt) => dur_amt,
+            Err(_) => return Err("Failed to parse duration amount".to_string()),
+        };
+
+        // Receivers without a schedule keep the original linear drip
+        let schedule = match tuple_str.get(2) {
+            Some(schedule_str) => VestingSchedule::from_payload(schedule_str, dur_amt)?,
+            None => VestingSchedule::Linear,
+        };
+
+        rec_dict.insert(rec_utxo.to_string(), (rec_amt, dur_amt, schedule));
+    }
+    return Ok((senders, rec_dict, change));
+}
+
+pub fn handle_revoke_drip_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(String, String, String, String), String> {
+    let words: Vec<&str> = payload.split("REVOKE_DRIP").collect();
+    if words.len() < 2 {
+        return Err("Invalid revoke drip payload".to_string());
+    }
+
+    let fields: Vec<String> = words[1]
+        .split(",")
+        .map(|field| replace_payload_special_characters(&field.to_string()))
+        .collect();
+    if fields.len() != 4 {
+        return Err("Invalid revoke drip payload".to_string());
+    }
+
+    let new_authority_utxo = fields[1].replace("TXID", txid);
+    let receiver_utxo = fields[3].replace("TXID", txid);
+    let prefix = format!("{}:", txid);
+    if !new_authority_utxo.starts_with(&prefix)
+        || !receiver_utxo.starts_with(&prefix)
+        || new_authority_utxo == receiver_utxo
+    {
+        return Err("Invalid revoke drip outputs".to_string());
+    }
+
+    return Ok((
+        fields[0].clone(),
+        new_authority_utxo,
+        fields[2].clone(),
+        receiver_utxo,
+    ));
+}
+
+pub fn handle_burn_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<(Vec<String>, u64, String), String> {
+    let words: Vec<&str> = payload.split("BURN").collect();
+    if words.len() < 2 {
+        return Err("Invalid burn payload".to_string());
+    }
+
+    let burn_split: Vec<&str> = words[1].split("],").collect();
+    if burn_split.len() < 2 {
+        return Err("Invalid burn payload".to_string());
+    }
+    let burners_split: Vec<&str> = burn_split[0].split(",").collect();
+    let mut burners: Vec<String> = Vec::new();
+    for burner in burners_split.clone() {
+        let clean_burner = replace_payload_special_characters(&burner.to_string());
+        burners.push(clean_burner);
+    }
+
+    let burn_info: Vec<&str> = burn_split[1].split(",").collect();
+    if burn_info.len() < 2 {
+        return Err("Invalid burn payload".to_string());
+    }
+
+    let burn_amount_str = replace_payload_special_characters(&burn_info[0].to_string());
+
+    let amt = match burn_amount_str.parse::<u64>() {
+        Ok(amt) => amt,
+        Err(_) => return Err("Invalid burn payload".to_string()),
+    };
+
+    let mut change_str = replace_payload_special_characters(&burn_info[1].to_string());
+    change_str = change_str.replace("TXID", txid);
+    for s in &burners {
+        let file_path = format!("./Json/UTXOS/{}.txt", s);
+        // Attempt to remove the file
+        let _ = fs::remove_file(file_path);
+    }
+    return Ok((burners, amt, change_str));
+}
+
+// Collection burns name the burned token ids as id;id after the change utxo
+pub fn handle_burn_token_ids_payload(payload: &str) -> Option<Vec<String>> {
+    let words: Vec<&str> = payload.split("BURN").collect();
+    if words.len() < 2 {
+        return None;
+    }
+
+    let burn_split: Vec<&str> = words[1].split("],").collect();
+    if burn_split.len() < 2 {
+        return None;
+    }
+
+    let burn_info: Vec<&str> = burn_split[1].split(",").collect();
+    if burn_info.len() < 3 {
+        return None;
+    }
+
+    let token_ids: Vec<String> = replace_payload_special_characters(&burn_info[2].to_string())
+        .split(";")
+        .filter(|token_id| !token_id.is_empty())
+        .map(|token_id| token_id.to_string())
+        .collect();
+    if token_ids.is_empty() {
+        return None;
+    }
+
+    return Some(token_ids);
+}
+
+pub fn handle_list_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<
+    (
+        Vec<String>,
+        String,
+        String,
+        String,
+        u64,
+        u64,
+        Option<u64>,
+        bool,
+    ),
+    String,
+> {
+    let words: Vec<&str> = payload.split("LIST").collect();
+    if words.len() < 2 {
+        return Err("Invalid List payload".to_string());
+    }
+
+    let list_split: Vec<&str> = words[1].split("],").collect();
+    if list_split.len() < 2 {
+        return Err("Invalid List payload".to_string());
+    }
+
+    let lists_split: Vec<&str> = list_split[0].split(",").collect();
+    let listing_info: Vec<&str> = list_split[1].split(",").collect();
+    let mut listings_senders: Vec<String> = Vec::new();
+    for lising in lists_split.clone() {
+        let temp1 = lising.to_string().replace("[", "");
+        let temp2 = temp1.replace("]", "");
+        listings_senders.push(temp2);
+    }
+
+    if listing_info.len() < 5 {
+        return Err("Invalid List payload".to_string());
+    }
+
+    let mut change_str = replace_payload_special_characters(&listing_info[0].to_string());
+    change_str = change_str.replace("TXID", txid);
+    let mut listing_utxo_str = replace_payload_special_characters(&listing_info[1].to_string());
+    listing_utxo_str = listing_utxo_str.replace("TXID", txid);
+    let list_amount_str = replace_payload_special_characters(&listing_info[2].to_string());
+    let sell_price_str = replace_payload_special_characters(&listing_info[3].to_string());
+    let pay_address_str = replace_payload_special_characters(&listing_info[4].to_string());
+    let listing_amt = match list_amount_str.parse::<u64>() {
+        Ok(amt) => amt,
+        Err(_) => return Err("Invalid List payload. Listing amount invalid".to_string()),
+    };
+
+    let sell_price = match sell_price_str.parse::<u64>() {
+        Ok(amt) => amt,
+        Err(_) => return Err("Invalid List payload. Sell price invalid".to_string()),
+    };
+
+    // An expiry of 0 leaves the listing open so partial fills can be enabled on their own
+    let mut expiry_block: Option<u64> = None;
+    if listing_info.len() > 5 {
+        let expiry_str = replace_payload_special_characters(&listing_info[5].to_string());
+        expiry_block = match expiry_str.parse::<u64>() {
+            Ok(0) => None,
+            Ok(expiry) => Some(expiry),
+            Err(_) => return Err("Invalid List payload. Expiry block invalid".to_string()),
+        };
+    }
+
+    let mut allow_partial = false;
+    if listing_info.len() > 6 {
+        let partial_str = replace_payload_special_characters(&listing_info[6].to_string());
+        allow_partial = match partial_str.as_str() {
+            "1" | "true" | "PARTIAL" => true,
+            "0" | "false" => false,
+            _ => return Err("Invalid List payload. Partial fill flag invalid".to_string()),
+        };
+    }
+
+    return Ok((
+        listings_senders,
+        change_str,
+        listing_utxo_str,
+        pay_address_str,
+        listing_amt,
+        sell_price,
+        expiry_block,
+        allow_partial,
+    ));
+}
+
+// Collection listings can name their token ids after the partial fill flag, as id;id
+pub fn handle_list_token_ids_payload(payload: &str) -> Option<Vec<String>> {
+    let words: Vec<&str> = payload.split("LIST").collect();
+    if words.len() < 2 {
+        return None;
+    }
+
+    let list_split: Vec<&str> = words[1].split("],").collect();
+    if list_split.len() < 2 {
+        return None;
+    }
+
+    let listing_info: Vec<&str> = list_split[1].split(",").collect();
+    if listing_info.len() < 8 {
+        return None;
+    }
+
+    let token_ids: Vec<String> = replace_payload_special_characters(&listing_info[7].to_string())
+        .split(";")
+        .filter(|token_id| !token_id.is_empty())
+        .map(|token_id| token_id.to_string())
+        .collect();
+    if token_ids.is_empty() {
+        return None;
+    }
+
+    return Some(token_ids);
+}
+
+pub fn handle_bid_payload(
+    txid: &str,
+    payload: &str,
+) -> Result<Vec<(String, u64, u64, String)>, String> {
+    let words: Vec<&str> = payload.split("BID").collect();
+    let bid_split: Vec<&str> = words[1].split("],").collect();
+    if bid_split.is_empty() {
+        return Err("Invalid Bid payload. Sell price invalid".to_string());
+    }
+
+    let mut bid_results: Vec<(String, u64, u64, String)> = Vec::new();
+    for split in bid_split {
+        let bid_info: Vec<&str> = split.split(",").collect();
+        if bid_info.len() < 4 {
+            continue;
+        }
+
+        let order_id_split = replace_payload_special_characters(&bid_info[0].to_string());
+        let amount_split = replace_payload_special_characters(&bid_info[1].to_string());
+        let amt = match amount_split.parse::<u64>() {
+            Ok(amt) => amt,
+            Err(_) => continue,
+        };
+
+        let price_split = replace_payload_special_characters(&bid_info[2].to_string());
+        let price = match price_split.parse::<u64>() {
+            Ok(amt) => amt,
+            Err(_) => continue,
+        };
+
+        let mut res_utxo_str = replace_payload_special_characters(&bid_info[3].to_string());
+        res_utxo_str = res_utxo_str.replace("TXID", txid);
+        bid_results.push((order_id_split, amt, price, res_utxo_str));
+    }
+
+    return Ok(bid_results);
+}
+
+// Trade payloads carry "-ExtraInfo-{bid_id},{amount},{price}" and the royalty owed when the
+// contract has one
+pub fn handle_payload_extra_trade_info(
+    payload: &str,
+) -> Result<(String, u64, u64, Option<u64>), String> {
+    let words: Vec<&str> = payload.split("-ExtraInfo-").collect();
+    if words.len() < 2 {
+        return Err("Invalid payload".to_string());
+    }
+    let bid_split: Vec<&str> = words[1].split(",").collect();
+    if bid_split.len() < 3 {
+        return Err("Invalid payload".to_string());
+    }
+
+    let bid_id_split = replace_payload_special_characters(&bid_split[0].to_string());
+    let amount_split = replace_payload_special_characters(&bid_split[1].to_string());
+    let amount = match amount_split.parse::<u64>() {
+        Ok(amount) => amount,
+        Err(_) => return Err("Invalid payload".to_string()),
+    };
+
+    let price_split = replace_payload_special_characters(&bid_split[2].to_string());
+    let price = match price_split.parse::<u64>() {
+        Ok(amt) => amt,
+        Err(_) => return Err("Invalid payload".to_string()),
+    };
+
+    let mut royalty_amount: Option<u64> = None;
+    if bid_split.len() > 3 {
+        let royalty_split = replace_payload_special_characters(&bid_split[3].to_string());
+        royalty_amount = match royalty_split.parse::<u64>() {
+            Ok(royalty_amount) => Some(royalty_amount),
+            Err(_) => return Err("Invalid payload".to_string()),
+        };
+    }
+
+    return Ok((bid_id_split, amount, price, royalty_amount));
+}
+
+pub fn convert_old_contracts() {
+    let directory_path = "./Json/Contracts/"; // Your directory path here
+    if let Ok(entries) = fs::read_dir(directory_path) {
+        for entry in entries.flatten() {
+            if let Ok(metadata) = entry.metadata() {
+                if metadata.is_dir() {
+                    if let Ok(folder_name) = entry.file_name().into_string() {
+                        let file_path = format!("./Json/Contracts/{}/state.txt", &folder_name);
+                        let user_data_str = match fs::read_to_string(&file_path) {
+                            Ok(user_data_str) => user_data_str,
+                            Err(_) => continue,
+                        };
+
+                        // Deserialize user data from JSON
+                        let mut user_data: SCL01Contract =
+                            match serde_json::from_str(&user_data_str) {
+                                Ok(user_data) => user_data,
+                                Err(_) => continue,
+                            };
+
+                        if let Some(mut liquidity_pool) = user_data.liquidity_pool.clone() {
+                            liquidity_pool.migrate_fixed_point();
+                            user_data.liquidity_pool = Some(liquidity_pool);
+                        } else {
+                            let airdrop_amount = match user_data.airdrop_amount {
+                                Some(airdrop_amount) => airdrop_amount,
+                                None => continue,
+                            };
+
+                            let current_airdrops = match user_data.current_airdrops {
+                                Some(current_airdrops) => current_airdrops,
+                                None => continue,
+                            };
+
+                            let total_airdrops = match user_data.total_airdrops {
+                                Some(total_airdrops) => total_airdrops,
+                                None => continue,
+                            };
+
+                            user_data.max_supply = Some(total_airdrops * airdrop_amount);
+                            user_data.supply = current_airdrops * airdrop_amount;
+                        }
+
+                        let serialised_user_data = match serde_json::to_string(&user_data) {
+                            Ok(serialised_user_data) => serialised_user_data,
+                            Err(_) => continue,
+                        };
+
+                        write_to_file(
+                            format!("./Json/Contracts/{}/state.txt", &folder_name),
+                            serialised_user_data.clone(),
+                        );
+                        write_to_file(
+                            format!("./Json/Contracts/{}/pending.txt", &folder_name),
+                            serialised_user_data,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Liquidity Pools
+pub fn perform_minting_scl04(txid: &str, payload: &str) {
+    if read_contract(txid, false).is_ok() {
+        return;
+    };
+
+    let (mint_payload, metadata_authority) = split_authority(txid, payload, "AUTHORITY");
+    let (mint_payload, freeze_authority) = split_authority(txid, &mint_payload, "FREEZE_AUTHORITY");
+
+    let words: Vec<&str> = mint_payload.split("SCL04:").collect();
+    if words.len() < 2 {
+        println!("Invalid liquidity pool payload");
+        return;
+    }
+
+    let mint_split: Vec<&str> = words[1].split(",").collect();
+    if mint_split.len() < 5 {
+        println!("Invalid liquidity pool payload");
+        return;
+    }
+
+    let ticker = replace_payload_special_characters(&mint_split[0].to_string());
+    let contract_id_1 = replace_payload_special_characters(&mint_split[1].to_string());
+    let contract_id_2 = replace_payload_special_characters(&mint_split[2].to_string());
+    let ratio_split = replace_payload_special_characters(&mint_split[3].to_string());
+    let fee_split = replace_payload_special_characters(&mint_split[4].to_string());
+
+    // Parse strings to numeric types
+    let ratio = match ratio_split.parse::<f64>() {
+        Ok(ratio) => ratio,
+        Err(_) => {
+            println!("Not mint valid payload");
+            return;
+        }
+    };
+
+    let fee = match fee_split.parse::<f32>() {
+        Ok(fee) => fee,
+        Err(_) => {
+            println!("Not mint valid payload");
+            return;
+        }
+    };
+
+    let ratio_fixed = match parse_fixed_point(&ratio_split, 9) {
+        Ok(ratio_fixed) => ratio_fixed,
+        Err(_) => {
+            println!("Not mint valid payload");
+            return;
+        }
+    };
+
+    let fee_bps = match parse_fixed_point(&fee_split, 4) {
+        Ok(fee_bps) if SCL04_FEE_TIERS_BPS.contains(&(fee_bps as u64)) => fee_bps as u64,
+        _ => {
+            println!("Fee is not one of the SCL04 fee tiers");
+            return;
+        }
+    };
+
+    // Optional protocol fee share, as a fraction of the swap fee, and its recipient
+    let mut protocol_fee: Option<ProtocolFee> = None;
+    if mint_split.len() >= 7 {
+        let share_split = replace_payload_special_characters(&mint_split[5].to_string());
+        let recipient = replace_payload_special_characters(&mint_split[6].to_string());
+        let share_bps = match parse_fixed_point(&share_split, 4) {
+            Ok(share_bps) if share_bps <= MAX_PROTOCOL_FEE_SHARE_BPS as u128 => share_bps as u64,
+            _ => {
+                println!("Not mint valid payload");
+                return;
+            }
+        };
+
+        if share_bps > 0 {
+            if recipient.is_empty() {
+                println!("Not mint valid payload");
+                return;
+            }
+
+            protocol_fee = Some(ProtocolFee {
+                recipient,
+                share_bps,
+                ..Default::default()
+            });
+        }
+    }
+
+    // Optional curve, constant product unless the payload selects a weighted or stable pool
+    let mut curve = PoolCurve::ConstantProduct;
+    if mint_split.len() >= 8 {
+        let curve_split = replace_payload_special_characters(&mint_split[7].to_string());
+        curve = match PoolCurve::from_payload(&curve_split) {
+            Ok(curve) => curve,
+            Err(err) => {
+                println!("Not mint valid payload: {}", err);
+                return;
+            }
+        };
+    }
+
+    if contract_id_1 == contract_id_2 {
+        return;
+    }
+
+    let contract_1 = match read_contract(&contract_id_1, false) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    let contract_2 = match read_contract(&contract_id_2, false) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    if contract_1.decimals != contract_2.decimals {
+        return;
+    }
+
+    let mut payloads: HashMap<String, String> = HashMap::new();
+    payloads.insert(txid.to_string(), payload.to_string());
+    let pools: LiquidityPool = LiquidityPool {
+        contract_id_1,
+        contract_id_2,
+        pool_1: 0,
+        pool_2: 0,
+        fee,
+        k: 0,
+        liquidity_ratio: ratio,
+        swaps: HashMap::new(),
+        liquidations: HashMap::new(),
+        swap_records: None,
+        liquidation_records: None,
+        fee_bps: Some(fee_bps),
+        ratio_fixed: Some(ratio_fixed),
+        providers: Some(HashMap::new()),
+        protocol_fee,
+        curve: Some(curve),
+    };
+
+    let new_contract = SCL01Contract {
+        ticker,
+        contractid: txid.to_string(),
+        supply: 0,
+        decimals: contract_1.decimals,
+        owners: HashMap::new(),
+        payloads,
+        listings: None,
+        bids: None,
+        fulfillments: None,
+        drips: None,
+        diminishing_airdrops: None,
+        dges: None,
+        airdrop_amount: None,
+        current_airdrops: None,
+        total_airdrops: None,
+        pending_claims: None,
+        last_airdrop_split: None,
+        right_to_mint: None,
+        max_supply: None,
+        liquidated_tokens: None,
+        liquidity_pool: Some(pools),
+        token_data: None,
+        nft_tokens: None,
+        royalty: None,
+        metadata_authority,
+        mint_authority: None,
+        minted: None,
+        freeze_authority,
+        frozen_utxos: None,
+        merkle_airdrops: None,
+    };
+
+    if let Ok(s) = serde_json::to_string(&new_contract) {
+        write_contract_directory(
+            format!("./Json/Contracts/{}/state.txt", &new_contract.contractid),
+            s.clone(),
+            new_contract.contractid.as_str(),
+        );
+        write_contract_directory(
+            format!("./Json/Contracts/{}/pending.txt", &new_contract.contractid),
+            s.clone(),
+            new_contract.contractid.as_str(),
+        );
+        let path = "./Json/Contracts/".to_string() + "/" + &new_contract.contractid + "/header.txt";
+        let config = read_server_config().unwrap_or_default();
+
+        let url = match config.url {
+            Some(url) => url,
+            None => "https://scl.darkfusion.tech/".to_owned(),
+        };
+
+        let import = ContractImport {
+            contract_id: new_contract.contractid.clone(),
+            ticker: new_contract.ticker,
+            rest_url: url.to_string(),
+            contract_type: "SCL04".to_string(),
+            decimals: new_contract.decimals,
+            metadata: None,
+        };
+
+        let result = match serde_json::to_string(&import) {
+            Ok(result) => result,
+            Err(_) => return,
+        };
+
+        write_to_file(path, result);
+
+        let mut lookup = read_server_lookup().unwrap_or_default();
+
+        lookup.lps.push(new_contract.contractid);
+        let _ = save_server_lookup(lookup);
+    };
+}
+
+pub fn perform_minting_scl05(txid: &str, payload: &str) {
+    if read_contract(txid, false).is_ok() {
+        return;
+    };
+
+    let (mint_payload, metadata_authority) = split_authority(txid, payload, "AUTHORITY");
+    let (mint_payload, freeze_authority) = split_authority(txid, &mint_payload, "FREEZE_AUTHORITY");
+
+    let words: Vec<&str> = mint_payload.split("SCL05:").collect();
+    if words.len() < 2 {
+        println!("Invalid Non-Fungible Token payload");
+        return;
+    }
+
+    let mint_split: Vec<&str> = words[1].split(",").collect();
+    if mint_split.len() < 3 {
+        println!("Invalid Non-Fungible Token payload");
+        return;
+    }
+
+    let ticker = replace_payload_special_characters(&mint_split[0].to_string());
+    let utxo_rec = replace_payload_special_characters(&mint_split[1].to_string());
+    let base_64 = replace_payload_special_characters(&mint_split[2].to_string());
+
+    // Any fields after the content are a royalty or the tokens of a collection
+    let mut royalty: Option<Royalty> = None;
+    let mut token_payloads: Vec<String> = Vec::new();
+    for field in &mint_split[3..] {
+        let field_str = field.replace("}", "");
+        if !field_str.trim().starts_with("ROYALTY(") {
+            token_payloads.push(field_str);
+            continue;
+        }
+
+        royalty = match Royalty::from_payload(&replace_payload_special_characters(&field_str)) {
+            Ok(royalty) => Some(royalty),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+    }
+
+    let mut nft_tokens: Option<HashMap<String, NftToken>> = None;
+    if !token_payloads.is_empty() {
+        let mut tokens: HashMap<String, NftToken> = HashMap::new();
+        for token_str in token_payloads {
+            let token = match NftToken::from_payload(&token_str, &utxo_rec) {
+                Ok(token) => token,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            };
+
+            if tokens.contains_key(&token.token_id) {
+                println!("Duplicate collection token id {}", token.token_id);
+                return;
+            }
+
+            tokens.insert(token.token_id.clone(), token);
+        }
+
+        nft_tokens = Some(tokens);
+    }
+
+    let supply = match &nft_tokens {
+        Some(tokens) => tokens.len() as u64,
+        None => 1,
+    };
+
+    let mut token_data = Some(base_64.clone());
+    if nft_tokens.is_some() && base_64.is_empty() {
+        token_data = None;
+    }
+
+    let mut payloads: HashMap<String, String> = HashMap::new();
+    payloads.insert(txid.to_string(), payload.to_string());
+    let mut owners: HashMap<String, u64> = HashMap::new();
+    owners.insert(utxo_rec, supply);
+    let new_contract = SCL01Contract {
+        ticker,
+        contractid: txid.to_string(),
+        supply,
+        decimals: 0,
+        owners,
+        payloads,
+        listings: None,
+        bids: None,
+        fulfillments: None,
+        drips: None,
+        diminishing_airdrops: None,
+        dges: None,
+        airdrop_amount: None,
+        current_airdrops: None,
+        total_airdrops: None,
+        pending_claims: None,
+        last_airdrop_split: None,
+        right_to_mint: None,
+        max_supply: None,
+        liquidated_tokens: None,
+        liquidity_pool: None,
+        token_data,
+        nft_tokens,
+        royalty,
+        metadata_authority,
+        mint_authority: None,
+        minted: None,
+        freeze_authority,
+        frozen_utxos: None,
+        merkle_airdrops: None,
+    };
+
+    if let Ok(s) = serde_json::to_string(&new_contract) {
+        write_contract_directory(
+            format!("./Json/Contracts/{}/state.txt", &new_contract.contractid),
+            s.clone(),
+            new_contract.contractid.as_str(),
+        );
+        write_contract_directory(
+            format!("./Json/Contracts/{}/pending.txt", &new_contract.contractid),
+            s.clone(),
+            new_contract.contractid.as_str(),
+        );
+        let config = read_server_config().unwrap_or_default();
+
+        let url = match config.url {
+            Some(url) => url,
+            None => "https://scl.darkfusion.tech/".to_owned(),
+        };
+
+        let path = "./Json/Contracts/".to_string() + "/" + &new_contract.contractid + "/header.txt";
+        let import = ContractImport {
+            contract_id: new_contract.contractid,
+            ticker: new_contract.ticker,
+            rest_url: url,
+            contract_type: "SCL05".to_string(),
+            decimals: new_contract.decimals,
+            metadata: None,
+        };
+        let result = match serde_json::to_string(&import) {
+            Ok(result) => result,
+            Err(_) => return,
+        };
+        write_to_file(path, result);
+    };
+}
+
+pub async fn perform_provide_liquidity(
+    txid: &str,
+    payload: &str,
+    pending: bool,
+    lp_contract_id: &str,
+    block_height: i32,
+) {
+    let captures = match handle_provide_liquidity_payload_lp(payload) {
+        Ok(captures) => captures,
+        Err(_) => {
+            println!("Failed to parse liquity provision payload");
+            return;
+        }
+    };
+
+    let lp_contract = match read_contract(lp_contract_id, pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    let lp_pool = match lp_contract.liquidity_pool {
+        Some(lp_pool) => lp_pool,
+        None => return,
+    };
+
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let mut contract_1 = match read_contract(lp_pool.contract_id_1.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    if contract_1.payloads.contains_key(txid) {
+        return;
+    }
+
+    let res_1 = match contract_1.provide_liquidity(
+        &txid.to_string(),
+        &payload.to_string(),
+        &input_utxos,
+        captures,
+        block_height as u64,
+        true,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let mut contract_2 = match read_contract(lp_pool.contract_id_2.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    if contract_2.payloads.contains_key(txid) {
+        return;
+    }
+
+    let res_2 = match contract_2.provide_liquidity(
+        &txid.to_string(),
+        &payload.to_string(),
+        &input_utxos,
+        captures,
+        block_height as u64,
+        false,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let _ = save_contract(&contract_1, payload, txid, pending);
+    let _ = save_contract(&contract_2, payload, txid, pending);
+    save_check_utxo_file(
+        &contract_2.contractid,
+        &res_2.0,
+        res_2.1,
+        res_2.2,
+        pending,
+        "O",
+    );
+    save_check_utxo_file(
+        &contract_1.contractid,
+        &res_1.0,
+        res_1.1,
+        res_1.2,
+        pending,
+        "O",
+    );
+    if !pending {
+        let _ = save_contract(&contract_1, payload, txid, true);
+        let _ = save_contract(&contract_2, payload, txid, true);
+        for utxo in input_utxos {
+            let file_path = format!("./Json/UTXOS/{}.txt", utxo);
+            // Attempt to remove the file
+            let _ = fs::remove_file(file_path);
+        }
+    }
+}
+
+pub async fn perform_provide_liquidity_lp(
+    txid: &str,
+    payload: &str,
+    pending: bool,
+    lp_contract_id: &str,
+    block_height: i32,
+) {
+    let mut lp_contract = match read_contract(lp_contract_id, pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    if lp_contract.payloads.contains_key(txid) {
+        return;
+    }
+
+    let lp = match lp_contract.liquidity_pool.clone() {
+        Some(lp) => lp,
+        None => return,
+    };
+
+    let captures = match handle_provide_liquidity_payload_lp(payload) {
+        Ok(captures) => captures,
+        Err(_) => {
+            println!("Failed to parse liquity provision payload");
+            return;
+        }
+    };
+
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let mut contract_1 = match read_contract(&lp.contract_id_1, pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    if !contract_1.payloads.contains_key(txid) {
+        match contract_1.provide_liquidity(
+            &txid.to_string(),
+            &payload.to_string(),
+            &input_utxos,
+            captures,
+            block_height as u64,
+            true,
+        ) {
+            Ok(res) => res,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+    }
+
+    let mut contract_2 = match read_contract(&lp.contract_id_2, pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
+
+    if !contract_2.payloads.contains_key(txid) {
+        let amount: u64 = match lp.paired_amount(captures) {
+            Ok(amount) => amount,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        match contract_2.provide_liquidity(
+            &txid.to_string(),
+            &payload.to_string(),
+            &input_utxos,
+            amount,
+            block_height as u64,
+            false,
+        ) {
+            Ok(res) => res,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+    }
+
+    let lp_res =
+        match lp_contract.provide_liquidity_lp(txid, payload, captures, block_height as u64) {
+            Ok(lp_res) => lp_res,
+            Err(err) => {
+                println!("Failed to execute liquity provision: {}", err);
+                return;
+            }
+        };
+
+    let _ = save_contract(&lp_contract, payload, txid, pending);
+    let mut balance_type = "O";
+    if pending {
+        balance_type = "U";
+    }
+
+    save_check_utxo_file(
+        &lp_contract.contractid,
+        &lp_res.0,
+        lp_res.1,
+        false,
+        pending,
+        balance_type,
+    );
+    if !pending {
+        let _ = save_contract(&lp_contract, payload, txid, true);
     }
+}
 
-    let mint_split: Vec<&str> = words[1].split(",").collect();
-    if mint_split.len() < 5 {
-        println!("Invalid liquidity pool payload");
-        return;
-    }
+pub async fn perform_swap(
+    txid: &str,
+    payload: &str,
+    pending: bool,
+    lp_contract_id: &str,
+    block_height: i32,
+) {
+    let mut lp_contract = match read_contract(lp_contract_id, pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
+    };
 
-    let ticker = replace_payload_special_characters(&mint_split[0].to_string());
-    let contract_id_1 = replace_payload_special_characters(&mint_split[1].to_string());
-    let contract_id_2 = replace_payload_special_characters(&mint_split[2].to_string());
-    let ratio_split = replace_payload_special_characters(&mint_split[3].to_string());
-    let fee_split = replace_payload_special_characters(&mint_split[4].to_string());
+    let liquidity_pool = match lp_contract.liquidity_pool.clone() {
+        Some(liquidity_pool) => liquidity_pool,
+        None => return,
+    };
 
-    // Parse strings to numeric types
-    let ratio = match ratio_split.parse::<f64>() {
-        Ok(ratio) => ratio,
-        Err(_) => {
-            println!("Not mint valid payload");
-            return;
-        }
+    let lp_captures = match handle_swap_payload_lp(payload) {
+        Ok(captures) => captures,
+        Err(_) => return,
     };
 
-    let fee = match fee_split.parse::<f32>() {
-        Ok(fee) => fee,
-        Err(_) => {
-            println!("Not mint valid payload");
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
             return;
         }
     };
 
-    if contract_id_1 == contract_id_2 {
-        return;
+    let reciever_contract_id;
+    let claimer_contract_id;
+    if lp_captures.0 {
+        reciever_contract_id = liquidity_pool.contract_id_2;
+        claimer_contract_id = liquidity_pool.contract_id_1;
+    } else {
+        reciever_contract_id = liquidity_pool.contract_id_1;
+        claimer_contract_id = liquidity_pool.contract_id_2;
     }
 
-    let contract_1 = match read_contract(&contract_id_1, false) {
+    let mut claimer_contract = match read_contract(claimer_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    let contract_2 = match read_contract(&contract_id_2, false) {
+    let mut reciever_contract = match read_contract(reciever_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    if contract_1.decimals != contract_2.decimals {
-        return;
+    let recieving_amount: u64;
+    if liquidity_pool.swaps.contains_key(txid) {
+        recieving_amount = liquidity_pool.swaps[txid].1;
+    } else {
+        let swap_block = get_tx_block_info(txid).await;
+        recieving_amount = match lp_contract.swap_lp(
+            txid,
+            payload,
+            claimer_contract_id,
+            lp_captures.1,
+            lp_captures.2,
+            lp_captures.3,
+            swap_block,
+        ) {
+            Ok(lp_res) => lp_res,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
     }
 
-    let mut payloads: HashMap<String, String> = HashMap::new();
-    payloads.insert(txid.to_string(), payload.to_string());
-    let pools: LiquidityPool = LiquidityPool {
-        contract_id_1: contract_id_1,
-        contract_id_2: contract_id_2,
-        pool_1: 0,
-        pool_2: 0,
-        fee: fee,
-        k: 0,
-        liquidity_ratio: ratio,
-        swaps: HashMap::new(),
-        liquidations: HashMap::new(),
-    };
-
-    let new_contract = SCL01Contract {
-        ticker: ticker,
-        contractid: txid.to_string(),
-        supply: 0,
-        decimals: contract_1.decimals,
-        owners: HashMap::new(),
-        payloads: payloads,
-        listings: None,
-        bids: None,
-        fulfillments: None,
-        drips: None,
-        diminishing_airdrops: None,
-        dges: None,
-        airdrop_amount: None,
-        current_airdrops: None,
-        total_airdrops: None,
-        pending_claims: None,
-        last_airdrop_split: None,
-        right_to_mint: None,
-        max_supply: None,
-        liquidated_tokens: None,
-        liquidity_pool: Some(pools),
-        token_data: None,
-    };
-
-    match serde_json::to_string(&new_contract) {
-        Ok(s) => {
-            write_contract_directory(
-                format!("./Json/Contracts/{}/state.txt", &new_contract.contractid),
-                s.clone(),
-                new_contract.contractid.as_str(),
-            );
-            write_contract_directory(
-                format!("./Json/Contracts/{}/pending.txt", &new_contract.contractid),
-                s.clone(),
-                new_contract.contractid.as_str(),
-            );
-            let path =
-                "./Json/Contracts/".to_string() + "/" + &new_contract.contractid + "/header.txt";
-            let config = match read_server_config() {
-                Ok(config) => config,
-                Err(_) => Config::default(),
-            };
-
-            let url = match config.url {
-                Some(url) => url,
-                None => "https://scl.darkfusion.tech/".to_owned(),
-            };
-
-            let import = ContractImport {
-                contract_id: new_contract.contractid.clone(),
-                ticker: new_contract.ticker,
-                rest_url: url.to_string(),
-                contract_type: "SCL04".to_string(),
-                decimals: new_contract.decimals,
-            };
+    let mut reciever_res: (String, u64) = (String::new(), 0);
+    if !reciever_contract.payloads.contains_key(txid) {
+        reciever_res = match reciever_contract.swap_recieve(
+            &txid.to_string(),
+            &payload.to_string(),
+            recieving_amount,
+        ) {
+            Ok(res) => res,
+            Err(err) => {
+                println!("{}", err);
+                (String::new(), 0)
+            }
+        };
+    }
 
-            let result = match serde_json::to_string(&import) {
-                Ok(result) => result,
-                Err(_) => return,
-            };
+    let mut claim_res: (String, u64, bool) = (String::new(), 0, false);
+    let mut swap_amount = lp_captures.1;
+    if recieving_amount == 0 {
+        swap_amount = 0;
+    }
+    if !claimer_contract.payloads.contains_key(txid) {
+        claim_res = match claimer_contract.swap_claim(
+            &txid.to_string(),
+            &payload.to_string(),
+            &input_utxos,
+            swap_amount,
+            block_height as u64,
+        ) {
+            Ok(res) => res,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+    }
 
-            write_to_file(path, result);
+    let _ = save_contract(&claimer_contract, payload, txid, pending);
+    let _ = save_contract(&reciever_contract, payload, txid, pending);
+    let mut balance_type = "O";
+    if pending {
+        balance_type = "U";
+    }
 
-            let mut lookup = match read_server_lookup() {
-                Ok(lookup) => lookup,
-                Err(_) => Lookups::default(),
-            };
+    save_check_utxo_file(
+        &reciever_contract.contractid,
+        &reciever_res.0,
+        reciever_res.1,
+        false,
+        pending,
+        balance_type,
+    );
+    save_check_utxo_file(
+        &claimer_contract.contractid,
+        &claim_res.0,
+        claim_res.1,
+        claim_res.2,
+        pending,
+        balance_type,
+    );
+    if !pending {
+        let _ = save_contract(&claimer_contract, payload, txid, true);
+        let _ = save_contract(&reciever_contract, payload, txid, true);
 
-            lookup.lps.push(new_contract.contractid);
-            let _ = save_server_lookup(lookup);
+        for utxo in input_utxos {
+            let file_path = format!("./Json/UTXOS/{}.txt", utxo);
+            // Attempt to remove the file
+            let _ = fs::remove_file(file_path);
         }
-        Err(_) => {}
-    };
+    }
 }
 
-pub fn perform_minting_scl05(txid: &str, payload: &str) {
-    match read_contract(txid, false) {
-        Ok(_) => return,
-        Err(_) => {}
+pub async fn perform_swap_lp(
+    txid: &str,
+    payload: &str,
+    pending: bool,
+    lp_contract_id: &str,
+    block_height: i32,
+) {
+    let mut lp_contract = match read_contract(lp_contract_id, pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
     };
 
-    let words: Vec<&str> = payload.split("SCL05:").collect();
-    if words.len() < 2 {
-        println!("Invalid Non-Fungible Token payload");
+    if lp_contract.payloads.contains_key(txid) {
         return;
     }
 
-    let mint_split: Vec<&str> = words[1].split(",").collect();
-    if mint_split.len() < 3 {
-        println!("Invalid Non-Fungible Token payload");
-        return;
+    let lp_pool = match lp_contract.liquidity_pool.clone() {
+        Some(lp_pool) => lp_pool,
+        None => return,
+    };
+
+    let lp_captures = match handle_swap_payload_lp(payload) {
+        Ok(captures) => captures,
+        Err(_) => return,
+    };
+
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let contract_id;
+    if lp_captures.0 {
+        contract_id = lp_pool.contract_id_1;
+    } else {
+        contract_id = lp_pool.contract_id_2;
     }
 
-    let ticker = replace_payload_special_characters(&mint_split[0].to_string());
-    let utxo_rec = replace_payload_special_characters(&mint_split[1].to_string());
-    let base_64 = replace_payload_special_characters(&mint_split[2].to_string());
-    let mut payloads: HashMap<String, String> = HashMap::new();
-    payloads.insert(txid.to_string(), payload.to_string());
-    let mut owners: HashMap<String, u64> = HashMap::new();
-    owners.insert(utxo_rec, 1);
-    let new_contract = SCL01Contract {
-        ticker,
-        contractid: txid.to_string(),
-        supply: 1,
-        decimals: 0,
-        owners,
-        payloads,
-        listings: None,
-        bids: None,
-        fulfillments: None,
-        drips: None,
-        diminishing_airdrops: None,
-        dges: None,
-        airdrop_amount: None,
-        current_airdrops: None,
-        total_airdrops: None,
-        pending_claims: None,
-        last_airdrop_split: None,
-        right_to_mint: None,
-        max_supply: None,
-        liquidated_tokens: None,
-        liquidity_pool: None,
-        token_data: Some(base_64),
+    let mut sender_contract = match read_contract(contract_id.as_str(), pending) {
+        Ok(contract) => contract,
+        Err(_) => return,
     };
 
-    match serde_json::to_string(&new_contract) {
-        Ok(s) => {
-            write_contract_directory(
-                format!("./Json/Contracts/{}/state.txt", &new_contract.contractid),
-                s.clone(),
-                new_contract.contractid.as_str(),
-            );
-            write_contract_directory(
-                format!("./Json/Contracts/{}/pending.txt", &new_contract.contractid),
-                s.clone(),
-                new_contract.contractid.as_str(),
-            );
-            let config = match read_server_config() {
-                Ok(config) => config,
-                Err(_) => Config::default(),
-            };
+    if sender_contract.payloads.contains_key(txid) {
+        return;
+    }
 
-            let url = match config.url {
-                Some(url) => url,
-                None => "https://scl.darkfusion.tech/".to_owned(),
-            };
+    match sender_contract.swap_claim(
+        &txid.to_string(),
+        &payload.to_string(),
+        &input_utxos,
+        lp_captures.1,
+        block_height as u64,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
 
-            let path =
-                "./Json/Contracts/".to_string() + "/" + &new_contract.contractid + "/header.txt";
-            let import = ContractImport {
-                contract_id: new_contract.contractid,
-                ticker: new_contract.ticker,
-                rest_url: url,
-                contract_type: "SCL05".to_string(),
-                decimals: new_contract.decimals,
-            };
-            let result = match serde_json::to_string(&import) {
-                Ok(result) => result,
-                Err(_) => return,
-            };
-            write_to_file(path, result);
+    let swap_block = get_tx_block_info(txid).await;
+    _ = match lp_contract.swap_lp(
+        txid,
+        payload,
+        contract_id,
+        lp_captures.1,
+        lp_captures.2,
+        lp_captures.3,
+        swap_block,
+    ) {
+        Ok(lp_res) => lp_res,
+        Err(err) => {
+            println!("{}", err);
+            return;
         }
-        Err(_) => {}
     };
+
+    if !pending {
+        let _ = save_contract(&lp_contract, payload, txid, false);
+    } else {
+        let _ = save_contract(&lp_contract, payload, txid, true);
+    }
 }
 
-pub async fn perform_provide_liquidity(
+pub async fn perform_liquidate_position(
     txid: &str,
     payload: &str,
     pending: bool,
     lp_contract_id: &String,
     block_height: i32,
 ) {
-    let captures = match handle_provide_liquidity_payload_lp(&payload) {
-        Ok(captures) => captures,
-        Err(_) => {
-            println!("Failed to parse liquity provision payload");
-            return;
-        }
-    };
-
-    let lp_contract = match read_contract(lp_contract_id.as_str(), pending) {
+    let mut lp_contract = match read_contract(lp_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    let lp_pool = match lp_contract.liquidity_pool {
-        Some(lp_pool) => lp_pool,
+    let liquidity_pool = match lp_contract.liquidity_pool.clone() {
+        Some(liquidity_pool) => liquidity_pool,
         None => return,
     };
 
-    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
-        Ok(inputs) => inputs,
-        Err(err) => {
-            println!("{}", err);
-            return;
-        }
-    };
+    let lp_res: (u64, u64, String, u64, bool);
+    if !liquidity_pool.liquidations.contains_key(txid) {
+        let captures = match handle_liquidatation_payload_lp(payload) {
+            Ok(captures) => captures,
+            Err(_) => return,
+        };
 
-    let mut contract_1 = match read_contract(lp_pool.contract_id_1.as_str(), pending) {
+        let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+            Ok(inputs) => inputs,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+
+        let liquidation_block = get_tx_block_info(txid).await;
+        lp_res = match lp_contract.liquidate_postion_lp(
+            txid,
+            payload,
+            &input_utxos,
+            captures,
+            block_height as u64,
+            liquidation_block,
+        ) {
+            Ok(lp_res) => lp_res,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+    } else {
+        lp_res = (
+            liquidity_pool.liquidations[txid].0,
+            liquidity_pool.liquidations[txid].1,
+            format!("{}:0", txid),
+            0,
+            false,
+        )
+    }
+
+    let mut contract_1 = match read_contract(liquidity_pool.contract_id_1.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
@@ -3119,12 +5235,10 @@ pub async fn perform_provide_liquidity(
         return;
     }
 
-    let res_1 = match contract_1.provide_liquidity(
+    let res_1 = match contract_1.liquidate_position(
         &txid.to_string(),
         &payload.to_string(),
-        &input_utxos,
-        captures,
-        block_height as u64,
+        lp_res.0,
         true,
     ) {
         Ok(res) => res,
@@ -3134,7 +5248,7 @@ pub async fn perform_provide_liquidity(
         }
     };
 
-    let mut contract_2 = match read_contract(lp_pool.contract_id_2.as_str(), pending) {
+    let mut contract_2 = match read_contract(liquidity_pool.contract_id_2.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
@@ -3143,12 +5257,10 @@ pub async fn perform_provide_liquidity(
         return;
     }
 
-    let res_2 = match contract_2.provide_liquidity(
+    let res_2 = match contract_2.liquidate_position(
         &txid.to_string(),
         &payload.to_string(),
-        &input_utxos,
-        captures,
-        block_height as u64,
+        lp_res.1,
         false,
     ) {
         Ok(res) => res,
@@ -3160,13 +5272,17 @@ pub async fn perform_provide_liquidity(
 
     let _ = save_contract(&contract_1, payload, txid, pending);
     let _ = save_contract(&contract_2, payload, txid, pending);
+    let mut balance_type = "O";
+    if pending {
+        balance_type = "U";
+    }
     save_check_utxo_file(
         &contract_2.contractid,
         &res_2.0,
         res_2.1,
         res_2.2,
         pending,
-        "O",
+        balance_type,
     );
     save_check_utxo_file(
         &contract_1.contractid,
@@ -3174,23 +5290,15 @@ pub async fn perform_provide_liquidity(
         res_1.1,
         res_1.2,
         pending,
-        "O",
+        balance_type,
     );
     if !pending {
         let _ = save_contract(&contract_1, payload, txid, true);
         let _ = save_contract(&contract_2, payload, txid, true);
-        for utxo in input_utxos {
-            let file_path = format!("./Json/UTXOS/{}.txt", utxo);
-            // Attempt to remove the file
-            match fs::remove_file(file_path) {
-                Ok(_) => {}
-                Err(_) => {}
-            }
-        }
     }
 }
 
-pub async fn perform_provide_liquidity_lp(
+pub async fn perform_liquidate_position_lp(
     txid: &str,
     payload: &str,
     pending: bool,
@@ -3206,122 +5314,196 @@ pub async fn perform_provide_liquidity_lp(
         return;
     }
 
-    let lp = match lp_contract.liquidity_pool.clone() {
-        Some(lp) => lp,
-        None => return,
+    let captures = match handle_liquidatation_payload_lp(payload) {
+        Ok(captures) => captures,
+        Err(_) => return,
     };
 
-    let captures = match handle_provide_liquidity_payload_lp(&payload) {
-        Ok(captures) => captures,
-        Err(_) => {
-            println!("Failed to parse liquity provision payload");
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
             return;
         }
     };
 
-    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
-        Ok(inputs) => inputs,
+    let liquidation_block = get_tx_block_info(txid).await;
+    let lp_res = match lp_contract.liquidate_postion_lp(
+        txid,
+        payload,
+        &input_utxos,
+        captures,
+        block_height as u64,
+        liquidation_block,
+    ) {
+        Ok(lp_res) => lp_res,
         Err(err) => {
             println!("{}", err);
             return;
         }
     };
 
-    let mut contract_1 = match read_contract(&lp.contract_id_1, pending) {
+    let _ = save_contract(&lp_contract, payload, txid, pending);
+    save_check_utxo_file(
+        &lp_contract.contractid,
+        &lp_res.2,
+        lp_res.3,
+        lp_res.4,
+        pending,
+        "O",
+    );
+    if !pending {
+        let _ = save_contract(&lp_contract, payload, txid, true);
+        for utxo in input_utxos {
+            let file_path = format!("./Json/UTXOS/{}.txt", utxo);
+            // Attempt to remove the file
+            let _ = fs::remove_file(file_path);
+        }
+    }
+}
+
+pub async fn perform_claim_protocol_fees(
+    txid: &str,
+    payload: &str,
+    pending: bool,
+    lp_contract_id: &str,
+) {
+    let mut lp_contract = match read_contract(lp_contract_id, pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    if !contract_1.payloads.contains_key(txid) {
-        match contract_1.provide_liquidity(
-            &txid.to_string(),
-            &payload.to_string(),
-            &input_utxos,
-            captures,
-            block_height as u64,
-            true,
-        ) {
-            Ok(res) => res,
-            Err(err) => {
-                println!("{}", err);
-                return;
-            }
-        };
+    if lp_contract.payloads.contains_key(txid) {
+        return;
     }
 
-    let mut contract_2 = match read_contract(&lp.contract_id_2, pending) {
-        Ok(contract) => contract,
-        Err(_) => return,
+    let liquidity_pool = match lp_contract.liquidity_pool.clone() {
+        Some(liquidity_pool) => liquidity_pool,
+        None => return,
     };
 
-    if !contract_2.payloads.contains_key(txid) {
-        let amount: u64 = (captures as f64 * lp.liquidity_ratio as f64) as u64;
-        match contract_2.provide_liquidity(
-            &txid.to_string(),
-            &payload.to_string(),
-            &input_utxos,
-            amount,
-            block_height as u64,
-            false,
-        ) {
-            Ok(res) => res,
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let input_address = match get_tx_first_input_address(txid).await {
+        Ok(input_address) => input_address,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let (amount_1, amount_2) =
+        match lp_contract.claim_protocol_fees(txid, payload, &input_utxos, &input_address) {
+            Ok(amounts) => amounts,
             Err(err) => {
                 println!("{}", err);
                 return;
             }
         };
-    }
 
-    let lp_res =
-        match lp_contract.provide_liquidity_lp(&txid.to_string(), &payload.to_string(), captures) {
-            Ok(lp_res) => lp_res,
+    let mut token_contracts: Vec<(SCL01Contract, (String, u64))> = Vec::new();
+    for (contract_id, amount) in [
+        (&liquidity_pool.contract_id_1, amount_1),
+        (&liquidity_pool.contract_id_2, amount_2),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+
+        let mut contract = match read_contract(contract_id.as_str(), pending) {
+            Ok(contract) => contract,
+            Err(_) => return,
+        };
+
+        let res = match contract.swap_recieve(&txid.to_string(), &payload.to_string(), amount) {
+            Ok(res) => res,
             Err(err) => {
-                println!("Failed to execute liquity provision: {}", err);
+                println!("{}", err);
                 return;
             }
         };
 
+        token_contracts.push((contract, res));
+    }
+
     let _ = save_contract(&lp_contract, payload, txid, pending);
     let mut balance_type = "O";
     if pending {
         balance_type = "U";
     }
 
-    save_check_utxo_file(
-        &lp_contract.contractid,
-        &lp_res.0,
-        lp_res.1,
-        false,
-        pending,
-        balance_type,
-    );
+    for (contract, res) in &token_contracts {
+        let _ = save_contract(contract, payload, txid, pending);
+        save_check_utxo_file(
+            &contract.contractid,
+            &res.0,
+            res.1,
+            false,
+            pending,
+            balance_type,
+        );
+    }
+
     if !pending {
+        for input_utxo in &input_utxos {
+            let _ = fs::remove_file(format!("./Json/UTXOS/{}.txt", input_utxo));
+        }
+
         let _ = save_contract(&lp_contract, payload, txid, true);
+        for (contract, _) in &token_contracts {
+            let _ = save_contract(contract, payload, txid, true);
+        }
     }
 }
 
-pub async fn perform_swap(
+pub async fn perform_zap_liquidity(
     txid: &str,
     payload: &str,
     pending: bool,
     lp_contract_id: &String,
     block_height: i32,
 ) {
+    let (sender_is_token_1, amount, minimum_lp_tokens) = match handle_zap_payload_lp(payload) {
+        Ok(captures) => captures,
+        Err(_) => {
+            println!("Failed to parse zap payload");
+            return;
+        }
+    };
+
     let mut lp_contract = match read_contract(lp_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
+    if lp_contract.payloads.contains_key(txid) {
+        return;
+    }
+
     let liquidity_pool = match lp_contract.liquidity_pool.clone() {
         Some(liquidity_pool) => liquidity_pool,
         None => return,
     };
 
-    let lp_captures = match handle_swap_payload_lp(&payload) {
-        Ok(captures) => captures,
-        Err(_) => return,
+    let plan = match liquidity_pool.zap_plan(sender_is_token_1, amount, lp_contract.supply) {
+        Ok(plan) => plan,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
     };
 
+    if plan.lp_tokens < minimum_lp_tokens {
+        println!("zap_liquidity: lp tokens are below the minimum");
+        return;
+    }
+
     let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
         Ok(inputs) => inputs,
         Err(err) => {
@@ -3330,39 +5512,41 @@ pub async fn perform_swap(
         }
     };
 
-    let reciever_contract_id;
-    let claimer_contract_id;
-    if lp_captures.0 {
-        reciever_contract_id = liquidity_pool.contract_id_2;
-        claimer_contract_id = liquidity_pool.contract_id_1;
-    } else {
-        reciever_contract_id = liquidity_pool.contract_id_1;
-        claimer_contract_id = liquidity_pool.contract_id_2;
-    }
-
-    let mut claimer_contract = match read_contract(claimer_contract_id.as_str(), pending) {
+    let mut sender_contract = match read_contract(plan.sender_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    let mut reciever_contract = match read_contract(reciever_contract_id.as_str(), pending) {
-        Ok(contract) => contract,
-        Err(_) => return,
+    if sender_contract.payloads.contains_key(txid) {
+        return;
+    }
+
+    let sender_res = match sender_contract.provide_liquidity(
+        &txid.to_string(),
+        &payload.to_string(),
+        &input_utxos,
+        amount,
+        block_height as u64,
+        sender_is_token_1,
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
     };
 
-    let recieving_amount: u64;
-    if liquidity_pool.swaps.contains_key(txid) {
-        recieving_amount = liquidity_pool.swaps[txid].1;
-    } else {
-        recieving_amount = match lp_contract.swap_lp(
-            &txid.to_string(),
-            &payload.to_string(),
-            claimer_contract_id,
-            lp_captures.1,
-            lp_captures.2,
-            lp_captures.3,
+    if plan.swap_in > 0 {
+        let swap_block = get_tx_block_info(txid).await;
+        match lp_contract.settle_swap(
+            txid,
+            payload,
+            &plan.sender_contract_id,
+            plan.swap_in,
+            plan.swap_out,
+            swap_block,
         ) {
-            Ok(lp_res) => lp_res,
+            Ok(_) => {}
             Err(err) => {
                 println!("{}", err);
                 return;
@@ -3370,35 +5554,38 @@ pub async fn perform_swap(
         };
     }
 
-    let mut reciever_res: (String, u64) = (String::new(), 0);
-    if !reciever_contract.payloads.contains_key(txid) {
-        reciever_res = match reciever_contract.swap_recieve(
-            &txid.to_string(),
-            &payload.to_string(),
-            recieving_amount,
-        ) {
-            Ok(res) => res,
+    let lp_res =
+        match lp_contract.provide_liquidity_lp(txid, payload, plan.provided_1, block_height as u64)
+        {
+            Ok(lp_res) => lp_res,
             Err(err) => {
-                println!("{}", err);
-                (String::new(), 0)
+                println!("Failed to execute zap: {}", err);
+                return;
             }
         };
-    }
 
-    let mut claim_res: (String, u64, bool) = (String::new(), 0, false);
-    let mut swap_amount = lp_captures.1;
-    if recieving_amount == 0 {
-        swap_amount = 0;
-    }
-    if !claimer_contract.payloads.contains_key(txid) {
-        claim_res = match claimer_contract.swap_claim(
+    // Unpaired token 2 goes back to the lp token output
+    let mut contract_2: Option<SCL01Contract> = None;
+    let mut refund_res: Option<(String, u64)> = None;
+    if plan.refund_2 > 0 {
+        let mut refund_contract = &mut sender_contract;
+        if sender_is_token_1 {
+            contract_2 = match read_contract(liquidity_pool.contract_id_2.as_str(), pending) {
+                Ok(contract) => Some(contract),
+                Err(_) => return,
+            };
+
+            if let Some(contract) = contract_2.as_mut() {
+                refund_contract = contract;
+            }
+        }
+
+        refund_res = match refund_contract.swap_recieve(
             &txid.to_string(),
             &payload.to_string(),
-            &input_utxos,
-            swap_amount,
-            block_height as u64,
+            plan.refund_2,
         ) {
-            Ok(res) => res,
+            Ok(res) => Some(res),
             Err(err) => {
                 println!("{}", err);
                 return;
@@ -3406,36 +5593,53 @@ pub async fn perform_swap(
         };
     }
 
-    let _ = save_contract(&claimer_contract, payload, txid, pending);
-    let _ = save_contract(&reciever_contract, payload, txid, pending);
     let mut balance_type = "O";
     if pending {
         balance_type = "U";
     }
 
+    let _ = save_contract(&lp_contract, payload, txid, pending);
+    let _ = save_contract(&sender_contract, payload, txid, pending);
+    if let Some(contract) = &contract_2 {
+        let _ = save_contract(contract, payload, txid, pending);
+    }
+
     save_check_utxo_file(
-        &reciever_contract.contractid,
-        &reciever_res.0,
-        reciever_res.1,
+        &lp_contract.contractid,
+        &lp_res.0,
+        lp_res.1,
         false,
         pending,
         balance_type,
     );
     save_check_utxo_file(
-        &claimer_contract.contractid,
-        &claim_res.0,
-        claim_res.1,
-        claim_res.2,
+        &sender_contract.contractid,
+        &sender_res.0,
+        sender_res.1,
+        sender_res.2,
         pending,
         balance_type,
     );
+    if let Some(res) = &refund_res {
+        save_check_utxo_file(
+            &liquidity_pool.contract_id_2,
+            &res.0,
+            res.1,
+            false,
+            pending,
+            balance_type,
+        );
+    }
+
     if !pending {
-        let _ = save_contract(&claimer_contract, payload, txid, true);
-        let _ = save_contract(&reciever_contract, payload, txid, true);
+        let _ = save_contract(&lp_contract, payload, txid, true);
+        let _ = save_contract(&sender_contract, payload, txid, true);
+        if let Some(contract) = &contract_2 {
+            let _ = save_contract(contract, payload, txid, true);
+        }
 
         for utxo in input_utxos {
             let file_path = format!("./Json/UTXOS/{}.txt", utxo);
-            // Attempt to remove the file
             match fs::remove_file(file_path) {
                 Ok(_) => {}
                 Err(_) => {}
@@ -3444,183 +5648,332 @@ pub async fn perform_swap(
     }
 }
 
-pub async fn perform_swap_lp(
-    txid: &str,
-    payload: &str,
-    pending: bool,
-    lp_contract_id: &String,
-    block_height: i32,
-) {
-    let mut lp_contract = match read_contract(lp_contract_id.as_str(), pending) {
-        Ok(contract) => contract,
-        Err(_) => return,
-    };
+pub fn handle_zap_payload_lp(payload: &str) -> Result<(bool, u64, u64), String> {
+    let words: Vec<&str> = payload.split("ZLP[").collect();
+    if words.len() < 2 {
+        return Err("Invalid zap payload".to_string());
+    }
 
-    if lp_contract.payloads.contains_key(txid) {
-        return;
+    let zap_split: Vec<&str> = words[1].split(",").collect();
+    if zap_split.len() < 2 {
+        return Err("Invalid zap payload".to_string());
     }
 
-    let lp_pool = match lp_contract.liquidity_pool.clone() {
-        Some(lp_pool) => lp_pool,
-        None => return,
+    // Same token selector as SLP, 0 sends token 1 and 1 sends token 2
+    let sender_is_token_1: bool = zap_split[0].to_ascii_lowercase().contains("0");
+    let amount = match replace_payload_special_characters(&zap_split[1].to_string()).parse() {
+        Ok(amount) => amount,
+        Err(_) => return Err("Not valid payload".to_string()),
     };
 
-    let lp_captures = match handle_swap_payload_lp(&payload) {
-        Ok(captures) => captures,
-        Err(_) => return,
+    let mut minimum_lp_tokens = 0;
+    if zap_split.len() > 2 {
+        minimum_lp_tokens =
+            match replace_payload_special_characters(&zap_split[2].to_string()).parse() {
+                Ok(minimum_lp_tokens) => minimum_lp_tokens,
+                Err(_) => return Err("Not valid payload".to_string()),
+            };
+    }
+
+    return Ok((sender_is_token_1, amount, minimum_lp_tokens));
+}
+
+pub fn is_liquidity_pool_payload(payload: &str) -> bool {
+    return payload.contains("SLP[")
+        || payload.contains("PLP[")
+        || payload.contains("ZLP[")
+        || payload.contains("LLP[")
+        || payload.contains("PFC[");
+}
+
+// LP envelope: "SCL" | version | tag | 32 byte LP contract id | command
+pub const LP_ENVELOPE_MARKER: &[u8] = b"SCL";
+pub const LP_ENVELOPE_VERSION: u8 = 1;
+pub const LP_ENVELOPE_TAG: u8 = b'F';
+pub const LP_ENVELOPE_ID_LEN: usize = 32;
+
+pub fn lp_envelope_id(contract_id: &str) -> Option<String> {
+    let id = contract_id.to_ascii_lowercase();
+    match decode(&id) {
+        Ok(bytes) if bytes.len() == LP_ENVELOPE_ID_LEN => return Some(id),
+        _ => return None,
     };
+}
 
-    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
-        Ok(inputs) => inputs,
-        Err(err) => {
-            println!("{}", err);
-            return;
+/// Map of envelope ids to LP contract ids, keyed by the full id so every host routes an
+/// envelope to the same pool whatever other pools it knows about.
+pub fn lp_envelope_ids(contract_ids: &Vec<String>) -> HashMap<String, String> {
+    let mut lp_ids: HashMap<String, String> = HashMap::new();
+    for contract_id in contract_ids {
+        if let Some(id) = lp_envelope_id(contract_id) {
+            lp_ids.insert(id, contract_id.clone());
         }
+    }
+
+    return lp_ids;
+}
+
+// Legacy magic-crypt LP payloads are only trial decrypted in blocks below the legacy_lp_cutoff
+// set in the server config, with no cutoff set they are decoded at every height
+pub fn legacy_lp_payload_allowed(block_height: u64, legacy_cutoff: Option<u64>) -> bool {
+    return legacy_cutoff.is_none_or(|legacy_cutoff| block_height < legacy_cutoff);
+}
+
+pub fn encode_lp_envelope(contract_id: &str, payload: &str) -> Result<Vec<u8>, String> {
+    if !is_liquidity_pool_payload(payload) {
+        return Err("Invalid liquidity pool payload".to_string());
+    }
+
+    let id_bytes = match lp_envelope_id(contract_id).map(decode) {
+        Some(Ok(id_bytes)) => id_bytes,
+        _ => return Err("Invalid liquidity pool contract id".to_string()),
     };
 
-    let contract_id;
-    if lp_captures.0 {
-        contract_id = lp_pool.contract_id_1;
-    } else {
-        contract_id = lp_pool.contract_id_2;
+    let mut envelope = LP_ENVELOPE_MARKER.to_vec();
+    envelope.push(LP_ENVELOPE_VERSION);
+    envelope.push(LP_ENVELOPE_TAG);
+    envelope.extend(id_bytes);
+    envelope.extend(payload.as_bytes());
+    return Ok(envelope);
+}
+
+// Returns the contract id and the plaintext command of a tagged envelope
+pub fn decode_lp_envelope(bytes: &[u8]) -> Option<(String, String)> {
+    let header_len = LP_ENVELOPE_MARKER.len() + 2;
+    if bytes.len() <= header_len + LP_ENVELOPE_ID_LEN || !bytes.starts_with(LP_ENVELOPE_MARKER) {
+        return None;
     }
 
-    let mut sender_contract = match read_contract(contract_id.as_str(), pending) {
-        Ok(contract) => contract,
-        Err(_) => return,
+    if bytes[LP_ENVELOPE_MARKER.len()] != LP_ENVELOPE_VERSION
+        || bytes[LP_ENVELOPE_MARKER.len() + 1] != LP_ENVELOPE_TAG
+    {
+        return None;
+    }
+
+    let id = hex::encode(&bytes[header_len..header_len + LP_ENVELOPE_ID_LEN]);
+    let payload = match std::str::from_utf8(&bytes[header_len + LP_ENVELOPE_ID_LEN..]) {
+        Ok(payload) => payload.to_string(),
+        Err(_) => return None,
     };
 
-    if sender_contract.payloads.contains_key(txid) {
-        return;
+    return Some((id, payload));
+}
+
+// Pre-envelope payloads were magic-crypt encrypted with the LP contract id as the key
+pub fn decode_legacy_lp_payload(bytes: &[u8], contract_id: &str) -> Option<String> {
+    let mc = new_magic_crypt!(contract_id, 64);
+    let payload_bytes = match mc.decrypt_bytes_to_bytes(bytes) {
+        Ok(bytes) => bytes,
+        Err(_) => return None,
+    };
+
+    let payload: String = payload_bytes.iter().map(|&byte| byte as char).collect();
+    if !is_liquidity_pool_payload(&payload) {
+        return None;
     }
 
-    match sender_contract.swap_claim(
-        &txid.to_string(),
-        &payload.to_string(),
-        &input_utxos,
-        lp_captures.1,
-        block_height as u64,
-    ) {
-        Ok(res) => res,
-        Err(err) => {
-            println!("{}", err);
-            return;
+    return Some(payload);
+}
+
+pub fn decode_lp_payload(
+    bytes: &[u8],
+    contract_id: &str,
+    block_height: u64,
+    legacy_cutoff: Option<u64>,
+) -> Option<String> {
+    if let Some((id, payload)) = decode_lp_envelope(bytes) {
+        if Some(id) != lp_envelope_id(contract_id) || !is_liquidity_pool_payload(&payload) {
+            return None;
         }
-    };
 
-    _ = match lp_contract.swap_lp(
-        &txid.to_string(),
-        &payload.to_string(),
-        contract_id,
-        lp_captures.1,
-        lp_captures.2,
-        lp_captures.3,
-    ) {
-        Ok(lp_res) => lp_res,
-        Err(err) => {
-            println!("{}", err);
-            return;
+        return Some(payload);
+    }
+
+    if !legacy_lp_payload_allowed(block_height, legacy_cutoff) {
+        return None;
+    }
+
+    return decode_legacy_lp_payload(bytes, contract_id);
+}
+
+// Routes an OP_RETURN to its liquidity pool, by id for envelopes and by trial decryption for
+// legacy payloads in blocks before the cutoff
+pub fn route_lp_payload(
+    bytes: &[u8],
+    lp_ids: &HashMap<String, String>,
+    contract_ids: &Vec<String>,
+    block_height: u64,
+    legacy_cutoff: Option<u64>,
+) -> Option<(String, String)> {
+    if let Some((id, payload)) = decode_lp_envelope(bytes) {
+        let contract_id = match lp_ids.get(&id) {
+            Some(contract_id) => contract_id.clone(),
+            None => return None,
+        };
+
+        if !is_liquidity_pool_payload(&payload) {
+            return None;
         }
-    };
 
-    if !pending {
-        let _ = save_contract(&lp_contract, payload, txid, false);
-    } else {
-        let _ = save_contract(&lp_contract, payload, txid, true);
+        return Some((contract_id, payload));
     }
+
+    if !legacy_lp_payload_allowed(block_height, legacy_cutoff) {
+        return None;
+    }
+
+    for contract_id in contract_ids {
+        if let Some(payload) = decode_legacy_lp_payload(bytes, contract_id) {
+            return Some((contract_id.clone(), payload));
+        }
+    }
+
+    return None;
 }
 
-pub async fn perform_liquidate_position(
+pub fn handle_provide_liquidity_payload_lp(payload: &str) -> Result<u64, String> {
+    let words: Vec<&str> = payload.split("PLP[").collect();
+    if words.len() < 2 {
+        return Err("Invalid liquidity pool payload".to_string());
+    }
+
+    let amount_split = replace_payload_special_characters(&words[1].to_string());
+
+    // Parse strings to numeric types
+    let amount = match amount_split.parse() {
+        Ok(amount) => amount,
+        Err(_) => return Err("Not mint valid payload".to_string()),
+    };
+    return Ok(amount);
+}
+
+pub async fn perform_multi_hop_swap(
     txid: &str,
     payload: &str,
     pending: bool,
     lp_contract_id: &String,
     block_height: i32,
 ) {
-    let mut lp_contract = match read_contract(lp_contract_id.as_str(), pending) {
+    let (from_contract_id, amount, quoted, tolerance_bps, lp_contract_ids) =
+        match handle_multi_hop_swap_payload(payload) {
+            Ok(captures) => captures,
+            Err(_) => return,
+        };
+
+    if lp_contract_ids.is_empty()
+        || lp_contract_ids.len() > MAX_SWAP_HOPS
+        || lp_contract_ids[0] != *lp_contract_id
+    {
+        return;
+    }
+
+    let mut lp_contracts: Vec<SCL01Contract> = Vec::new();
+    for lp_id in &lp_contract_ids {
+        if lp_contracts
+            .iter()
+            .any(|contract| contract.contractid == *lp_id)
+        {
+            return;
+        }
+
+        let lp_contract = match read_contract(lp_id.as_str(), pending) {
+            Ok(contract) => contract,
+            Err(_) => return,
+        };
+
+        if lp_contract.payloads.contains_key(txid) {
+            return;
+        }
+
+        lp_contracts.push(lp_contract);
+    }
+
+    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let mut claimer_contract = match read_contract(from_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    let liquidity_pool = match lp_contract.liquidity_pool.clone() {
-        Some(liquidity_pool) => liquidity_pool,
-        None => return,
-    };
+    if claimer_contract.payloads.contains_key(txid) {
+        return;
+    }
 
-    let lp_res: (u64, u64, String, u64, bool);
-    if !liquidity_pool.liquidations.contains_key(txid) {
-        let captures = match handle_liquidatation_payload_lp(&payload) {
-            Ok(captures) => captures,
-            Err(_) => return,
+    // Quote every hop before touching any state so the swap settles all or nothing
+    let mut quotes: Vec<SwapQuote> = Vec::new();
+    let mut current_contract_id = from_contract_id.clone();
+    let mut current_amount = amount;
+    for lp_contract in &lp_contracts {
+        let liquidity_pool = match lp_contract.liquidity_pool.clone() {
+            Some(liquidity_pool) => liquidity_pool,
+            None => return,
         };
 
-        let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
-            Ok(inputs) => inputs,
+        let quote = match liquidity_pool.quote_swap(&current_contract_id, current_amount, 0) {
+            Ok(quote) => quote,
             Err(err) => {
                 println!("{}", err);
                 return;
             }
         };
 
-        lp_res = match lp_contract.liquidate_postion_lp(
-            &txid.to_string(),
-            &payload.to_string(),
-            &input_utxos,
-            captures,
-            block_height as u64,
-        ) {
-            Ok(lp_res) => lp_res,
-            Err(err) => {
-                println!("{}", err);
-                return;
-            }
-        };
-    } else {
-        lp_res = (
-            liquidity_pool.liquidations[txid].0,
-            liquidity_pool.liquidations[txid].1,
-            format!("{}:0", txid),
-            0,
-            false,
-        )
+        current_contract_id = quote.to_contract_id.clone();
+        current_amount = quote.amount_out;
+        quotes.push(quote);
     }
 
-    let mut contract_1 = match read_contract(liquidity_pool.contract_id_1.as_str(), pending) {
-        Ok(contract) => contract,
-        Err(_) => return,
-    };
-
-    if contract_1.payloads.contains_key(txid) {
+    if current_contract_id == from_contract_id {
         return;
     }
 
-    let res_1 = match contract_1.liquidate_position(
-        &txid.to_string(),
-        &payload.to_string(),
-        lp_res.0,
-        true,
-    ) {
-        Ok(res) => res,
-        Err(err) => {
-            println!("{}", err);
-            return;
-        }
-    };
-
-    let mut contract_2 = match read_contract(liquidity_pool.contract_id_2.as_str(), pending) {
+    let mut reciever_contract = match read_contract(current_contract_id.as_str(), pending) {
         Ok(contract) => contract,
         Err(_) => return,
     };
 
-    if contract_2.payloads.contains_key(txid) {
-        return;
+    let swap_amount = apply_slippage_tolerance(current_amount, quoted, tolerance_bps);
+    let mut claim_amount = amount;
+    if swap_amount == 0 {
+        println!("multi_hop_swap: swap amount is below tolerence");
+        claim_amount = 0;
+    } else {
+        let swap_block = get_tx_block_info(txid).await;
+        let last_hop = quotes.len() - 1;
+        for (index, lp_contract) in lp_contracts.iter_mut().enumerate() {
+            let mut hop_amount = quotes[index].amount_out;
+            if index == last_hop {
+                hop_amount = swap_amount;
+            }
+
+            match lp_contract.settle_swap(
+                txid,
+                payload,
+                &quotes[index].from_contract_id,
+                quotes[index].amount_in,
+                hop_amount,
+                swap_block,
+            ) {
+                Ok(_) => {}
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            };
+        }
     }
 
-    let res_2 = match contract_2.liquidate_position(
+    let claim_res = match claimer_contract.swap_claim(
         &txid.to_string(),
         &payload.to_string(),
-        lp_res.1,
-        false,
+        &input_utxos,
+        claim_amount,
+        block_height as u64,
     ) {
         Ok(res) => res,
         Err(err) => {
@@ -3629,116 +5982,228 @@ pub async fn perform_liquidate_position(
         }
     };
 
-    let _ = save_contract(&contract_1, payload, txid, pending);
-    let _ = save_contract(&contract_2, payload, txid, pending);
+    let mut reciever_res: (String, u64) = (String::new(), 0);
+    if swap_amount > 0 {
+        reciever_res = match reciever_contract.swap_recieve(
+            &txid.to_string(),
+            &payload.to_string(),
+            swap_amount,
+        ) {
+            Ok(res) => res,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+    }
+
+    for lp_contract in &lp_contracts {
+        let _ = save_contract(lp_contract, payload, txid, pending);
+    }
+
+    let _ = save_contract(&claimer_contract, payload, txid, pending);
+    let _ = save_contract(&reciever_contract, payload, txid, pending);
     let mut balance_type = "O";
     if pending {
         balance_type = "U";
     }
+
     save_check_utxo_file(
-        &contract_2.contractid,
-        &res_2.0,
-        res_2.1,
-        res_2.2,
+        &reciever_contract.contractid,
+        &reciever_res.0,
+        reciever_res.1,
+        false,
         pending,
         balance_type,
     );
     save_check_utxo_file(
-        &contract_1.contractid,
-        &res_1.0,
-        res_1.1,
-        res_1.2,
+        &claimer_contract.contractid,
+        &claim_res.0,
+        claim_res.1,
+        claim_res.2,
         pending,
         balance_type,
     );
     if !pending {
-        let _ = save_contract(&contract_1, payload, txid, true);
-        let _ = save_contract(&contract_2, payload, txid, true);
+        for lp_contract in &lp_contracts {
+            let _ = save_contract(lp_contract, payload, txid, true);
+        }
+
+        let _ = save_contract(&claimer_contract, payload, txid, true);
+        let _ = save_contract(&reciever_contract, payload, txid, true);
+        for utxo in input_utxos {
+            let file_path = format!("./Json/UTXOS/{}.txt", utxo);
+            let _ = fs::remove_file(file_path);
+        }
     }
 }
 
-pub async fn perform_liquidate_position_lp(
-    txid: &str,
-    payload: &str,
+pub fn find_swap_route(
+    from_contract_id: &String,
+    to_contract_id: &String,
+    amount: u64,
+    tolerance_bps: u64,
     pending: bool,
-    lp_contract_id: &String,
-    block_height: i32,
-) {
-    let mut lp_contract = match read_contract(lp_contract_id.as_str(), pending) {
-        Ok(contract) => contract,
-        Err(_) => return,
+) -> Result<SwapRoute, String> {
+    if from_contract_id == to_contract_id {
+        return Err("find_swap_route: from and to contracts are the same".to_string());
+    }
+
+    let lookups = match read_server_lookup() {
+        Ok(lookups) => lookups,
+        Err(_) => return Err("find_swap_route: unable to read lookups".to_string()),
     };
 
-    if lp_contract.payloads.contains_key(txid) {
-        return;
+    let mut pools: Vec<(String, LiquidityPool)> = Vec::new();
+    for lp_id in lookups.lps {
+        let lp_contract = match read_contract(lp_id.as_str(), pending) {
+            Ok(contract) => contract,
+            Err(_) => continue,
+        };
+
+        if let Some(liquidity_pool) = lp_contract.liquidity_pool {
+            pools.push((lp_id, liquidity_pool));
+        }
     }
 
-    let captures = match handle_liquidatation_payload_lp(&payload) {
-        Ok(captures) => captures,
-        Err(_) => return,
-    };
+    let mut hops: Vec<(String, SwapQuote)> = Vec::new();
+    let mut best: Vec<(String, SwapQuote)> = Vec::new();
+    search_swap_routes(
+        &pools,
+        from_contract_id,
+        to_contract_id,
+        amount,
+        &mut hops,
+        &mut best,
+    );
+    let amount_out = match best.last() {
+        Some((_, quote)) => quote.amount_out,
+        None => return Err("find_swap_route: no route between contracts".to_string()),
+    };
+
+    let minimum_received =
+        (amount_out as u128 * (BPS_SCALE - tolerance_bps) as u128 / BPS_SCALE as u128) as u64;
+    let lp_contract_ids: Vec<String> = best.iter().map(|(lp_id, _)| lp_id.clone()).collect();
+    let payload = format!(
+        "{{{}:MSLP[{},{},{},{}.{:04},{}]}}",
+        lp_contract_ids[0],
+        from_contract_id,
+        amount,
+        amount_out,
+        tolerance_bps / BPS_SCALE,
+        tolerance_bps % BPS_SCALE,
+        lp_contract_ids.join(",")
+    );
 
-    let input_utxos: Vec<String> = match get_tx_inputs(txid).await {
-        Ok(inputs) => inputs,
-        Err(err) => {
-            println!("{}", err);
-            return;
+    return Ok(SwapRoute {
+        from_contract_id: from_contract_id.to_string(),
+        to_contract_id: to_contract_id.to_string(),
+        amount_in: amount,
+        amount_out,
+        minimum_received,
+        lp_contract_ids,
+        hops: best.into_iter().map(|(_, quote)| quote).collect(),
+        payload,
+    });
+}
+
+fn search_swap_routes(
+    pools: &Vec<(String, LiquidityPool)>,
+    current_contract_id: &str,
+    to_contract_id: &String,
+    amount: u64,
+    hops: &mut Vec<(String, SwapQuote)>,
+    best: &mut Vec<(String, SwapQuote)>,
+) {
+    if hops.len() >= MAX_SWAP_HOPS {
+        return;
+    }
+
+    for (lp_id, liquidity_pool) in pools {
+        if hops.iter().any(|(hop_lp_id, _)| hop_lp_id == lp_id) {
+            continue;
         }
-    };
 
-    let lp_res = match lp_contract.liquidate_postion_lp(
-        &txid.to_string(),
-        &payload.to_string(),
-        &input_utxos,
-        captures,
-        block_height as u64,
-    ) {
-        Ok(lp_res) => lp_res,
-        Err(err) => {
-            println!("{}", err);
-            return;
+        let quote = match liquidity_pool.quote_swap(current_contract_id, amount, 0) {
+            Ok(quote) => quote,
+            Err(_) => continue,
+        };
+
+        // Never route back through a token the path has already left
+        if quote.amount_out == 0
+            || hops
+                .iter()
+                .any(|(_, hop)| hop.from_contract_id == quote.to_contract_id)
+        {
+            continue;
         }
-    };
 
-    let _ = save_contract(&lp_contract, payload, txid, pending);
-    save_check_utxo_file(
-        &lp_contract.contractid,
-        &lp_res.2,
-        lp_res.3,
-        lp_res.4,
-        pending,
-        "O",
-    );
-    if !pending {
-        let _ = save_contract(&lp_contract, payload, txid, true);
-        for utxo in input_utxos {
-            let file_path = format!("./Json/UTXOS/{}.txt", utxo);
-            // Attempt to remove the file
-            match fs::remove_file(file_path) {
-                Ok(_) => {}
-                Err(_) => {}
+        let next_contract_id = quote.to_contract_id.clone();
+        let amount_out = quote.amount_out;
+        hops.push((lp_id.clone(), quote));
+        if next_contract_id == *to_contract_id {
+            let best_amount = match best.last() {
+                Some((_, best_quote)) => best_quote.amount_out,
+                None => 0,
+            };
+
+            if amount_out > best_amount {
+                *best = hops.clone();
             }
+        } else {
+            search_swap_routes(
+                pools,
+                &next_contract_id,
+                to_contract_id,
+                amount_out,
+                hops,
+                best,
+            );
         }
+
+        hops.pop();
     }
 }
 
-pub fn handle_provide_liquidity_payload_lp(payload: &str) -> Result<u64, String> {
-    let words: Vec<&str> = payload.split("PLP[").collect();
+pub fn handle_multi_hop_swap_payload(
+    payload: &str,
+) -> Result<(String, u64, u64, u64, Vec<String>), String> {
+    let words: Vec<&str> = payload.split("MSLP[").collect();
     if words.len() < 2 {
-        return Err("Invalid liquidity pool payload".to_string());
+        return Err("Invalid multi hop swap payload".to_string());
     }
 
-    let amount_split = replace_payload_special_characters(&words[1].to_string());
+    let swap_split: Vec<&str> = words[1].split(",").collect();
+    if swap_split.len() < 5 {
+        return Err("Invalid multi hop swap payload".to_string());
+    }
 
-    // Parse strings to numeric types
-    let amount = match amount_split.parse() {
+    let from_contract_id = replace_payload_special_characters(&swap_split[0].to_string());
+    let amount = match replace_payload_special_characters(&swap_split[1].to_string()).parse() {
         Ok(amount) => amount,
-        Err(_) => return Err("Not mint valid payload".to_string()),
+        Err(_) => return Err("Not valid payload".to_string()),
     };
-    return Ok(amount);
+
+    let quoted = match replace_payload_special_characters(&swap_split[2].to_string()).parse() {
+        Ok(quoted) => quoted,
+        Err(_) => return Err("Not valid payload".to_string()),
+    };
+
+    let tolerance_split = replace_payload_special_characters(&swap_split[3].to_string());
+    let tolerance = match parse_fixed_point(&tolerance_split, 4) {
+        Ok(tolerance) if tolerance <= BPS_SCALE as u128 => tolerance as u64,
+        _ => return Err("Not valid payload".to_string()),
+    };
+
+    let mut lp_contract_ids: Vec<String> = Vec::new();
+    for lp_split in &swap_split[4..] {
+        lp_contract_ids.push(replace_payload_special_characters(&lp_split.to_string()));
+    }
+
+    return Ok((from_contract_id, amount, quoted, tolerance, lp_contract_ids));
 }
 
-pub fn handle_swap_payload_lp(payload: &str) -> Result<(bool, u64, u64, f32), String> {
+pub fn handle_swap_payload_lp(payload: &str) -> Result<(bool, u64, u64, u64), String> {
     let words: Vec<&str> = payload.split("SLP[").collect();
     if words.len() < 2 {
         return Err("Invalid liquidity pool payload".to_string());
@@ -3765,9 +6230,10 @@ pub fn handle_swap_payload_lp(payload: &str) -> Result<(bool, u64, u64, f32), St
         Err(_) => return Err("Not valid payload".to_string()),
     };
 
-    let tolerance = match tolerance_split.parse() {
-        Ok(tolerance) => tolerance,
-        Err(_) => return Err("Not valid payload".to_string()),
+    // Slippage tolerance is sent as a fraction, eg 0.01, and settled in basis points
+    let tolerance = match parse_fixed_point(&tolerance_split, 4) {
+        Ok(tolerance) if tolerance <= u64::MAX as u128 => tolerance as u64,
+        _ => return Err("Not valid payload".to_string()),
     };
 
     return Ok((lp_contract, amount, quoted, tolerance));
@@ -3816,3 +6282,226 @@ pub fn save_check_utxo_file(
         Err(_) => return,
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LP_1: &str = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+    const LP_2: &str = "0011223344556677ffeeddccbbaa99887766554433221100ffeeddccbbaa9988";
+    const LP_3: &str = "8899aabbccddeeff00112233445566778899aabbccddeeff0011223344556677";
+
+    #[test]
+    fn lp_envelope_routes_by_full_id() {
+        let contract_ids = vec![LP_1.to_string(), LP_2.to_string(), LP_3.to_string()];
+        let lp_ids = lp_envelope_ids(&contract_ids);
+        for contract_id in [LP_1, LP_2, LP_3] {
+            let envelope = encode_lp_envelope(contract_id, "SLP[0,1000,990,0.01]").unwrap();
+            assert_eq!(envelope.len(), 5 + LP_ENVELOPE_ID_LEN + 20);
+            assert_eq!(
+                route_lp_payload(&envelope, &lp_ids, &contract_ids, 0, None),
+                Some((contract_id.to_string(), "SLP[0,1000,990,0.01]".to_string()))
+            );
+            assert_eq!(
+                decode_lp_payload(&envelope, contract_id, 0, None),
+                Some("SLP[0,1000,990,0.01]".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn lp_envelope_ignores_unknown_pools_and_tags() {
+        let contract_ids = vec![LP_1.to_string()];
+        let lp_ids = lp_envelope_ids(&contract_ids);
+        let envelope = encode_lp_envelope(LP_2, "LLP[100]").unwrap();
+        assert_eq!(
+            route_lp_payload(&envelope, &lp_ids, &contract_ids, 0, None),
+            None
+        );
+        assert_eq!(decode_lp_payload(&envelope, LP_1, 0, None), None);
+
+        // Short prefix envelopes are not routed
+        let mut envelope = b"SCL\x01L".to_vec();
+        envelope.extend(decode(&LP_1[..16]).unwrap());
+        envelope.extend(b"LLP[100]");
+        assert_eq!(
+            route_lp_payload(&envelope, &lp_ids, &contract_ids, 0, None),
+            None
+        );
+    }
+
+    #[test]
+    fn metadata_payload_decodes_hex_fields() {
+        let name = hex::encode("BURN, SCL01 & {friends}");
+        let link = hex::encode("https://x.com/a?b=c;d");
+        let command = format!(
+            "cid:METADATA[old:0,TXID:1,{},,,{},x={};web_site-2={}]",
+            name,
+            hex::encode("https://example.com"),
+            link,
+            link
+        );
+        let (authority_utxo, new_authority_utxo, metadata) =
+            handle_metadata_payload("abc", &command).unwrap();
+        assert_eq!(authority_utxo, "old:0");
+        assert_eq!(new_authority_utxo, "abc:1");
+        assert_eq!(metadata.name, Some("BURN, SCL01 & {friends}".to_string()));
+        assert_eq!(metadata.description, None);
+        assert_eq!(metadata.website, Some("https://example.com".to_string()));
+        assert_eq!(metadata.socials["x"], "https://x.com/a?b=c;d");
+        assert_eq!(metadata.socials.len(), 2);
+    }
+
+    #[test]
+    fn metadata_payload_rejects_plain_text() {
+        assert!(handle_metadata_payload("abc", "cid:METADATA[old:0,TXID:1,My Token]").is_err());
+        assert!(handle_metadata_payload("abc", "cid:METADATA[old:0,TXID:1,,,,,x=plain]").is_err());
+        assert!(handle_metadata_payload("abc", "cid:METADATA[old:0,TXID:1,,,,,a b=00]").is_err());
+        assert!(handle_metadata_payload("abc", "cid:METADATA[old:0,TXID:1,,,,,,extra]").is_err());
+    }
+
+    #[test]
+    fn legacy_lp_payloads_stop_at_cutoff() {
+        let contract_ids = vec![LP_1.to_string()];
+        let lp_ids = lp_envelope_ids(&contract_ids);
+        let mc = new_magic_crypt!(LP_1, 64);
+        let bytes = mc.encrypt_bytes_to_bytes("SLP[0,1000,990,0.01]".as_bytes());
+        let routed = Some((LP_1.to_string(), "SLP[0,1000,990,0.01]".to_string()));
+        assert_eq!(
+            route_lp_payload(&bytes, &lp_ids, &contract_ids, 999, Some(1_000)),
+            routed
+        );
+        assert_eq!(
+            route_lp_payload(&bytes, &lp_ids, &contract_ids, 1_000, Some(1_000)),
+            None
+        );
+        assert_eq!(
+            route_lp_payload(&bytes, &lp_ids, &contract_ids, 5_000, None),
+            routed
+        );
+
+        let payload = Some("SLP[0,1000,990,0.01]".to_string());
+        assert_eq!(decode_lp_payload(&bytes, LP_1, 999, Some(1_000)), payload);
+        assert_eq!(decode_lp_payload(&bytes, LP_1, 1_000, Some(1_000)), None);
+    }
+
+    #[test]
+    fn transfer_tokens_payload_names_tokens_per_receiver() {
+        let payload = "cid:TRANSFER_TOKENS[a:0,b:1],[TXID:0(7;9),TXID:1(8)]";
+        let (senders, receivers) = handle_transfer_tokens_payload("abc", payload).unwrap();
+        assert_eq!(senders, vec!["a:0".to_string(), "b:1".to_string()]);
+        assert_eq!(
+            receivers,
+            vec![
+                ("abc:0".to_string(), vec!["7".to_string(), "9".to_string()]),
+                ("abc:1".to_string(), vec!["8".to_string()]),
+            ]
+        );
+
+        assert!(handle_transfer_tokens_payload("abc", "cid:TRANSFER_TOKENS[a:0]").is_err());
+        assert!(
+            handle_transfer_tokens_payload("abc", "cid:TRANSFER_TOKENS[a:0],[TXID:0]").is_err()
+        );
+        assert!(
+            handle_transfer_tokens_payload("abc", "cid:TRANSFER_TOKENS[a:0],[TXID:0()]").is_err()
+        );
+    }
+
+    #[test]
+    fn collection_token_ids_follow_list_and_burn_payloads() {
+        let ids = Some(vec!["7".to_string(), "9".to_string()]);
+        let list = "cid:LIST[a:0],TXID:1,TXID:0,2,1000,bc1qseller,0,1";
+        assert_eq!(handle_list_token_ids_payload(list), None);
+        assert_eq!(handle_list_token_ids_payload(&format!("{},7;9", list)), ids);
+        assert_eq!(handle_list_token_ids_payload(&format!("{},", list)), None);
+
+        assert_eq!(
+            handle_burn_token_ids_payload("cid:BURN[a:0],2,TXID:0"),
+            None
+        );
+        assert_eq!(
+            handle_burn_token_ids_payload("cid:BURN[a:0,b:0],2,TXID:0,7;9"),
+            ids
+        );
+    }
+
+    const ROYALTY_ADDRESS: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const SELLER_ADDRESS: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    fn fulfil_tx(outputs: &[(&str, u64)]) -> String {
+        let transaction = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::default(),
+                script_sig: bitcoin::Script::new(),
+                sequence: 0xffffffff,
+                witness: Vec::new(),
+            }],
+            output: outputs
+                .iter()
+                .map(|(address, value)| bitcoin::TxOut {
+                    value: *value,
+                    script_pubkey: address.parse::<Address>().unwrap().script_pubkey(),
+                })
+                .collect(),
+        };
+        return hex::encode(bitcoin::consensus::serialize(&transaction));
+    }
+
+    #[test]
+    fn royalty_payment_adds_up_the_outputs_to_the_recipient() {
+        let royalty = Royalty {
+            bps: 250,
+            address: ROYALTY_ADDRESS.to_string(),
+        };
+        let seller = SELLER_ADDRESS.to_string();
+        let paid = fulfil_tx(&[(SELLER_ADDRESS, 1_000), (ROYALTY_ADDRESS, 25)]);
+        assert_eq!(
+            check_royalty_payment(&royalty, &paid, &seller, 1_000),
+            Ok(25)
+        );
+
+        let split = fulfil_tx(&[
+            (ROYALTY_ADDRESS, 10),
+            (SELLER_ADDRESS, 15),
+            (ROYALTY_ADDRESS, 15),
+        ]);
+        assert_eq!(
+            check_royalty_payment(&royalty, &split, &seller, 1_039),
+            Ok(25)
+        );
+    }
+
+    #[test]
+    fn royalty_payment_rejects_underpayment() {
+        let royalty = Royalty {
+            bps: 250,
+            address: ROYALTY_ADDRESS.to_string(),
+        };
+        let seller = SELLER_ADDRESS.to_string();
+        let short = fulfil_tx(&[(SELLER_ADDRESS, 1_000), (ROYALTY_ADDRESS, 24)]);
+        assert!(check_royalty_payment(&royalty, &short, &seller, 1_000).is_err());
+        let unpaid = fulfil_tx(&[(SELLER_ADDRESS, 1_025)]);
+        assert!(check_royalty_payment(&royalty, &unpaid, &seller, 1_000).is_err());
+        assert!(check_royalty_payment(&royalty, &"zz".to_string(), &seller, 1_000).is_err());
+    }
+
+    #[test]
+    fn royalty_payment_is_not_needed_when_the_seller_is_the_recipient() {
+        let royalty = Royalty {
+            bps: 250,
+            address: ROYALTY_ADDRESS.to_string(),
+        };
+        let seller = ROYALTY_ADDRESS.to_string();
+        let unpaid = fulfil_tx(&[(SELLER_ADDRESS, 1_000)]);
+        assert_eq!(
+            check_royalty_payment(&royalty, &unpaid, &seller, 1_000),
+            Ok(0)
+        );
+
+        // Dust trades round the royalty down to nothing
+        let seller = SELLER_ADDRESS.to_string();
+        assert_eq!(check_royalty_payment(&royalty, &unpaid, &seller, 39), Ok(0));
+    }
+}
@@ -24,6 +24,7 @@ use std::fs::{self};
 use warp::reject::Reject;
 
 use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxIn};
+use bitcoin::blockdata::script::{Instruction, Script};
 use bitcoin::consensus::deserialize;
 use hex::decode;
 
@@ -100,6 +101,7 @@ pub struct Status {
     pub confirmed: Option<bool>,
     pub block_height: Option<u64>,
     pub block_hash: Option<String>,
+    pub block_time: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -109,6 +111,16 @@ pub struct ContractImport {
     pub rest_url: String,
     pub contract_type: String,
     pub decimals: i32,
+    pub metadata: Option<ContractMetadata>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct ContractMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub website: Option<String>,
+    pub socials: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -199,6 +211,7 @@ pub struct ContractHistoryEntry {
     pub txid: String,
     pub pending: bool,
     pub btc_price: Option<u64>,
+    pub royalty_amount: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -212,6 +225,7 @@ pub struct Config {
     pub key: Option<String>,
     pub esplora: Option<String>,
     pub url: Option<String>,
+    pub legacy_lp_cutoff: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -220,6 +234,49 @@ pub struct FulfilledSummary {
     pub listing_price: u64,
     pub listing_amount: u64,
     pub bid_amount: u64,
+    pub txid: Option<String>,
+    pub block_height: Option<u64>,
+    pub block_time: Option<i64>,
+    pub royalty_amount: Option<u64>,
+    pub royalty_address: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct PoolWindowStats {
+    pub swaps: u64,
+    pub liquidations: u64,
+    pub volume_token_1: u64,
+    pub volume_token_2: u64,
+    pub fees_token_1: u64,
+    pub fees_token_2: u64,
+    pub volume_sats: Option<u64>,
+    pub fees_sats: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct PoolAnalytics {
+    pub contract_id: String,
+    pub contract_id_1: String,
+    pub contract_id_2: String,
+    pub price_1: Option<u64>,
+    pub price_2: Option<u64>,
+    pub tvl_token_1: u64,
+    pub tvl_token_2: u64,
+    pub tvl_sats: Option<u64>,
+    pub day: PoolWindowStats,
+    pub week: PoolWindowStats,
+    pub fee_apr_bps: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct Candle {
+    pub time: i64,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,
+    pub trades: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -254,6 +311,7 @@ pub struct ContractSummary {
     pub lp_contracts: Option<(String, String)>,
     pub lp_ratio: Option<f64>,
     pub token_data: Option<String>,
+    pub metadata: Option<ContractMetadata>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -299,6 +357,26 @@ pub struct LiquidityPoolString {
     pub liquidations: HashMap<String, (u64, u64)>,
 }
 
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct OrderBookLevel {
+    pub price: u64,
+    pub amount: u64,
+    pub orders: u64,
+    pub cumulative_amount: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct OrderBook {
+    pub contract_id: String,
+    pub pending: bool,
+    pub asks: Vec<OrderBookLevel>,
+    pub bids: Vec<OrderBookLevel>,
+    pub best_ask: Option<u64>,
+    pub best_bid: Option<u64>,
+    pub spread: Option<i64>,
+    pub mid_price: Option<u64>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SpentResult {
     pub spent: bool,
@@ -346,6 +424,23 @@ pub fn write_to_file(relative_path: String, data: String) -> bool {
     }
 }
 
+pub fn append_to_file(relative_path: String, data: String) -> bool {
+    use std::io::Write;
+    let mut file = match fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&relative_path)
+    {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+
+    match file.write_all(data.as_bytes()) {
+        Ok(_) => return true,
+        Err(_) => return false,
+    }
+}
+
 pub fn enqueue_item(filename: String, item: &str) -> std::io::Result<()> {
     match fs::write(&filename, item) {
         Ok(_) => return Ok(()),
@@ -424,6 +519,50 @@ pub fn replace_payload_special_characters(input: &String) -> String {
     trimmed_str
 }
 
+/// Parse a decimal string such as "0.003" into an integer scaled by 10^decimals without going
+/// through floats. Digits past the requested precision are truncated.
+pub fn parse_fixed_point(value: &str, decimals: u32) -> Result<u128, String> {
+    let parts: Vec<&str> = value.trim().split(".").collect();
+    if parts.len() > 2 || parts.concat().is_empty() {
+        return Err("Invalid decimal value".to_string());
+    }
+
+    let whole = match parts[0] {
+        "" => 0,
+        whole => match whole.parse::<u128>() {
+            Ok(whole) => whole,
+            Err(_) => return Err("Invalid decimal value".to_string()),
+        },
+    };
+
+    let mut fraction: u128 = 0;
+    if parts.len() == 2 {
+        if !parts[1].chars().all(|c| c.is_ascii_digit()) {
+            return Err("Invalid decimal value".to_string());
+        }
+
+        let mut digits: String = parts[1].chars().take(decimals as usize).collect();
+        while digits.len() < decimals as usize {
+            digits.push('0');
+        }
+
+        if !digits.is_empty() {
+            fraction = match digits.parse::<u128>() {
+                Ok(fraction) => fraction,
+                Err(_) => return Err("Invalid decimal value".to_string()),
+            };
+        }
+    }
+
+    match whole
+        .checked_mul(10u128.pow(decimals))
+        .and_then(|whole| whole.checked_add(fraction))
+    {
+        Some(result) => return Ok(result),
+        None => return Err("Decimal value overflow".to_string()),
+    }
+}
+
 pub async fn handle_get_request(url: String) -> Option<String> {
     let client = Client::new();
     let response = client.get(&url).send().await;
@@ -472,6 +611,21 @@ pub fn extract_contract_id(payload: &str) -> Result<String, String> {
     return Ok(trimmed_str.to_string());
 }
 
+// The command keyword after "{contract_id}:", up to its arguments
+pub fn extract_command_keyword(command: &str) -> String {
+    let after_contract_id = match command.split_once(":") {
+        Some((_, after_contract_id)) => after_contract_id,
+        None => return String::new(),
+    };
+
+    return after_contract_id
+        .split("[")
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_string();
+}
+
 pub async fn check_utxo_inputs(utxos: &Vec<String>, txid: &str) -> bool {
     let tx_info: TxInfo = match get_transaction(txid, false).await {
         Ok(tx_info) => tx_info,
@@ -589,6 +743,25 @@ pub async fn get_tx_inputs(txid: &str) -> Result<Vec<String>, String> {
     return Ok(input_str);
 }
 
+pub async fn get_tx_first_input_address(txid: &str) -> Result<Option<String>, String> {
+    let tx_info: TxInfo = match get_transaction(txid, false).await {
+        Ok(tx_info) => tx_info,
+        Err(_) => return Err("Unable to get inputs for txid".to_string()),
+    };
+
+    let inputs = match &tx_info.vin {
+        Some(v) => v,
+        None => return Err("Unable to get inputs for txid".to_string()),
+    };
+
+    let address = match inputs.first().and_then(|input| input.prevout.as_ref()) {
+        Some(prevout) => prevout.scriptpubkey_address.clone(),
+        None => None,
+    };
+
+    return Ok(address);
+}
+
 pub async fn check_txid_confirmed(txid: &str) -> Result<bool, String> {
     let config = match read_server_config() {
         Ok(config) => config,
@@ -660,12 +833,97 @@ pub async fn get_current_block_height() -> Result<i32, String> {
     return Ok(config.block_height);
 }
 
+/// Block height and block time a transaction was confirmed in, or None while it is unconfirmed.
+pub async fn get_tx_block_info(txid: &str) -> Option<(u64, i64)> {
+    let tx_info = match get_transaction(txid, false).await {
+        Ok(tx_info) => tx_info,
+        Err(_) => return None,
+    };
+
+    // A cached tx may have been stored before it confirmed
+    let mut status = tx_info.status;
+    if status
+        .as_ref()
+        .is_none_or(|status| status.block_time.is_none())
+    {
+        status = match get_transaction(txid, true).await {
+            Ok(tx_info) => tx_info.status,
+            Err(_) => return None,
+        };
+    }
+
+    match status {
+        Some(status) => match (status.block_height, status.block_time) {
+            (Some(block_height), Some(block_time)) => return Some((block_height, block_time)),
+            _ => return None,
+        },
+        None => return None,
+    }
+}
+
+// Confirmed transactions esplora returns per page of an address history
+const ESPLORA_CHAIN_TXS_PAGE: usize = 25;
+
+/// Lowest block height up to `block_height` the address has a confirmed transaction in. Esplora
+/// pages the history newest first, so paging stops once the address is seen at or before `seen_by`.
+pub async fn get_address_first_seen_height(
+    address: &str,
+    block_height: u64,
+    seen_by: u64,
+) -> Result<Option<u64>, String> {
+    let config = read_server_config().unwrap_or_default();
+
+    let esplora = match config.esplora {
+        Some(esplora) => esplora,
+        None => "https://btc.darkfusion.tech/".to_owned(),
+    };
+
+    let mut first_seen: Option<u64> = None;
+    let mut url = format!("{}address/{}/txs/chain", esplora, address);
+    loop {
+        let response = match handle_get_request(url.clone()).await {
+            Some(response) => response,
+            None => return Err("No response from esplora".to_string()),
+        };
+
+        let txs = match serde_json::from_str::<Vec<TxInfo>>(&response) {
+            Ok(txs) => txs,
+            Err(_) => return Err("No response from esplora".to_string()),
+        };
+
+        for tx in &txs {
+            let tx_height = match tx.status.as_ref().and_then(|status| status.block_height) {
+                Some(tx_height) if tx_height <= block_height => tx_height,
+                _ => continue,
+            };
+
+            first_seen = Some(first_seen.map_or(tx_height, |seen| seen.min(tx_height)));
+            if tx_height <= seen_by {
+                return Ok(first_seen);
+            }
+        }
+
+        let last_seen_txid = match txs.last().and_then(|tx| tx.txid.clone()) {
+            Some(last_seen_txid) if txs.len() >= ESPLORA_CHAIN_TXS_PAGE => last_seen_txid,
+            _ => return Ok(first_seen),
+        };
+
+        url = format!(
+            "{}address/{}/txs/chain/{}",
+            esplora, address, last_seen_txid
+        );
+    }
+}
+
 pub fn get_contract_header(contract_id: &str) -> Result<ContractImport, String> {
     let path = "./Json/Contracts/".to_string() + "/" + contract_id + "/header.txt";
     match read_from_file(path) {
         Some(contract_obj) => {
             match serde_json::from_str::<ContractImport>(&contract_obj) {
-                Ok(parsed_data) => return Ok(parsed_data),
+                Ok(mut parsed_data) => {
+                    parsed_data.metadata = read_contract_metadata(contract_id);
+                    return Ok(parsed_data);
+                }
                 Err(_) => return Err("Failed to deserialize contract".to_string()),
             };
         }
@@ -673,6 +931,26 @@ pub fn get_contract_header(contract_id: &str) -> Result<ContractImport, String>
     }
 }
 
+pub fn read_contract_metadata(contract_id: &str) -> Option<ContractMetadata> {
+    let path = "./Json/Contracts/".to_string() + "/" + contract_id + "/metadata.txt";
+    let metadata_str = read_from_file(path)?;
+
+    match serde_json::from_str::<ContractMetadata>(&metadata_str) {
+        Ok(metadata) => return Some(metadata),
+        Err(_) => return None,
+    };
+}
+
+pub fn save_contract_metadata(contract_id: &str, metadata: &ContractMetadata) {
+    let path = "./Json/Contracts/".to_string() + "/" + contract_id + "/metadata.txt";
+    match serde_json::to_string(metadata) {
+        Ok(metadata_str) => {
+            write_to_file(path, metadata_str);
+        }
+        Err(_) => println!("Unable to save contract metadata"),
+    };
+}
+
 pub fn get_txid_from_hash(tx_hex: &String) -> Result<String, String> {
     let tx_bytes = match decode(tx_hex) {
         Ok(tx_bytes) => tx_bytes,
@@ -863,3 +1141,530 @@ pub async fn get_addresses_for_utxos(utxos: Vec<String>) -> HashMap<String, Stri
     }
     result
 }
+
+// Concatenates the data pushes of an OP_RETURN scriptpubkey, covering OP_PUSHBYTES_n and OP_PUSHDATA1/2/4
+pub fn get_op_return_payload(scriptpubkey: &str) -> Option<Vec<u8>> {
+    let script_bytes = match decode(scriptpubkey) {
+        Ok(script_bytes) => script_bytes,
+        Err(_) => return None,
+    };
+
+    let script = Script::from(script_bytes);
+    if !script.is_op_return() {
+        return None;
+    }
+
+    let mut payload: Vec<u8> = Vec::new();
+    for instruction in script.instructions().skip(1) {
+        match instruction {
+            Ok(Instruction::PushBytes(bytes)) => payload.extend_from_slice(bytes),
+            _ => return None,
+        };
+    }
+
+    if payload.is_empty() {
+        return None;
+    }
+
+    return Some(payload);
+}
+
+// Sniffs the media type of NFT content from its leading bytes
+pub fn sniff_content_type(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]) {
+        return "image/png";
+    }
+
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        return "image/jpeg";
+    }
+
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return "image/gif";
+    }
+
+    if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').trim(),
+        Err(_) => return "application/octet-stream",
+    };
+
+    let head: String = text
+        .chars()
+        .take(1024)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if head.contains("<svg")
+        && (head.starts_with("<svg") || head.starts_with("<?xml") || head.starts_with("<!"))
+    {
+        return "image/svg+xml";
+    }
+
+    if (text.starts_with('{') || text.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+    {
+        return "application/json";
+    }
+
+    return "text/plain; charset=utf-8";
+}
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+// Static SVG elements that can be embedded. Scripts, embedded documents, images and animation
+// elements are not listed, so they are dropped with everything inside them.
+const SVG_ALLOWED_ELEMENTS: &[&str] = &[
+    "svg",
+    "g",
+    "defs",
+    "title",
+    "desc",
+    "symbol",
+    "use",
+    "path",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "text",
+    "tspan",
+    "textPath",
+    "linearGradient",
+    "radialGradient",
+    "stop",
+    "clipPath",
+    "mask",
+    "pattern",
+    "marker",
+    "filter",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+];
+
+const SVG_ALLOWED_ATTRIBUTES: &[&str] = &[
+    "id",
+    "class",
+    "style",
+    "version",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "fx",
+    "fy",
+    "fr",
+    "dx",
+    "dy",
+    "width",
+    "height",
+    "d",
+    "points",
+    "pathLength",
+    "transform",
+    "viewBox",
+    "preserveAspectRatio",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "stroke",
+    "stroke-width",
+    "stroke-opacity",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+    "stroke-miterlimit",
+    "opacity",
+    "color",
+    "display",
+    "visibility",
+    "overflow",
+    "vector-effect",
+    "shape-rendering",
+    "text-rendering",
+    "image-rendering",
+    "mix-blend-mode",
+    "isolation",
+    "font-family",
+    "font-size",
+    "font-weight",
+    "font-style",
+    "font-variant",
+    "text-anchor",
+    "text-decoration",
+    "dominant-baseline",
+    "alignment-baseline",
+    "baseline-shift",
+    "letter-spacing",
+    "word-spacing",
+    "writing-mode",
+    "rotate",
+    "textLength",
+    "lengthAdjust",
+    "startOffset",
+    "method",
+    "spacing",
+    "side",
+    "offset",
+    "stop-color",
+    "stop-opacity",
+    "gradientUnits",
+    "gradientTransform",
+    "spreadMethod",
+    "patternUnits",
+    "patternContentUnits",
+    "patternTransform",
+    "clip-path",
+    "clip-rule",
+    "clipPathUnits",
+    "mask",
+    "maskUnits",
+    "maskContentUnits",
+    "filter",
+    "filterUnits",
+    "primitiveUnits",
+    "in",
+    "in2",
+    "result",
+    "stdDeviation",
+    "mode",
+    "operator",
+    "k1",
+    "k2",
+    "k3",
+    "k4",
+    "values",
+    "type",
+    "tableValues",
+    "slope",
+    "intercept",
+    "amplitude",
+    "exponent",
+    "flood-color",
+    "flood-opacity",
+    "radius",
+    "edgeMode",
+    "markerWidth",
+    "markerHeight",
+    "markerUnits",
+    "refX",
+    "refY",
+    "orient",
+    "marker-start",
+    "marker-mid",
+    "marker-end",
+];
+
+// Attribute and style values may only reference fragments of the same document. CSS escapes
+// are rejected so a url or import cannot be spelt around the checks.
+fn is_safe_svg_value(value: &str) -> bool {
+    let value: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if value.contains('\\')
+        || value.contains("javascript:")
+        || value.contains("expression(")
+        || value.contains("image-set(")
+        || value.contains("src(")
+        || value.contains("@import")
+    {
+        return false;
+    }
+
+    return value
+        .split("url(")
+        .skip(1)
+        .all(|reference| reference.trim_start_matches(['"', '\'']).starts_with('#'));
+}
+
+fn escape_svg_text(text: &str, attribute: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    return escaped;
+}
+
+fn write_svg_node(node: roxmltree::Node, sanitized: &mut String) {
+    if node.is_text() {
+        if let Some(text) = node.text() {
+            sanitized.push_str(&escape_svg_text(text, false));
+        }
+
+        return;
+    }
+
+    if !node.is_element() {
+        return;
+    }
+
+    let tag = node.tag_name();
+    if !matches!(tag.namespace(), None | Some(SVG_NAMESPACE))
+        || !SVG_ALLOWED_ELEMENTS.contains(&tag.name())
+    {
+        return;
+    }
+
+    sanitized.push('<');
+    sanitized.push_str(tag.name());
+    if node.parent().is_some_and(|parent| parent.is_root()) {
+        sanitized.push_str(" xmlns=\"");
+        sanitized.push_str(SVG_NAMESPACE);
+        sanitized.push_str("\" xmlns:xlink=\"");
+        sanitized.push_str(XLINK_NAMESPACE);
+        sanitized.push('"');
+    }
+
+    for attribute in node.attributes() {
+        let name = match (attribute.namespace(), attribute.name()) {
+            (None, "href") | (Some(XLINK_NAMESPACE), "href") => {
+                // Only links to fragments of this document, such as use or gradient references
+                if !attribute.value().trim_start().starts_with('#') {
+                    continue;
+                }
+
+                match attribute.namespace() {
+                    Some(_) => "xlink:href".to_string(),
+                    None => "href".to_string(),
+                }
+            }
+            (Some(XML_NAMESPACE), "space") => "xml:space".to_string(),
+            (None, name)
+                if !name.to_ascii_lowercase().starts_with("on")
+                    && SVG_ALLOWED_ATTRIBUTES.contains(&name) =>
+            {
+                name.to_string()
+            }
+            _ => continue,
+        };
+
+        if !is_safe_svg_value(attribute.value()) {
+            continue;
+        }
+
+        sanitized.push(' ');
+        sanitized.push_str(&name);
+        sanitized.push_str("=\"");
+        sanitized.push_str(&escape_svg_text(attribute.value(), true));
+        sanitized.push('"');
+    }
+
+    sanitized.push('>');
+    for child in node.children() {
+        write_svg_node(child, sanitized);
+    }
+
+    sanitized.push_str("</");
+    sanitized.push_str(tag.name());
+    sanitized.push('>');
+}
+
+// Parses SVG content and rewrites it with only allowlisted static elements and attributes so it
+// can be embedded. Content that is not well formed XML with an svg root is rejected.
+pub fn sanitize_svg(svg: &str) -> Result<String, String> {
+    let document = match roxmltree::Document::parse(svg) {
+        Ok(document) => document,
+        Err(err) => return Err(format!("Invalid SVG: {}", err)),
+    };
+
+    let root = document.root_element();
+    if root.tag_name().name() != "svg"
+        || !matches!(root.tag_name().namespace(), None | Some(SVG_NAMESPACE))
+    {
+        return Err("Invalid SVG: root element is not svg".to_string());
+    }
+
+    let mut sanitized = String::new();
+    write_svg_node(root, &mut sanitized);
+    return Ok(sanitized);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fixed_point_scales_decimals() {
+        assert_eq!(parse_fixed_point("0.003", 8), Ok(300_000));
+        assert_eq!(parse_fixed_point("1", 0), Ok(1));
+        assert_eq!(parse_fixed_point("1", 8), Ok(100_000_000));
+        assert_eq!(parse_fixed_point(".5", 1), Ok(5));
+        assert_eq!(parse_fixed_point("5.", 2), Ok(500));
+        assert_eq!(parse_fixed_point(" 2.25 ", 2), Ok(225));
+        assert_eq!(parse_fixed_point("0", 18), Ok(0));
+    }
+
+    #[test]
+    fn parse_fixed_point_truncates_extra_digits() {
+        assert_eq!(parse_fixed_point("1.239", 2), Ok(123));
+        assert_eq!(parse_fixed_point("1.9", 0), Ok(1));
+        assert_eq!(parse_fixed_point("0.0000000009", 9), Ok(0));
+    }
+
+    #[test]
+    fn parse_fixed_point_rejects_invalid_values() {
+        for value in ["", " ", ".", "1.2.3", "1.a", "a.1", "-1", "1e3", "1,5"] {
+            assert!(parse_fixed_point(value, 4).is_err(), "accepted {:?}", value);
+        }
+    }
+
+    #[test]
+    fn parse_fixed_point_rejects_overflow() {
+        let max = u128::MAX.to_string();
+        assert_eq!(parse_fixed_point(&max, 0), Ok(u128::MAX));
+        assert!(parse_fixed_point(&max, 1).is_err());
+        assert!(parse_fixed_point(&format!("{}0", max), 0).is_err());
+        assert!(parse_fixed_point("340282366920938463463374607431768211455.9", 1).is_err());
+    }
+
+    #[test]
+    fn command_keyword_is_exact() {
+        assert_eq!(
+            extract_command_keyword("cid:METADATA[a,b,BURN]"),
+            "METADATA"
+        );
+        assert_eq!(extract_command_keyword("cid:UNFREEZE[a,b,c]"), "UNFREEZE");
+        assert_eq!(
+            extract_command_keyword("cid:TRANSFER[a:0,b,METADATA[c]]"),
+            "TRANSFER"
+        );
+        assert_eq!(extract_command_keyword("cid:FULFIL_TRADE"), "FULFIL_TRADE");
+        assert_eq!(extract_command_keyword("SCL01"), "");
+    }
+
+    #[test]
+    fn op_return_payload_reads_pushbytes() {
+        assert_eq!(
+            get_op_return_payload("6a0568656c6c6f"),
+            Some(b"hello".to_vec())
+        );
+
+        let data = "ab".repeat(75);
+        assert_eq!(
+            get_op_return_payload(&format!("6a4b{}", data)),
+            Some(vec![0xab; 75])
+        );
+    }
+
+    #[test]
+    fn op_return_payload_reads_pushdata() {
+        let data = "cd".repeat(80);
+        assert_eq!(
+            get_op_return_payload(&format!("6a4c50{}", data)),
+            Some(vec![0xcd; 80])
+        );
+
+        // OP_PUSHDATA2 lengths are little endian
+        let data = "ef".repeat(300);
+        assert_eq!(
+            get_op_return_payload(&format!("6a4d2c01{}", data)),
+            Some(vec![0xef; 300])
+        );
+
+        assert_eq!(
+            get_op_return_payload("6a4e03000000010203"),
+            Some(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn op_return_payload_concatenates_pushes() {
+        assert_eq!(
+            get_op_return_payload("6a0353434c024c0102aabb4c03010203"),
+            Some(vec![b'S', b'C', b'L', 0x4c, 0x01, 0xaa, 0xbb, 1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn op_return_payload_rejects_non_push_opcodes() {
+        // OP_DUP, OP_1 and OP_CHECKSIG between or after pushes
+        assert_eq!(get_op_return_payload("6a0201027602aabb"), None);
+        assert_eq!(get_op_return_payload("6a51"), None);
+        assert_eq!(get_op_return_payload("6a020102ac"), None);
+    }
+
+    #[test]
+    fn op_return_payload_rejects_invalid_scripts() {
+        // Not an OP_RETURN, a truncated push, no data, an empty push and bad hex
+        assert_eq!(
+            get_op_return_payload("76a914000000000000000000000000000000000000000088ac"),
+            None
+        );
+        assert_eq!(get_op_return_payload("6a05aabb"), None);
+        assert_eq!(get_op_return_payload("6a4d2c01aabb"), None);
+        assert_eq!(get_op_return_payload("6a"), None);
+        assert_eq!(get_op_return_payload("6a00"), None);
+        assert_eq!(get_op_return_payload("6a0x"), None);
+    }
+
+    #[test]
+    fn sanitize_svg_keeps_static_content() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 10 10"><defs><linearGradient id="g"><stop offset="0" stop-color="#fff"/></linearGradient></defs><rect width="10" height="10" fill="url(#g)" style="stroke: url('#g')"/><use xlink:href="#g"/><text x="1">a &amp; b</text></svg>"##;
+        assert_eq!(
+            sanitize_svg(svg),
+            Ok(r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 10 10"><defs><linearGradient id="g"><stop offset="0" stop-color="#fff"></stop></linearGradient></defs><rect width="10" height="10" fill="url(#g)" style="stroke: url('#g')"></rect><use xlink:href="#g"></use><text x="1">a &amp; b</text></svg>"##.to_string())
+        );
+    }
+
+    #[test]
+    fn sanitize_svg_drops_scripts_and_handlers() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)"><script>alert(1)</script><SCRIPT>alert(2)</SCRIPT><g OnClick="alert(3)" onmouseover='alert(4)'><foreignObject><body xmlns="http://www.w3.org/1999/xhtml"><script>alert(5)</script></body></foreignObject></g><h:script xmlns:h="http://www.w3.org/1999/xhtml">alert(6)</h:script></svg>"#;
+        assert_eq!(
+            sanitize_svg(svg),
+            Ok(r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><g></g></svg>"#.to_string())
+        );
+    }
+
+    #[test]
+    fn sanitize_svg_drops_animation_and_external_links() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><a href="javascript:alert(1)"><circle r="1"/></a><use href="https://example.com/x.svg#a"/><use xlink:href=" javascript:alert(2)"/><set attributeName="href" to="javascript:alert(3)"/><animate attributeName="href" values="javascript:alert(4)"/><image href="https://example.com/x.png"/><rect fill="url(https://example.com/x)" style="background:url(//example.com/x)"/><rect style="fill:\75rl(x)"/></svg>"#;
+        assert_eq!(
+            sanitize_svg(svg),
+            Ok(r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><use></use><use></use><rect></rect><rect></rect></svg>"#.to_string())
+        );
+    }
+
+    #[test]
+    fn sanitize_svg_rejects_invalid_documents() {
+        assert!(sanitize_svg("<svg><g></svg>").is_err());
+        assert!(sanitize_svg("<html><svg/></html>").is_err());
+        assert!(sanitize_svg("not svg").is_err());
+        assert!(sanitize_svg(r#"<!DOCTYPE svg [<!ENTITY x "y">]><svg>&x;</svg>"#).is_err());
+    }
+}